@@ -0,0 +1,63 @@
+use conducto_nom::{DbusTypeContainer, MatchIndex, MatchRule, Message};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+/// Rules as a broker with many clients watching distinct signals holds them
+fn rules(count: usize) -> Vec<MatchRule> {
+    (0..count)
+        .map(|i| {
+            let rule = match i % 4 {
+                0 => format!(
+                    "type='signal',interface='org.example.I{}',member='M{}'",
+                    i % 50,
+                    i
+                ),
+                1 => format!("type='signal',path='/org/example/o{}'", i),
+                2 => format!("type='signal',interface='org.example.Only{}'", i),
+                _ => format!(
+                    "type='signal',sender=':1.{}',member='Changed',arg0='k{}'",
+                    i, i
+                ),
+            };
+            rule.parse().unwrap()
+        })
+        .collect()
+}
+
+fn message() -> Message {
+    let mut message = Message::signal("/org/example/o1", "org.example.I0", "M0")
+        .unwrap()
+        .with_body(vec![DbusTypeContainer::String("k3".into())]);
+    message.header.fields.sender = Some(":1.3".into());
+    message
+}
+
+fn match_rules(c: &mut Criterion) {
+    let message = message();
+    let mut group = c.benchmark_group("match_rules");
+    for count in [100, 1_000, 10_000] {
+        let rules = rules(count);
+        group.bench_with_input(BenchmarkId::new("linear", count), &rules, |b, rules| {
+            b.iter(|| {
+                rules
+                    .iter()
+                    .filter(|rule| rule.matches(black_box(&message)))
+                    .count()
+            })
+        });
+
+        let mut index = MatchIndex::new();
+        for (i, rule) in rules.into_iter().enumerate() {
+            index.insert(rule, i);
+        }
+
+        group.bench_with_input(BenchmarkId::new("index", count), &index, |b, index| {
+            b.iter(|| index.matches(black_box(&message)).count())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, match_rules);
+criterion_main!(benches);
@@ -0,0 +1,356 @@
+use failure_derive::Fail;
+use std::path::PathBuf;
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum AddressError {
+    #[fail(display = "The address entry has no transport name")]
+    MissingTransport,
+    #[fail(display = "The address holds an invalid key/value pair")]
+    MalformedPair,
+    #[fail(display = "The address holds an invalid percent escape")]
+    InvalidEscape,
+    #[fail(display = "The key {} appears twice in an address entry", _0)]
+    DuplicateKey(String),
+    #[fail(display = "The {} transport requires the key {}", _0, _1)]
+    MissingKey(String, String),
+    #[fail(display = "The {} transport does not accept the key {}", _0, _1)]
+    UnexpectedKey(String, String),
+    #[fail(display = "The value of the key {} is invalid", _0)]
+    InvalidValue(String),
+}
+
+/// Bytes that may appear in a value without being escaped
+fn is_optionally_escaped(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-_/.\\*".contains(&b)
+}
+
+fn unescape(value: &str) -> Result<String, AddressError> {
+    let mut ret = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            ret.push(b);
+            continue;
+        }
+
+        let hex = [
+            bytes.next().ok_or(AddressError::InvalidEscape)?,
+            bytes.next().ok_or(AddressError::InvalidEscape)?,
+        ];
+        let mut decoded = [0];
+        hex::decode_to_slice(hex, &mut decoded).map_err(|_| AddressError::InvalidEscape)?;
+        ret.push(decoded[0]);
+    }
+
+    String::from_utf8(ret).map_err(|_| AddressError::InvalidEscape)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b if is_optionally_escaped(b) => (b as char).to_string(),
+            b => format!("%{:02x}", b),
+        })
+        .collect()
+}
+
+/// One transport of a server address, such as `unix:path=/run/bus`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressEntry {
+    transport: String,
+    params: Vec<(String, String)>,
+}
+
+impl AddressEntry {
+    pub fn new<S: Into<String>>(transport: S) -> Self {
+        Self {
+            transport: transport.into(),
+            params: vec![],
+        }
+    }
+
+    /// Adds a parameter, replacing the previous value of `key`
+    pub fn with<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        let key = key.into();
+        self.params.retain(|(k, _)| *k != key);
+        self.params.push((key, value.into()));
+        self
+    }
+
+    /// Name of the transport, the part before the colon
+    pub fn transport_name(&self) -> &str {
+        &self.transport
+    }
+
+    /// Parameters with their values unescaped, in address order
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// GUID of the server listening on this transport, if the address specifies one
+    pub fn guid(&self) -> Option<&str> {
+        self.get("guid")
+    }
+
+    /// Checks the parameters of a known transport and extracts them
+    ///
+    /// Transports this crate does not know about yield `None`.
+    pub fn transport(&self) -> Result<Option<Transport>, AddressError> {
+        let check_keys = |allowed: &[&str]| {
+            self.params
+                .iter()
+                .find(|(k, _)| k != "guid" && !allowed.contains(&k.as_str()))
+                .map_or(Ok(()), |(k, _)| {
+                    Err(AddressError::UnexpectedKey(
+                        self.transport.clone(),
+                        k.clone(),
+                    ))
+                })
+        };
+        let missing = |key: &str| AddressError::MissingKey(self.transport.clone(), key.to_string());
+
+        let transport = match self.transport.as_str() {
+            "unix" => {
+                let kinds = ["path", "abstract", "dir", "tmpdir", "runtime"];
+                check_keys(&kinds)?;
+                // Exactly one of the socket kinds must be given
+                let mut given = self
+                    .params
+                    .iter()
+                    .filter(|(k, _)| kinds.contains(&k.as_str()));
+                let (key, value) = given.next().ok_or_else(|| missing("path"))?;
+                if let Some((key, _)) = given.next() {
+                    return Err(AddressError::UnexpectedKey(
+                        self.transport.clone(),
+                        key.clone(),
+                    ));
+                }
+
+                Transport::Unix(match key.as_str() {
+                    "path" => UnixSocket::Path(value.into()),
+                    "abstract" => UnixSocket::Abstract(value.clone()),
+                    "dir" => UnixSocket::Dir(value.into()),
+                    "tmpdir" => UnixSocket::Tmpdir(value.into()),
+                    _ if value == "yes" => UnixSocket::Runtime,
+                    _ => return Err(AddressError::InvalidValue(key.clone())),
+                })
+            }
+            "tcp" | "nonce-tcp" => {
+                let nonce = self.transport == "nonce-tcp";
+                if nonce {
+                    check_keys(&["host", "bind", "port", "family", "noncefile"])?;
+                } else {
+                    check_keys(&["host", "bind", "port", "family"])?;
+                }
+
+                let port = match self.get("port") {
+                    Some(port) => Some(
+                        port.parse()
+                            .map_err(|_| AddressError::InvalidValue("port".to_string()))?,
+                    ),
+                    None => None,
+                };
+                let family = match self.get("family") {
+                    Some("ipv4") => Some(TcpFamily::Ipv4),
+                    Some("ipv6") => Some(TcpFamily::Ipv6),
+                    Some(_) => return Err(AddressError::InvalidValue("family".to_string())),
+                    None => None,
+                };
+                let tcp = TcpAddress {
+                    host: self.get("host").map(String::from),
+                    bind: self.get("bind").map(String::from),
+                    port,
+                    family,
+                };
+
+                if nonce {
+                    Transport::NonceTcp(tcp, self.get("noncefile").map(PathBuf::from))
+                } else {
+                    Transport::Tcp(tcp)
+                }
+            }
+            "unixexec" => {
+                let mut argv = vec![];
+                for (key, value) in self.params.iter() {
+                    match key.strip_prefix("argv").map(str::parse::<usize>) {
+                        Some(Ok(i)) => argv.push((i, value.clone())),
+                        _ if key == "path" || key == "guid" => {}
+                        _ => {
+                            return Err(AddressError::UnexpectedKey(
+                                self.transport.clone(),
+                                key.clone(),
+                            ))
+                        }
+                    }
+                }
+
+                argv.sort();
+                Transport::UnixExec {
+                    path: self.get("path").ok_or_else(|| missing("path"))?.into(),
+                    argv: argv.into_iter().map(|(_, arg)| arg).collect(),
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(transport))
+    }
+}
+
+impl std::str::FromStr for AddressEntry {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let colon = s.find(':').ok_or(AddressError::MissingTransport)?;
+        let (transport, params) = (&s[..colon], &s[colon + 1..]);
+        if transport.is_empty() {
+            return Err(AddressError::MissingTransport);
+        }
+
+        let mut entry = AddressEntry::new(transport);
+        for pair in params.split(',').filter(|pair| !pair.is_empty()) {
+            let eq = pair.find('=').ok_or(AddressError::MalformedPair)?;
+            let (key, value) = (&pair[..eq], &pair[eq + 1..]);
+            if key.is_empty() {
+                return Err(AddressError::MalformedPair);
+            }
+
+            if entry.get(key).is_some() {
+                return Err(AddressError::DuplicateKey(key.to_string()));
+            }
+
+            entry.params.push((key.to_string(), unescape(value)?));
+        }
+
+        entry.transport()?;
+        Ok(entry)
+    }
+}
+
+impl std::fmt::Display for AddressEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:", self.transport)?;
+        for (i, (key, value)) in self.params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+
+            write!(f, "{}={}", key, escape(value))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Flavours of Unix domain socket addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnixSocket {
+    /// A socket in the filesystem
+    Path(PathBuf),
+    /// A socket in the Linux abstract namespace
+    Abstract(String),
+    /// Listening only: a new socket in this directory
+    Dir(PathBuf),
+    /// Listening only: a new socket in this directory, abstract where supported
+    Tmpdir(PathBuf),
+    /// Listening only: the `bus` socket of `$XDG_RUNTIME_DIR`
+    Runtime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpFamily {
+    Ipv4,
+    Ipv6,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpAddress {
+    pub host: Option<String>,
+    pub bind: Option<String>,
+    pub port: Option<u16>,
+    pub family: Option<TcpFamily>,
+}
+
+/// The parameters of a transport this crate knows about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transport {
+    Unix(UnixSocket),
+    Tcp(TcpAddress),
+    /// TCP, authenticated by the contents of the nonce file
+    NonceTcp(TcpAddress, Option<PathBuf>),
+    /// The standard input and output of a spawned process
+    UnixExec {
+        path: PathBuf,
+        argv: Vec<String>,
+    },
+}
+
+/// A server address: transports to try in order, separated by semicolons
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusAddress(Vec<AddressEntry>);
+
+impl BusAddress {
+    pub fn new(entries: Vec<AddressEntry>) -> Self {
+        Self(entries)
+    }
+
+    /// The address of the session bus, from `DBUS_SESSION_BUS_ADDRESS`
+    pub fn session() -> Option<Result<Self, AddressError>> {
+        std::env::var("DBUS_SESSION_BUS_ADDRESS")
+            .ok()
+            .map(|s| s.parse())
+    }
+
+    /// The address of the system bus, `DBUS_SYSTEM_BUS_ADDRESS` or the well-known socket
+    pub fn system() -> Result<Self, AddressError> {
+        std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .unwrap_or_else(|_| "unix:path=/var/run/dbus/system_bus_socket".to_string())
+            .parse()
+    }
+
+    pub fn into_inner(self) -> Vec<AddressEntry> {
+        self.0
+    }
+}
+
+impl std::ops::Deref for BusAddress {
+    type Target = Vec<AddressEntry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::str::FromStr for BusAddress {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(';')
+            .filter(|entry| !entry.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(BusAddress)
+    }
+}
+
+impl std::fmt::Display for BusAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, entry) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ";")?;
+            }
+
+            write!(f, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,355 @@
+use crate::address::{BusAddress, Transport, UnixSocket};
+use crate::codec::{authenticate, MessageCodec};
+use crate::connection::{check_reply, match_call, ConnectionError, BUS_NAME, BUS_PATH};
+use crate::header::components::{MessageFlags, MessageType};
+use crate::sasl::{ExternalClient, SaslClient};
+use crate::{DbusTypeContainer, MatchRule, Message};
+use bytes::BytesMut;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// How long a call waits for its reply unless told otherwise, as in libdbus
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(25);
+
+type Writer = tokio::sync::Mutex<Box<dyn AsyncWrite + Send + Unpin>>;
+
+/// State shared by the handles of a connection and its reader task
+struct Shared {
+    codec: MessageCodec,
+    next_serial: AtomicU32,
+    closed: AtomicBool,
+    /// Calls waiting for a reply, by serial
+    pending: Mutex<HashMap<u32, oneshot::Sender<Message>>>,
+    subscribers: Mutex<Vec<mpsc::UnboundedSender<Message>>>,
+}
+
+impl Shared {
+    fn next_serial(&self) -> u32 {
+        // Serials must never be zero, even after wrapping around
+        self.next_serial
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |serial| {
+                Some(serial.checked_add(1).unwrap_or(1))
+            })
+            .expect("the update always succeeds")
+    }
+
+    /// Hands a message to the call it answers, or else to every subscriber
+    fn dispatch(&self, message: Message) {
+        let waiter = match message.header.message_type() {
+            MessageType::MethodReturn | MessageType::Error => message
+                .header
+                .reply_serial()
+                .and_then(|serial| self.pending.lock().unwrap().remove(&serial)),
+            _ => None,
+        };
+
+        match waiter {
+            // The caller may have given up in the meantime
+            Some(waiter) => drop(waiter.send(message)),
+            None => self
+                .subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| subscriber.send(message.clone()).is_ok()),
+        }
+    }
+
+    /// Fails every waiting call and ends every subscription
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.pending.lock().unwrap().clear();
+        self.subscribers.lock().unwrap().clear();
+    }
+}
+
+/// Stops the reader task once the last handle is gone
+struct Reader(JoinHandle<()>);
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Forgets a pending call when its future is dropped before the reply arrives
+struct PendingGuard<'a> {
+    shared: &'a Shared,
+    serial: u32,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.shared.pending.lock().unwrap().remove(&self.serial);
+    }
+}
+
+/// Messages the connection received that answer none of its calls
+pub struct Subscription {
+    receiver: mpsc::UnboundedReceiver<Message>,
+    rule: Option<MatchRule>,
+}
+
+impl Subscription {
+    /// Waits for the next message the rule matches, `None` once the connection is closed
+    pub async fn next(&mut self) -> Option<Message> {
+        loop {
+            let message = self.receiver.recv().await?;
+            if self.rule.as_ref().is_none_or(|rule| rule.matches(&message)) {
+                return Some(message);
+            }
+        }
+    }
+
+    pub fn rule(&self) -> Option<&MatchRule> {
+        self.rule.as_ref()
+    }
+}
+
+/// An asynchronous connection to a bus or a peer, shared by cheap clones
+///
+/// A background task reads incoming messages: replies wake up the call
+/// they answer, while signals and other unsolicited messages are delivered
+/// to every [`Subscription`]. The connection has to be created within a
+/// tokio runtime.
+#[derive(Clone)]
+pub struct AsyncConnection {
+    shared: Arc<Shared>,
+    writer: Arc<Writer>,
+    guid: Arc<str>,
+    unique_name: Arc<OnceLock<String>>,
+    _reader: Arc<Reader>,
+}
+
+impl AsyncConnection {
+    /// Connects to the first Unix socket of `address` that accepts, then authenticates
+    ///
+    /// This does not register on the bus, see [`AsyncConnection::hello`].
+    pub async fn open(address: &BusAddress) -> Result<Self, ConnectionError> {
+        let mut last_error = ConnectionError::UnsupportedAddress;
+        for entry in address.iter() {
+            let path = match entry.transport()? {
+                Some(Transport::Unix(UnixSocket::Path(path))) => path,
+                _ => continue,
+            };
+
+            match tokio::net::UnixStream::connect(path).await {
+                Ok(stream) => return Self::from_stream(stream).await,
+                Err(e) => last_error = ConnectionError::Io(e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Connects to the session bus and registers on it
+    pub async fn session() -> Result<Self, ConnectionError> {
+        let address = BusAddress::session().ok_or(ConnectionError::UnsupportedAddress)??;
+        let connection = Self::open(&address).await?;
+        connection.hello().await?;
+        Ok(connection)
+    }
+
+    /// Connects to the system bus and registers on it
+    pub async fn system() -> Result<Self, ConnectionError> {
+        let connection = Self::open(&BusAddress::system()?).await?;
+        connection.hello().await?;
+        Ok(connection)
+    }
+
+    /// Runs the EXTERNAL handshake over an already connected stream
+    pub async fn from_stream<S>(stream: S) -> Result<Self, ConnectionError>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let sasl = SaslClient::new(vec![Box::new(ExternalClient::new())]);
+        Self::with_sasl(stream, sasl, MessageCodec::new()).await
+    }
+
+    /// Runs the handshake of `sasl` over `stream`, then frames messages with `codec`
+    pub async fn with_sasl<S>(
+        stream: S,
+        sasl: SaslClient,
+        codec: MessageCodec,
+    ) -> Result<Self, ConnectionError>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        let (framed, guid) = authenticate(stream, sasl, codec).await?;
+        let parts = framed.into_parts();
+        let (read, write) = tokio::io::split(parts.io);
+
+        let shared = Arc::new(Shared {
+            codec,
+            next_serial: AtomicU32::new(1),
+            closed: AtomicBool::new(false),
+            pending: Mutex::new(HashMap::new()),
+            subscribers: Mutex::new(vec![]),
+        });
+        let reader = tokio::spawn(read_messages(read, parts.read_buf, shared.clone()));
+
+        Ok(Self {
+            shared,
+            writer: Arc::new(tokio::sync::Mutex::new(Box::new(write))),
+            guid: guid.into(),
+            unique_name: Arc::new(OnceLock::new()),
+            _reader: Arc::new(Reader(reader)),
+        })
+    }
+
+    /// Registers on the bus, which assigns the unique name of the connection
+    pub async fn hello(&self) -> Result<&str, ConnectionError> {
+        let call = Message::method_call(Some(BUS_NAME), BUS_PATH, Some(BUS_NAME), "Hello")
+            .map_err(|_| ConnectionError::InvalidMessage)?;
+        let reply = self
+            .call(call)
+            .await?
+            .ok_or(ConnectionError::InvalidMessage)?;
+        let name = match reply.message.first() {
+            Some(DbusTypeContainer::String(name)) => name.to_string(),
+            _ => return Err(ConnectionError::InvalidMessage),
+        };
+
+        Ok(self.unique_name.get_or_init(|| name))
+    }
+
+    /// Name assigned by the bus, once registered
+    pub fn unique_name(&self) -> Option<&str> {
+        self.unique_name.get().map(String::as_str)
+    }
+
+    /// GUID of the server, as sent during authentication
+    pub fn guid(&self) -> &str {
+        &self.guid
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+
+    /// Receives every message that does not answer a call of this connection
+    ///
+    /// Only messages arriving after the subscription are delivered.
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.shared.subscribers.lock().unwrap().push(sender);
+        Subscription {
+            receiver,
+            rule: None,
+        }
+    }
+
+    /// Asks the bus for the messages `rule` matches, and receives only those
+    pub async fn add_match(&self, rule: MatchRule) -> Result<Subscription, ConnectionError> {
+        // Subscribed first so that nothing sent right after the call is missed
+        let mut subscription = self.subscribe();
+        self.call(match_call("AddMatch", &rule)?).await?;
+        subscription.rule = Some(rule);
+        Ok(subscription)
+    }
+
+    /// Asks the bus to stop sending what `rule` matches
+    pub async fn remove_match(&self, rule: &MatchRule) -> Result<(), ConnectionError> {
+        self.call(match_call("RemoveMatch", rule)?).await?;
+        Ok(())
+    }
+
+    /// Assigns the next serial to `message`, then writes it out without waiting for a reply
+    pub async fn send(&self, mut message: Message) -> Result<u32, ConnectionError> {
+        let serial = self.shared.next_serial();
+        message.header.fixed.msg_serial = serial;
+        self.write(&message).await?;
+        Ok(serial)
+    }
+
+    /// Sends a method call and waits for its reply, for [`DEFAULT_CALL_TIMEOUT`] at most
+    ///
+    /// See [`AsyncConnection::call_with_timeout`].
+    pub async fn call(&self, message: Message) -> Result<Option<Message>, ConnectionError> {
+        self.call_with_timeout(message, DEFAULT_CALL_TIMEOUT).await
+    }
+
+    /// Sends a method call and waits for its reply, for `timeout` at most
+    ///
+    /// Calls flagged `NO_REPLY_EXPECTED` resolve to `None` as soon as they are
+    /// written. Error replies are turned into [`ConnectionError::MethodError`].
+    pub async fn call_with_timeout(
+        &self,
+        mut message: Message,
+        timeout: Duration,
+    ) -> Result<Option<Message>, ConnectionError> {
+        let no_reply = message
+            .header
+            .fixed
+            .flags
+            .contains(MessageFlags::NO_REPLY_EXPECTED);
+        if no_reply {
+            self.send(message).await?;
+            return Ok(None);
+        }
+
+        let serial = self.shared.next_serial();
+        message.header.fixed.msg_serial = serial;
+
+        // Registered before writing, as the reply may come back right away
+        let (sender, receiver) = oneshot::channel();
+        self.shared.pending.lock().unwrap().insert(serial, sender);
+        let _guard = PendingGuard {
+            shared: &self.shared,
+            serial,
+        };
+
+        // Checked after registering, so a concurrent close cannot be missed
+        self.write(&message).await?;
+        match tokio::time::timeout(timeout, receiver).await {
+            Ok(Ok(reply)) => check_reply(reply).map(Some),
+            Ok(Err(_)) => Err(ConnectionError::Disconnected),
+            Err(_) => Err(ConnectionError::Timeout),
+        }
+    }
+
+    async fn write(&self, message: &Message) -> Result<(), ConnectionError> {
+        if self.is_closed() {
+            return Err(ConnectionError::Disconnected);
+        }
+
+        let mut buf = BytesMut::new();
+        let mut codec = self.shared.codec;
+        codec.encode(message, &mut buf)?;
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&buf).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Decodes messages until the stream ends or breaks, then closes the connection
+async fn read_messages<S: AsyncRead>(
+    mut read: ReadHalf<S>,
+    mut buf: BytesMut,
+    shared: Arc<Shared>,
+) {
+    let mut codec = shared.codec;
+    loop {
+        match codec.decode(&mut buf) {
+            Ok(Some(message)) => {
+                shared.dispatch(message);
+                continue;
+            }
+            Ok(None) => {}
+            Err(_) => break,
+        }
+
+        match read.read_buf(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    shared.close();
+}
@@ -0,0 +1,253 @@
+//! The `org.freedesktop.DBus` interface the bus answers on its own
+
+use super::State;
+use crate::connection::BUS_NAME;
+use crate::header::components::{MessageFlags, MessageType};
+use crate::name_registry::{name_owner_changed, name_signal};
+use crate::signature_type::{Signature, SignatureType};
+use crate::types::containers::DbusArray;
+use crate::{error_name, DbusTypeContainer, MatchRule, Message, NameError, RequestNameFlags};
+
+/// Whether `message` is the `Hello` call registering a client
+pub(super) fn is_hello(message: &Message) -> bool {
+    let header = &message.header;
+    header.message_type() == MessageType::MethodCall
+        && header.destination() == Some(BUS_NAME)
+        && header
+            .interface()
+            .is_none_or(|interface| interface == BUS_NAME)
+        && header.member() == Some("Hello")
+}
+
+fn string(value: &str) -> DbusTypeContainer {
+    DbusTypeContainer::String(value.into())
+}
+
+fn string_array(values: Vec<&str>) -> DbusTypeContainer {
+    let values = values.into_iter().map(string).collect();
+    let array = DbusArray::new(Signature::from(SignatureType::String), values)
+        .expect("every element is a string");
+    DbusTypeContainer::Array(array)
+}
+
+/// The string arguments of `call`, when its signature is `signature`
+fn args<'a>(call: &'a Message, signature: &str) -> Option<Vec<&'a str>> {
+    if call.body_signature().to_string() != signature {
+        return None;
+    }
+
+    Some(
+        call.message
+            .iter()
+            .filter_map(|arg| match arg {
+                DbusTypeContainer::String(s) => Some(&**s),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+/// The `u` argument of `call` at `index`, once the signature is checked
+fn u32_arg(call: &Message, index: usize) -> u32 {
+    match call.message.get(index) {
+        Some(DbusTypeContainer::Uint32(value)) => (*value).into(),
+        _ => unreachable!("the signature was checked"),
+    }
+}
+
+fn name_error(e: NameError) -> (&'static str, String) {
+    (error_name::INVALID_ARGS, e.to_string())
+}
+
+/// Answers a message sent to the bus, returning the replies and signals to emit
+pub(super) fn handle(state: &mut State, call: &Message) -> Vec<Message> {
+    let header = &call.header;
+    if header.message_type() != MessageType::MethodCall {
+        return vec![];
+    }
+
+    let sender = header.sender().unwrap_or_default().to_string();
+    let mut messages = match header.interface() {
+        None | Some(BUS_NAME) => methods(state, call, &sender),
+        Some(_) => Err((
+            error_name::UNKNOWN_METHOD,
+            format!(
+                "{} does not implement the interface {}",
+                BUS_NAME,
+                header.interface().unwrap_or_default()
+            ),
+        )),
+    }
+    .unwrap_or_else(|(name, text)| vec![Message::error(call, name, &text)]);
+
+    // Signals always go out, but the reply only when one is expected
+    let no_reply = header.fixed.flags.contains(MessageFlags::NO_REPLY_EXPECTED);
+    if no_reply {
+        messages.retain(|message| message.header.reply_serial().is_none());
+    }
+
+    messages
+}
+
+type MethodResult = Result<Vec<Message>, (&'static str, String)>;
+
+fn methods(state: &mut State, call: &Message, sender: &str) -> MethodResult {
+    let member = call.header.member().unwrap_or_default();
+    let signature = match member {
+        "Hello" | "ListNames" => "",
+        "RequestName" => "su",
+        "ReleaseName" | "GetNameOwner" | "NameHasOwner" | "ListQueuedOwners" | "AddMatch"
+        | "RemoveMatch" => "s",
+        _ => {
+            return Err((
+                error_name::UNKNOWN_METHOD,
+                format!("{} has no method {}", BUS_NAME, member),
+            ))
+        }
+    };
+
+    let args = args(call, signature).ok_or_else(|| {
+        (
+            error_name::INVALID_ARGS,
+            format!(
+                "{} expects the signature \"{}\", not \"{}\"",
+                member,
+                signature,
+                call.body_signature()
+            ),
+        )
+    })?;
+    let reply = |body| Message::method_return(call).with_body(body);
+
+    match member {
+        "Hello" => {
+            let client = state
+                .clients
+                .get_mut(sender)
+                .expect("clients are registered before their first call");
+            if client.greeted {
+                return Err((
+                    error_name::FAILED,
+                    "Already handled an Hello message".to_string(),
+                ));
+            }
+
+            client.greeted = true;
+            Ok(vec![
+                reply(vec![string(sender)]),
+                name_signal("NameAcquired", sender, sender),
+                name_owner_changed(sender, None, Some(sender)),
+            ])
+        }
+        "RequestName" => {
+            let flags = RequestNameFlags::from(u32_arg(call, 1));
+            let (code, mut signals) = state
+                .names
+                .request_name(args[0], sender, flags)
+                .map_err(name_error)?;
+            signals.insert(
+                0,
+                reply(vec![DbusTypeContainer::Uint32((code as u32).into())]),
+            );
+            Ok(signals)
+        }
+        "ReleaseName" => {
+            let (code, mut signals) = state
+                .names
+                .release_name(args[0], sender)
+                .map_err(name_error)?;
+            signals.insert(
+                0,
+                reply(vec![DbusTypeContainer::Uint32((code as u32).into())]),
+            );
+            Ok(signals)
+        }
+        "GetNameOwner" => {
+            let name = args[0];
+            let owner = match name {
+                BUS_NAME => Some(BUS_NAME),
+                _ => state.owner(name),
+            };
+
+            match owner {
+                Some(owner) => Ok(vec![reply(vec![string(owner)])]),
+                None => Err((
+                    error_name::NAME_HAS_NO_OWNER,
+                    format!("Could not get owner of name '{}': no such name", name),
+                )),
+            }
+        }
+        "NameHasOwner" => {
+            let name = args[0];
+            let owned = name == BUS_NAME || state.owner(name).is_some();
+            Ok(vec![reply(vec![DbusTypeContainer::Boolean(owned.into())])])
+        }
+        "ListNames" => {
+            let mut names = vec![BUS_NAME];
+            names.extend(state.clients.keys().map(String::as_str));
+            names.extend(state.names.names());
+            names.sort();
+            Ok(vec![reply(vec![string_array(names)])])
+        }
+        "ListQueuedOwners" => {
+            let name = args[0];
+            let owners = match name {
+                BUS_NAME => vec![BUS_NAME],
+                _ if name.starts_with(':') => state.owner(name).into_iter().collect(),
+                _ => state.names.queued_owners(name),
+            };
+
+            if owners.is_empty() {
+                return Err((
+                    error_name::NAME_HAS_NO_OWNER,
+                    format!("Could not get owners of name '{}': no such name", name),
+                ));
+            }
+
+            Ok(vec![reply(vec![string_array(owners)])])
+        }
+        "AddMatch" => {
+            let rule: MatchRule = args[0].parse().map_err(|e| {
+                (
+                    error_name::MATCH_RULE_INVALID,
+                    format!("The rule \"{}\" is invalid: {}", args[0], e),
+                )
+            })?;
+            let id = state.rules.insert(rule, sender.to_string());
+            if let Some(client) = state.clients.get_mut(sender) {
+                client.rules.push(id);
+            }
+
+            Ok(vec![reply(vec![])])
+        }
+        "RemoveMatch" => {
+            let rule: MatchRule = args[0].parse().map_err(|e| {
+                (
+                    error_name::MATCH_RULE_INVALID,
+                    format!("The rule \"{}\" is invalid: {}", args[0], e),
+                )
+            })?;
+            let rules = &state.rules;
+            let client = state.clients.get_mut(sender);
+            let position = client.as_ref().and_then(|client| {
+                client
+                    .rules
+                    .iter()
+                    .position(|id| rules.get(*id).is_some_and(|(other, _)| *other == rule))
+            });
+
+            match (client, position) {
+                (Some(client), Some(position)) => {
+                    let id = client.rules.remove(position);
+                    state.rules.remove(id);
+                    Ok(vec![reply(vec![])])
+                }
+                _ => Err((
+                    error_name::MATCH_RULE_NOT_FOUND,
+                    format!("The rule \"{}\" was not added", args[0]),
+                )),
+            }
+        }
+        _ => unreachable!("the signature match lists every method"),
+    }
+}
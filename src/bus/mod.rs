@@ -0,0 +1,430 @@
+//! A message bus running inside the process, for tests that cannot rely on a
+//! system `dbus-daemon`
+//!
+//! Every client gets a thread that authenticates it, then reads its
+//! messages and routes them: calls to `org.freedesktop.DBus` are answered by
+//! the driver, messages with a destination go to its owner, and the others to
+//! every client with a matching rule.
+
+mod driver;
+
+use crate::address::{AddressEntry, BusAddress};
+use crate::connection::{write_message, Connection, ConnectionError, MessageStream, BUS_NAME};
+use crate::header::components::{MessageFlags, MessageType};
+use crate::name_registry::name_owner_changed;
+use crate::sasl::{AnonymousServer, ExternalServer, SaslServer};
+use crate::{error_name, random_hex, MatchIndex, Message, NameRegistry, RuleId};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::DirBuilder;
+use std::io;
+use std::net::Shutdown;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Uid of the process at the other end of `stream`
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    (ret == 0).then_some(cred.uid)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut uid = 0;
+    let mut gid = 0;
+    let ret = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    (ret == 0).then_some(uid)
+}
+
+/// Write end of a client socket, shared by the threads delivering to it
+type Writer = Arc<Mutex<UnixStream>>;
+
+/// A client that said `Hello`
+struct Client {
+    writer: Writer,
+    unix_fd: bool,
+    /// Whether the `Hello` call was answered
+    greeted: bool,
+    rules: Vec<RuleId>,
+}
+
+/// A message to write to a client once the state is unlocked
+struct Delivery {
+    writer: Writer,
+    message: Message,
+}
+
+impl Delivery {
+    fn write(&self) {
+        // A broken client is noticed, then dropped, by its own thread
+        let _ = write_message(&self.writer.lock().unwrap(), &self.message);
+    }
+}
+
+/// What the threads of a bus share
+struct State {
+    guid: String,
+    closed: bool,
+    next_serial: u32,
+    next_client: u64,
+    next_stream: u64,
+    /// Every open socket, registered or not, to shut down when the bus goes away
+    streams: HashMap<u64, UnixStream>,
+    /// Registered clients by unique name
+    clients: HashMap<String, Client>,
+    names: NameRegistry,
+    rules: MatchIndex<String>,
+}
+
+impl State {
+    fn next_serial(&mut self) -> u32 {
+        let serial = self.next_serial;
+        // Serials must never be zero, even after wrapping around
+        self.next_serial = self.next_serial.checked_add(1).unwrap_or(1);
+        serial
+    }
+
+    /// The unique name owning `name`, which may be a unique name itself
+    fn owner(&self, name: &str) -> Option<&str> {
+        if name.starts_with(':') {
+            self.clients.get_key_value(name).map(|(name, _)| &**name)
+        } else {
+            self.names.owner(name)
+        }
+    }
+
+    /// Registers a client under a fresh unique name
+    fn register(&mut self, writer: Writer, unix_fd: bool) -> String {
+        let name = format!(":1.{}", self.next_client);
+        self.next_client += 1;
+        self.clients.insert(
+            name.clone(),
+            Client {
+                writer,
+                unix_fd,
+                greeted: false,
+                rules: vec![],
+            },
+        );
+        name
+    }
+
+    /// Forgets a client along with its rules and names
+    fn unregister(&mut self, name: &str) -> Vec<Delivery> {
+        let client = match self.clients.remove(name) {
+            Some(client) => client,
+            None => return vec![],
+        };
+
+        for id in client.rules {
+            self.rules.remove(id);
+        }
+
+        let mut deliveries = vec![];
+        for signal in self.names.release_all(name) {
+            deliveries.extend(self.emit(signal));
+        }
+
+        deliveries.extend(self.emit(name_owner_changed(name, Some(name), None)));
+        deliveries
+    }
+
+    /// Routes a message from the bus itself
+    fn emit(&mut self, mut message: Message) -> Vec<Delivery> {
+        message.header.fixed.msg_serial = self.next_serial();
+        message.header.fields.sender = Some(BUS_NAME.into());
+        self.route(message)
+    }
+
+    /// Routes a message which sender is already set
+    ///
+    /// Messages without a destination go to every client with a matching
+    /// rule, the others to the owner of their destination only.
+    fn route(&mut self, message: Message) -> Vec<Delivery> {
+        let destination = match message.header.destination() {
+            Some(destination) => destination.to_string(),
+            None => return self.broadcast(message),
+        };
+
+        if destination == BUS_NAME {
+            let replies = driver::handle(self, &message);
+            return replies
+                .into_iter()
+                .flat_map(|reply| self.emit(reply))
+                .collect();
+        }
+
+        let client = self
+            .owner(&destination)
+            .and_then(|owner| self.clients.get(owner));
+        let error = match client {
+            Some(client) if message.fds.is_empty() || client.unix_fd => {
+                return vec![Delivery {
+                    writer: client.writer.clone(),
+                    message,
+                }];
+            }
+            Some(_) => (
+                error_name::NOT_SUPPORTED,
+                format!("{} does not accept file descriptors", destination),
+            ),
+            None => (
+                error_name::SERVICE_UNKNOWN,
+                format!(
+                    "The name {} was not provided by any .service files",
+                    destination
+                ),
+            ),
+        };
+
+        let wants_reply = message.header.message_type() == MessageType::MethodCall
+            && !message
+                .header
+                .fixed
+                .flags
+                .contains(MessageFlags::NO_REPLY_EXPECTED);
+        if !wants_reply {
+            return vec![];
+        }
+
+        self.emit(Message::error(&message, error.0, &error.1))
+    }
+
+    fn broadcast(&mut self, mut message: Message) -> Vec<Delivery> {
+        // Rules may name the sender by any of the names it owns
+        let sender = message.header.sender().unwrap_or_default().to_string();
+        let mut aliases = vec![sender.clone()];
+        aliases.extend(self.names.names_owned_by(&sender).map(String::from));
+
+        let mut recipients = BTreeSet::new();
+        for alias in aliases {
+            message.header.fields.sender = Some(alias.into());
+            recipients.extend(
+                self.rules
+                    .matches(&message)
+                    .map(|(_, client)| client.clone()),
+            );
+        }
+        message.header.fields.sender = Some(sender.into());
+
+        recipients
+            .iter()
+            .filter_map(|name| self.clients.get(name))
+            .filter(|client| message.fds.is_empty() || client.unix_fd)
+            .map(|client| Delivery {
+                writer: client.writer.clone(),
+                message: message.clone(),
+            })
+            .collect()
+    }
+}
+
+/// A message bus listening on a Unix socket in a private temporary directory
+///
+/// The bus runs until dropped, which disconnects every client and removes
+/// the socket. Clients authenticate with EXTERNAL or ANONYMOUS, and may pass
+/// file descriptors.
+///
+/// Messages are written to clients from the thread of the sender, so a
+/// client that stops reading eventually stalls the clients talking to it.
+pub struct Bus {
+    dir: PathBuf,
+    address: BusAddress,
+    state: Arc<Mutex<State>>,
+    accept: Option<JoinHandle<()>>,
+}
+
+impl Bus {
+    /// Starts a bus, listening in a fresh directory under the system temporary directory
+    pub fn new() -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("conducto-bus-{}", random_hex(8)));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let path = dir.join("bus");
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&dir);
+                return Err(e);
+            }
+        };
+
+        let guid = random_hex(16);
+        let address = BusAddress::new(vec![AddressEntry::new("unix")
+            .with("path", path.to_string_lossy())
+            .with("guid", guid.clone())]);
+        let state = Arc::new(Mutex::new(State {
+            guid,
+            closed: false,
+            next_serial: 1,
+            next_client: 1,
+            next_stream: 0,
+            streams: HashMap::new(),
+            clients: HashMap::new(),
+            names: NameRegistry::new(),
+            rules: MatchIndex::new(),
+        }));
+
+        let shared = state.clone();
+        let accept = thread::spawn(move || accept(listener, shared));
+        Ok(Self {
+            dir,
+            address,
+            state,
+            accept: Some(accept),
+        })
+    }
+
+    /// Address clients connect to
+    pub fn address(&self) -> &BusAddress {
+        &self.address
+    }
+
+    pub fn guid(&self) -> String {
+        self.state.lock().unwrap().guid.clone()
+    }
+
+    /// Opens a connection to the bus and registers it
+    pub fn connect(&self) -> Result<Connection, ConnectionError> {
+        let mut connection = Connection::open(&self.address)?;
+        connection.hello()?;
+        Ok(connection)
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            for stream in state.streams.values() {
+                let _ = stream.shutdown(Shutdown::Both);
+            }
+        }
+
+        // Wakes the accept thread up, so that it notices the bus is closed
+        let _ = UnixStream::connect(self.dir.join("bus"));
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn accept(listener: UnixListener, state: Arc<Mutex<State>>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        let id = {
+            let mut state = state.lock().unwrap();
+            if state.closed {
+                return;
+            }
+
+            let id = state.next_stream;
+            state.next_stream += 1;
+            match stream.try_clone() {
+                Ok(clone) => state.streams.insert(id, clone),
+                Err(_) => continue,
+            };
+            id
+        };
+
+        let state = state.clone();
+        thread::spawn(move || {
+            serve(&state, stream);
+            state.lock().unwrap().streams.remove(&id);
+        });
+    }
+}
+
+/// Authenticates a client, then routes its messages until it disconnects
+fn serve(state: &Mutex<State>, stream: UnixStream) {
+    let uid = peer_uid(&stream);
+    let writer = match stream.try_clone() {
+        Ok(writer) => Arc::new(Mutex::new(writer)),
+        Err(_) => return,
+    };
+
+    let mut io = MessageStream::new(stream);
+    let unix_fd = match handshake(state, &mut io, uid) {
+        Ok(unix_fd) => unix_fd,
+        Err(_) => return,
+    };
+
+    let mut name = None;
+    while let Ok(mut message) = io.read_message() {
+        let name = match &name {
+            Some(name) => name,
+            // The first message has to register the client
+            None if driver::is_hello(&message) => {
+                name.insert(state.lock().unwrap().register(writer.clone(), unix_fd))
+            }
+            None => break,
+        };
+
+        message.header.fields.sender = Some(name.as_str().into());
+        let deliveries = state.lock().unwrap().route(message);
+        for delivery in deliveries {
+            delivery.write();
+        }
+    }
+
+    if let Some(name) = name {
+        let deliveries = state.lock().unwrap().unregister(&name);
+        for delivery in deliveries {
+            delivery.write();
+        }
+    }
+}
+
+/// Runs the server side of the handshake, returning whether descriptors may be passed
+fn handshake(
+    state: &Mutex<State>,
+    io: &mut MessageStream,
+    uid: Option<u32>,
+) -> Result<bool, ConnectionError> {
+    let guid = state.lock().unwrap().guid.clone();
+    let mut sasl = SaslServer::new(
+        guid,
+        vec![
+            Box::new(ExternalServer::new(uid)),
+            Box::new(AnonymousServer::new()),
+        ],
+    )
+    .with_unix_fd_support();
+
+    io.read_nul_byte()?;
+    while !sasl.is_authenticated() {
+        if let Some(answer) = sasl.handle(io.read_command()?)? {
+            io.write_all(&answer.to_bytes())?;
+        }
+    }
+
+    Ok(sasl.unix_fd_agreed())
+}
@@ -0,0 +1,127 @@
+use crate::connection::ConnectionError;
+use crate::header::{HeaderPeek, FIXED_HEADER_LEN};
+use crate::parse_context::ParseLimits;
+use crate::sasl::{Command, SaslClient};
+use crate::Message;
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder, Framed, FramedParts};
+
+/// Frames messages out of a byte stream once the handshake is over
+///
+/// File descriptors cannot travel through a codec, so messages carrying
+/// some are refused by the encoder and decoded messages never have any.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageCodec {
+    limits: ParseLimits,
+}
+
+impl MessageCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Holds incoming and outgoing messages to `limits` instead of the specification ones
+    pub fn with_limits(self, limits: ParseLimits) -> Self {
+        Self { limits }
+    }
+
+    pub fn limits(&self) -> &ParseLimits {
+        &self.limits
+    }
+}
+
+impl Decoder for MessageCodec {
+    type Item = Message;
+    type Error = ConnectionError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Message>, ConnectionError> {
+        let len = match HeaderPeek::frame_len(src) {
+            Some(len) => len,
+            // With the fixed header buffered, only a bad endianness byte is left
+            None if src.len() >= FIXED_HEADER_LEN => return Err(ConnectionError::InvalidMessage),
+            None => return Ok(None),
+        };
+        if len > self.limits.max_message_len {
+            return Err(ConnectionError::TooLarge);
+        }
+
+        if src.len() < len {
+            src.reserve(len - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(len);
+        Message::parse_with_limits(&frame, self.limits)
+            .map(|(_, message)| Some(message))
+            .map_err(|_| ConnectionError::InvalidMessage)
+    }
+}
+
+impl Encoder<&Message> for MessageCodec {
+    type Error = ConnectionError;
+
+    fn encode(&mut self, message: &Message, dst: &mut BytesMut) -> Result<(), ConnectionError> {
+        if !message.fds.is_empty() {
+            return Err(ConnectionError::UnixFdNotSupported);
+        }
+
+        let data = message.marshal();
+        if data.len() > self.limits.max_message_len {
+            return Err(ConnectionError::TooLarge);
+        }
+
+        dst.extend_from_slice(&data);
+        Ok(())
+    }
+}
+
+impl Encoder<Message> for MessageCodec {
+    type Error = ConnectionError;
+
+    fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), ConnectionError> {
+        self.encode(&message, dst)
+    }
+}
+
+/// Runs the client side of the handshake over `stream`, then frames it with `codec`
+///
+/// Bytes the server sent past the handshake are handed over to the framed
+/// stream, so no message is lost. Returns the GUID of the server along with
+/// the framed stream. `sasl` should not negotiate file descriptor passing,
+/// which the codec cannot do.
+pub async fn authenticate<S>(
+    mut stream: S,
+    mut sasl: SaslClient,
+    codec: MessageCodec,
+) -> Result<(Framed<S, MessageCodec>, String), ConnectionError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(b"\0").await?;
+    stream.write_all(&sasl.start()?.to_bytes()).await?;
+
+    let mut buf = BytesMut::new();
+    while !sasl.is_authenticated() {
+        let (consumed, command) = match Command::parse(&buf) {
+            Ok((rest, command)) => (buf.len() - rest.len(), command),
+            Err(nom::Err::Incomplete(_)) => {
+                if stream.read_buf(&mut buf).await? == 0 {
+                    return Err(ConnectionError::Disconnected);
+                }
+
+                continue;
+            }
+            Err(_) => return Err(ConnectionError::InvalidMessage),
+        };
+
+        buf.advance(consumed);
+        let answer = sasl.handle(command)?;
+        stream.write_all(&answer.to_bytes()).await?;
+    }
+
+    let mut parts = FramedParts::new::<Message>(stream, codec);
+    parts.read_buf = buf;
+    let guid = sasl.guid().unwrap_or_default().to_string();
+    Ok((Framed::from_parts(parts), guid))
+}
@@ -0,0 +1,466 @@
+use crate::error::DbusParseError;
+use crate::header::components::MessageEndianness;
+use crate::parse_context::{padding, ParseContext};
+use crate::signature_type::{complete_type_len, Signature, SignatureType};
+use crate::type_container::DbusTypeContainer;
+use crate::types::{basic::*, containers::*};
+use crate::DbusType;
+use nom::bytes::streaming::take;
+use nom::combinator::{map, map_res};
+use nom::IResult;
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::sync::Arc;
+
+/// Consecutive fixed-size basic values whose offsets are known in advance
+///
+/// The run starts on an `alignment` boundary and no value in it needs a
+/// stricter alignment, so the padding between values never depends on where
+/// the run lands in the message.
+#[derive(Debug, Clone, PartialEq)]
+struct FixedRun {
+    alignment: usize,
+    size: usize,
+    values: Vec<(usize, SignatureType)>,
+}
+
+impl FixedRun {
+    fn new(alignment: usize) -> Self {
+        Self {
+            alignment,
+            size: 0,
+            values: vec![],
+        }
+    }
+
+    fn accepts(&self, signature_type: SignatureType) -> bool {
+        signature_type.alignment() <= self.alignment
+    }
+
+    fn push(&mut self, signature_type: SignatureType, size: usize) {
+        let offset = self.size + padding(self.size, signature_type.alignment());
+        self.values.push((offset, signature_type));
+        self.size = offset + size;
+    }
+
+    /// Alignment guaranteed right after the run
+    fn end_alignment(&self) -> usize {
+        let mut alignment = self.alignment;
+        while alignment > 1 && !self.size.is_multiple_of(alignment) {
+            alignment /= 2;
+        }
+
+        alignment
+    }
+
+    fn parse_buffer<'a>(
+        &self,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+        out: &mut Vec<DbusTypeContainer>,
+    ) -> IResult<&'a [u8], ()> {
+        let (buf, _) = ctx.align(buf, self.alignment)?;
+        let (buf, bytes) = take(self.size)(buf)?;
+
+        let mut end = 0;
+        for (offset, signature_type) in &self.values {
+            // Padding inside the run must be zeroed as well
+            if bytes[end..*offset].iter().any(|b| *b != 0) {
+                return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+            }
+
+            let size = signature_type.fixed_size().unwrap_or_default();
+            let value = &bytes[*offset..*offset + size];
+            out.push(
+                fixed_value(*signature_type, value, ctx.endianness())
+                    .ok_or(nom::Err::Error((buf, nom::error::ErrorKind::Verify)))?,
+            );
+            end = offset + size;
+        }
+
+        Ok((buf, ()))
+    }
+}
+
+/// Decodes a fixed-size basic value from exactly its wire bytes
+fn fixed_value(
+    signature_type: SignatureType,
+    bytes: &[u8],
+    endianness: MessageEndianness,
+) -> Option<DbusTypeContainer> {
+    macro_rules! read {
+        ($type:ty) => {
+            match endianness {
+                MessageEndianness::LittleEndian => <$type>::from_le_bytes(bytes.try_into().ok()?),
+                MessageEndianness::BigEndian => <$type>::from_be_bytes(bytes.try_into().ok()?),
+            }
+        };
+    }
+
+    Some(match signature_type {
+        SignatureType::Boolean => match read!(u32) {
+            0 => DbusTypeContainer::Boolean(false.into()),
+            1 => DbusTypeContainer::Boolean(true.into()),
+            _ => return None,
+        },
+        SignatureType::Byte => DbusTypeContainer::Byte(bytes[0].into()),
+        SignatureType::Uint16 => DbusTypeContainer::Uint16(read!(u16).into()),
+        SignatureType::Int16 => DbusTypeContainer::Int16(read!(i16).into()),
+        SignatureType::Uint32 => DbusTypeContainer::Uint32(read!(u32).into()),
+        SignatureType::Int32 => DbusTypeContainer::Int32(read!(i32).into()),
+        SignatureType::Uint64 => DbusTypeContainer::Uint64(read!(u64).into()),
+        SignatureType::Int64 => DbusTypeContainer::Int64(read!(i64).into()),
+        SignatureType::Double => DbusTypeContainer::Double(read!(f64).into()),
+        SignatureType::UnixFd => DbusTypeContainer::UnixFd(read!(u32).into()),
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Run(FixedRun),
+    Value(Plan),
+}
+
+/// How to decode a single complete type
+#[derive(Debug, Clone, PartialEq)]
+enum Plan {
+    Basic(SignatureType),
+    Variant,
+    FixedArray(SignatureType),
+    Array {
+        element_signature: Signature,
+        element: Box<Plan>,
+    },
+    Dict {
+        key_signature: Signature,
+        value_signature: Signature,
+        entry: Vec<Step>,
+    },
+    Struct(Vec<Step>),
+}
+
+/// Compiles a sequence of complete types starting on an `alignment` boundary
+fn compile_sequence(
+    mut signature: &[SignatureType],
+    mut alignment: usize,
+) -> Result<Vec<Step>, DbusParseError> {
+    let mut steps = vec![];
+    let mut run: Option<FixedRun> = None;
+    while !signature.is_empty() {
+        let len = complete_type_len(signature)?;
+        let signature_type = signature[0];
+        match signature_type.fixed_size() {
+            Some(size) => {
+                let current = match run.take() {
+                    Some(current) if current.accepts(signature_type) => current,
+                    previous => {
+                        if let Some(previous) = previous {
+                            alignment = previous.end_alignment();
+                            steps.push(Step::Run(previous));
+                        }
+
+                        FixedRun::new(std::cmp::max(alignment, signature_type.alignment()))
+                    }
+                };
+                let mut current = current;
+                current.push(signature_type, size);
+                run = Some(current);
+            }
+            None => {
+                if let Some(previous) = run.take() {
+                    steps.push(Step::Run(previous));
+                }
+
+                steps.push(Step::Value(compile_complete_type(&signature[..len])?));
+                // Variable-sized values leave the alignment unknown
+                alignment = 1;
+            }
+        }
+
+        signature = &signature[len..];
+    }
+
+    if let Some(previous) = run {
+        steps.push(Step::Run(previous));
+    }
+
+    Ok(steps)
+}
+
+fn compile_complete_type(signature: &[SignatureType]) -> Result<Plan, DbusParseError> {
+    Ok(match signature[0] {
+        SignatureType::Variant => Plan::Variant,
+        SignatureType::Array => match signature[1] {
+            SignatureType::Byte
+            | SignatureType::Uint16
+            | SignatureType::Int16
+            | SignatureType::Uint32
+            | SignatureType::Int32
+            | SignatureType::Uint64
+            | SignatureType::Int64
+            | SignatureType::Double
+                if signature.len() == 2 =>
+            {
+                Plan::FixedArray(signature[1])
+            }
+            SignatureType::DictStart => {
+                let entry = &signature[2..signature.len() - 1];
+                let key_len = complete_type_len(entry)?;
+                Plan::Dict {
+                    key_signature: Signature::new(entry[..key_len].to_vec()),
+                    value_signature: Signature::new(entry[key_len..].to_vec()),
+                    entry: compile_sequence(entry, DbusDictEntry::ALIGNMENT)?,
+                }
+            }
+            _ => Plan::Array {
+                element_signature: Signature::new(signature[1..].to_vec()),
+                element: Box::new(compile_complete_type(&signature[1..])?),
+            },
+        },
+        SignatureType::StructStart => Plan::Struct(compile_sequence(
+            &signature[1..signature.len() - 1],
+            DbusStruct::ALIGNMENT,
+        )?),
+        signature_type if signature_type.is_basic() => Plan::Basic(signature_type),
+        _ => return Err(DbusParseError::InvalidSignature),
+    })
+}
+
+fn parse_steps<'a>(
+    steps: &[Step],
+    buf: &'a [u8],
+    ctx: &ParseContext,
+    out: &mut Vec<DbusTypeContainer>,
+) -> IResult<&'a [u8], ()> {
+    steps
+        .iter()
+        .try_fold((buf, ()), |(buf, _), step| match step {
+            Step::Run(run) => run.parse_buffer(buf, ctx, out),
+            Step::Value(plan) => {
+                let (buf, value) = plan.parse_buffer(buf, ctx)?;
+                out.push(value);
+                Ok((buf, ()))
+            }
+        })
+}
+
+impl Plan {
+    fn parse_buffer<'a>(
+        &self,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+    ) -> IResult<&'a [u8], DbusTypeContainer> {
+        match self {
+            Plan::Basic(SignatureType::String) => map(
+                |buf| unmarshal_str(buf, ctx),
+                |s| DbusTypeContainer::String(s.into()),
+            )(buf),
+            Plan::Basic(SignatureType::ObjectPath) => map_res(
+                |buf| unmarshal_str(buf, ctx),
+                |s| DbusObjectPath::try_from(s).map(DbusTypeContainer::ObjectPath),
+            )(buf),
+            Plan::Basic(SignatureType::Signature) => map(unmarshal_signature_str, |s| {
+                DbusTypeContainer::Signature(DbusSignature(s.into()))
+            })(buf),
+            Plan::Basic(signature_type) => {
+                let size = signature_type.fixed_size().unwrap_or_default();
+                let (buf, _) = ctx.align(buf, signature_type.alignment())?;
+                let (buf, bytes) = take(size)(buf)?;
+                let value = fixed_value(*signature_type, bytes, ctx.endianness())
+                    .ok_or(nom::Err::Error((buf, nom::error::ErrorKind::Verify)))?;
+                Ok((buf, value))
+            }
+            Plan::Variant => map(
+                |buf| DbusVariant::unmarshal(buf, ctx, &SignatureType::Variant.into()),
+                |v| DbusTypeContainer::Variant(Box::new(v)),
+            )(buf),
+            Plan::FixedArray(SignatureType::Byte) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::ByteArray,
+            )(buf),
+            Plan::FixedArray(SignatureType::Uint16) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::Uint16Array,
+            )(buf),
+            Plan::FixedArray(SignatureType::Int16) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::Int16Array,
+            )(buf),
+            Plan::FixedArray(SignatureType::Uint32) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::Uint32Array,
+            )(buf),
+            Plan::FixedArray(SignatureType::Int32) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::Int32Array,
+            )(buf),
+            Plan::FixedArray(SignatureType::Uint64) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::Uint64Array,
+            )(buf),
+            Plan::FixedArray(SignatureType::Int64) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::Int64Array,
+            )(buf),
+            Plan::FixedArray(_) => map(
+                |buf| unmarshal_fixed_array(buf, ctx),
+                DbusTypeContainer::DoubleArray,
+            )(buf),
+            Plan::Array {
+                element_signature,
+                element,
+            } => {
+                let (buf, ctx) = ctx.nest(buf)?;
+                let ctx = &ctx;
+                let alignment = element_signature[0].alignment();
+                let (buf, inner) =
+                    ctx.array(buf, alignment, |buf| element.parse_buffer(buf, ctx))?;
+                Ok((
+                    buf,
+                    DbusTypeContainer::Array(DbusArray {
+                        signature: element_signature.clone(),
+                        inner,
+                    }),
+                ))
+            }
+            Plan::Dict {
+                key_signature,
+                value_signature,
+                entry,
+            } => {
+                let (buf, ctx) = ctx.nest(buf)?;
+                let ctx = &ctx;
+                let (buf, inner) = ctx.array(buf, DbusDictEntry::ALIGNMENT, |buf| {
+                    let (buf, _) = ctx.align(buf, DbusDictEntry::ALIGNMENT)?;
+                    let mut values = Vec::with_capacity(2);
+                    let (buf, _) = parse_steps(entry, buf, ctx, &mut values)?;
+                    let value = values.pop().unwrap();
+                    let key = values.pop().unwrap();
+                    Ok((buf, DbusDictEntry(key, value)))
+                })?;
+                Ok((
+                    buf,
+                    DbusTypeContainer::Dict(DbusDict {
+                        key_signature: key_signature.clone(),
+                        value_signature: value_signature.clone(),
+                        inner,
+                    }),
+                ))
+            }
+            Plan::Struct(steps) => {
+                let (buf, ctx) = ctx.nest(buf)?;
+                let ctx = &ctx;
+                let (buf, _) = ctx.align(buf, DbusStruct::ALIGNMENT)?;
+                let mut fields = vec![];
+                let (buf, _) = parse_steps(steps, buf, ctx, &mut fields)?;
+                Ok((buf, DbusTypeContainer::Struct(DbusStruct(fields))))
+            }
+        }
+    }
+}
+
+/// A signature turned into a decode plan once, for signatures decoded over and over
+///
+/// Alignment, runs of fixed-size values and the container structure are all
+/// worked out at compile time, decoding then only walks the plan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledSignature {
+    signature: Signature,
+    steps: Vec<Step>,
+}
+
+impl CompiledSignature {
+    pub fn new(signature: Signature) -> Result<Self, DbusParseError> {
+        // The start of a message body is the only alignment known for sure
+        let steps = compile_sequence(&signature, 1)?;
+        Ok(Self { signature, steps })
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Decodes one value per single complete type, like [`Signature::parse_buffer`]
+    pub fn parse_buffer<'a>(
+        &self,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+    ) -> IResult<&'a [u8], Vec<DbusTypeContainer>> {
+        let mut ret = vec![];
+        let (buf, _) = parse_steps(&self.steps, buf, ctx, &mut ret)?;
+        Ok((buf, ret))
+    }
+}
+
+impl TryFrom<Signature> for CompiledSignature {
+    type Error = DbusParseError;
+
+    fn try_from(signature: Signature) -> Result<Self, Self::Error> {
+        Self::new(signature)
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    compiled: Arc<CompiledSignature>,
+    last_used: u64,
+}
+
+/// A bounded cache of compiled signatures, evicting the least recently used one
+#[derive(Debug)]
+pub struct SignatureCache {
+    capacity: usize,
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl SignatureCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: std::cmp::max(capacity, 1),
+            clock: 0,
+            entries: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the compiled form of `signature`, compiling and caching it on a miss
+    pub fn get(&mut self, signature: &str) -> Result<Arc<CompiledSignature>, DbusParseError> {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(signature) {
+            entry.last_used = self.clock;
+            return Ok(entry.compiled.clone());
+        }
+
+        let compiled = Arc::new(CompiledSignature::new(signature.parse()?)?);
+        if self.entries.len() >= self.capacity {
+            // Misses on a full cache are rare enough for a linear scan
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.entries.insert(
+            signature.into(),
+            CacheEntry {
+                compiled: compiled.clone(),
+                last_used: self.clock,
+            },
+        );
+
+        Ok(compiled)
+    }
+}
@@ -0,0 +1,359 @@
+use crate::address::{AddressError, BusAddress, Transport, UnixSocket};
+use crate::header::components::MessageType;
+use crate::header::FIXED_HEADER_LEN;
+use crate::sasl::{parse_nul_byte, Command, ExternalClient, SaslClient, SaslError};
+use crate::unix_fds::{recv_with_fds, send_with_fds};
+use crate::{DbusTypeContainer, HeaderPeek, MatchRule, Message, ParseLimits, UnixFds};
+use failure_derive::Fail;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::os::unix::io::OwnedFd;
+use std::os::unix::net::UnixStream;
+
+pub(crate) const BUS_NAME: &str = "org.freedesktop.DBus";
+pub(crate) const BUS_PATH: &str = "/org/freedesktop/DBus";
+
+#[derive(Debug, Fail)]
+pub enum ConnectionError {
+    #[fail(display = "I/O error on the connection: {}", _0)]
+    Io(#[cause] io::Error),
+    #[fail(display = "The bus address is invalid: {}", _0)]
+    Address(#[cause] AddressError),
+    #[fail(display = "Authentication failed: {}", _0)]
+    Sasl(#[cause] SaslError),
+    #[fail(display = "The peer sent data that is not a valid message")]
+    InvalidMessage,
+    #[fail(display = "The address holds no transport this crate can connect to")]
+    UnsupportedAddress,
+    #[fail(display = "The peer closed the connection")]
+    Disconnected,
+    #[fail(display = "The peer did not agree to pass file descriptors")]
+    UnixFdNotSupported,
+    #[fail(display = "The message is longer than the parse limits allow")]
+    TooLarge,
+    #[fail(display = "No reply arrived in time")]
+    Timeout,
+    #[fail(display = "The method call failed with {}: {}", name, message)]
+    MethodError { name: String, message: String },
+}
+
+impl From<io::Error> for ConnectionError {
+    fn from(e: io::Error) -> Self {
+        ConnectionError::Io(e)
+    }
+}
+
+impl From<AddressError> for ConnectionError {
+    fn from(e: AddressError) -> Self {
+        ConnectionError::Address(e)
+    }
+}
+
+impl From<SaslError> for ConnectionError {
+    fn from(e: SaslError) -> Self {
+        ConnectionError::Sasl(e)
+    }
+}
+
+/// Turns an `ERROR` reply into [`ConnectionError::MethodError`]
+pub(crate) fn check_reply(reply: Message) -> Result<Message, ConnectionError> {
+    match reply.header.message_type() {
+        MessageType::Error => Err(ConnectionError::MethodError {
+            name: reply.header.error_name().unwrap_or_default().to_string(),
+            message: match reply.message.first() {
+                Some(DbusTypeContainer::String(text)) => text.to_string(),
+                _ => String::new(),
+            },
+        }),
+        _ => Ok(reply),
+    }
+}
+
+/// A call of `AddMatch` or `RemoveMatch` on the bus for `rule`
+pub(crate) fn match_call(member: &str, rule: &MatchRule) -> Result<Message, ConnectionError> {
+    Ok(
+        Message::method_call(Some(BUS_NAME), BUS_PATH, Some(BUS_NAME), member)
+            .map_err(|_| ConnectionError::InvalidMessage)?
+            .with_body(vec![DbusTypeContainer::String(rule.to_string().into())]),
+    )
+}
+
+/// A blocking connection to a bus or a peer over a Unix domain socket
+///
+/// Messages that arrive while waiting for a method reply are queued and
+/// handed out by later calls to [`Connection::receive`].
+pub struct Connection {
+    io: MessageStream,
+    next_serial: u32,
+    guid: String,
+    unique_name: Option<String>,
+    queue: VecDeque<Message>,
+    unix_fd: bool,
+}
+
+impl Connection {
+    /// Connects to the first transport of `address` that accepts, then authenticates
+    ///
+    /// This does not register on the bus, see [`Connection::hello`].
+    pub fn open(address: &BusAddress) -> Result<Self, ConnectionError> {
+        let mut last_error = ConnectionError::UnsupportedAddress;
+        for entry in address.iter() {
+            let stream = match entry.transport()? {
+                Some(Transport::Unix(UnixSocket::Path(path))) => UnixStream::connect(path),
+                #[cfg(target_os = "linux")]
+                Some(Transport::Unix(UnixSocket::Abstract(name))) => {
+                    use std::os::linux::net::SocketAddrExt;
+                    std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())
+                        .and_then(|addr| UnixStream::connect_addr(&addr))
+                }
+                _ => continue,
+            };
+
+            match stream {
+                Ok(stream) => return Self::from_stream(stream),
+                Err(e) => last_error = ConnectionError::Io(e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Connects to the session bus and registers on it
+    pub fn session() -> Result<Self, ConnectionError> {
+        let address = BusAddress::session().ok_or(ConnectionError::UnsupportedAddress)??;
+        let mut connection = Self::open(&address)?;
+        connection.hello()?;
+        Ok(connection)
+    }
+
+    /// Connects to the system bus and registers on it
+    pub fn system() -> Result<Self, ConnectionError> {
+        let mut connection = Self::open(&BusAddress::system()?)?;
+        connection.hello()?;
+        Ok(connection)
+    }
+
+    /// Runs the EXTERNAL handshake over an already connected socket
+    ///
+    /// File descriptor passing is negotiated along the way.
+    pub fn from_stream(stream: UnixStream) -> Result<Self, ConnectionError> {
+        let mut sasl =
+            SaslClient::new(vec![Box::new(ExternalClient::new())]).with_unix_fd_negotiation();
+        let mut io = MessageStream::new(stream);
+        io.write_all(b"\0")?;
+        io.write_all(&sasl.start()?.to_bytes())?;
+        while !sasl.is_authenticated() {
+            let answer = sasl.handle(io.read_command()?)?;
+            io.write_all(&answer.to_bytes())?;
+        }
+
+        Ok(Self {
+            io,
+            next_serial: 1,
+            guid: sasl.guid().unwrap_or_default().to_string(),
+            unique_name: None,
+            queue: VecDeque::new(),
+            unix_fd: sasl.unix_fd_agreed(),
+        })
+    }
+
+    /// Registers on the bus, which assigns the unique name of the connection
+    pub fn hello(&mut self) -> Result<&str, ConnectionError> {
+        let mut call = Message::method_call(Some(BUS_NAME), BUS_PATH, Some(BUS_NAME), "Hello")
+            .map_err(|_| ConnectionError::InvalidMessage)?;
+        let reply = self.call(&mut call)?;
+        let name = match reply.message.first() {
+            Some(DbusTypeContainer::String(name)) => name.to_string(),
+            _ => return Err(ConnectionError::InvalidMessage),
+        };
+
+        Ok(self.unique_name.get_or_insert(name))
+    }
+
+    /// Asks the bus for the messages `rule` matches, which [`Connection::receive`] returns
+    pub fn add_match(&mut self, rule: &MatchRule) -> Result<(), ConnectionError> {
+        self.call(&mut match_call("AddMatch", rule)?)?;
+        Ok(())
+    }
+
+    /// Asks the bus to stop sending what `rule` matches
+    pub fn remove_match(&mut self, rule: &MatchRule) -> Result<(), ConnectionError> {
+        self.call(&mut match_call("RemoveMatch", rule)?)?;
+        Ok(())
+    }
+
+    /// Name assigned by the bus, once registered
+    pub fn unique_name(&self) -> Option<&str> {
+        self.unique_name.as_deref()
+    }
+
+    /// GUID of the server, as sent during authentication
+    pub fn guid(&self) -> &str {
+        &self.guid
+    }
+
+    /// Whether the peer agreed to pass file descriptors
+    pub fn unix_fd_agreed(&self) -> bool {
+        self.unix_fd
+    }
+
+    /// Assigns the next serial to `message`, then writes it out with its descriptors
+    pub fn send(&mut self, message: &mut Message) -> Result<u32, ConnectionError> {
+        if !message.fds.is_empty() && !self.unix_fd {
+            return Err(ConnectionError::UnixFdNotSupported);
+        }
+
+        message
+            .check_unix_fds()
+            .map_err(|_| ConnectionError::InvalidMessage)?;
+        let serial = self.next_serial;
+        // Serials must never be zero, even after wrapping around
+        self.next_serial = self.next_serial.checked_add(1).unwrap_or(1);
+        message.header.fixed.msg_serial = serial;
+        write_message(self.io.stream(), message)?;
+        Ok(serial)
+    }
+
+    /// Returns the next message, queued or read from the socket
+    pub fn receive(&mut self) -> Result<Message, ConnectionError> {
+        if let Some(message) = self.queue.pop_front() {
+            return Ok(message);
+        }
+
+        self.io.read_message()
+    }
+
+    /// Sends a method call and waits for its reply
+    ///
+    /// Error replies are turned into [`ConnectionError::MethodError`].
+    pub fn call(&mut self, message: &mut Message) -> Result<Message, ConnectionError> {
+        let serial = self.send(message)?;
+        loop {
+            let reply = self.io.read_message()?;
+            if reply.header.reply_serial() != Some(serial) {
+                self.queue.push_back(reply);
+                continue;
+            }
+
+            return check_reply(reply);
+        }
+    }
+}
+
+/// A Unix socket carrying handshake lines, then messages and their descriptors
+pub(crate) struct MessageStream {
+    stream: UnixStream,
+    buf: Vec<u8>,
+    /// Descriptors received ahead of the message they belong to
+    fds: VecDeque<OwnedFd>,
+}
+
+impl MessageStream {
+    pub(crate) fn new(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            buf: vec![],
+            fds: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn stream(&self) -> &UnixStream {
+        &self.stream
+    }
+
+    pub(crate) fn write_all(&mut self, data: &[u8]) -> Result<(), ConnectionError> {
+        Ok(self.stream.write_all(data)?)
+    }
+
+    /// Reads the nul byte a client sends before the handshake
+    pub(crate) fn read_nul_byte(&mut self) -> Result<(), ConnectionError> {
+        loop {
+            match parse_nul_byte(&self.buf) {
+                Ok(_) => {
+                    self.buf.drain(..1);
+                    return Ok(());
+                }
+                Err(nom::Err::Incomplete(_)) => self.read_more()?,
+                Err(_) => return Err(ConnectionError::InvalidMessage),
+            }
+        }
+    }
+
+    /// Reads a line of the handshake
+    ///
+    /// Bytes past the line stay buffered, ready for the messages that follow.
+    pub(crate) fn read_command(&mut self) -> Result<Command, ConnectionError> {
+        loop {
+            match Command::parse(&self.buf) {
+                Ok((rest, command)) => {
+                    let consumed = self.buf.len() - rest.len();
+                    self.buf.drain(..consumed);
+                    return Ok(command);
+                }
+                Err(nom::Err::Incomplete(_)) => self.read_more()?,
+                Err(_) => return Err(ConnectionError::InvalidMessage),
+            }
+        }
+    }
+
+    pub(crate) fn read_message(&mut self) -> Result<Message, ConnectionError> {
+        loop {
+            match HeaderPeek::frame_len(&self.buf) {
+                Some(len) if len > ParseLimits::default().max_message_len => {
+                    return Err(ConnectionError::TooLarge);
+                }
+                Some(len) if self.buf.len() >= len => {
+                    let message = Message::parse(&self.buf[..len])
+                        .map(|(_, message)| message)
+                        .map_err(|_| ConnectionError::InvalidMessage);
+                    self.buf.drain(..len);
+                    let mut message = message?;
+
+                    // The descriptors came along with the first bytes of the message
+                    let count = message.header.fields.unix_fds.map_or(0, u32::from) as usize;
+                    if count > self.fds.len() {
+                        return Err(ConnectionError::InvalidMessage);
+                    }
+
+                    message.fds = UnixFds::new(self.fds.drain(..count).collect());
+                    return Ok(message);
+                }
+                // With the fixed header buffered, only a bad endianness byte is left
+                None if self.buf.len() >= FIXED_HEADER_LEN => {
+                    return Err(ConnectionError::InvalidMessage);
+                }
+                _ => {}
+            }
+
+            self.read_more()?;
+        }
+    }
+
+    /// Appends what the socket has to offer to the buffers
+    fn read_more(&mut self) -> Result<(), ConnectionError> {
+        let mut chunk = [0; 4096];
+        match recv_with_fds(&self.stream, &mut chunk, &mut self.fds)? {
+            0 => Err(ConnectionError::Disconnected),
+            n => {
+                self.buf.extend_from_slice(&chunk[..n]);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes `message` as is, serial included, along with its descriptors
+pub(crate) fn write_message(
+    mut stream: &UnixStream,
+    message: &Message,
+) -> Result<(), ConnectionError> {
+    let data = message.marshal();
+    let fds: Vec<_> = message.fds.iter().collect();
+    let sent = if fds.is_empty() {
+        0
+    } else {
+        send_with_fds(stream, &data, &fds)?
+    };
+    stream.write_all(&data[sent..])?;
+    Ok(())
+}
@@ -16,6 +16,33 @@ pub enum DbusParseError {
         display = "The type transformation is invalid, the container holds a different variant than the target"
     )]
     InvalidContainerVariantTarget,
+    #[fail(display = "The container element does not match its declared signature")]
+    InvalidElementSignature,
+    #[fail(display = "The message header is malformed")]
+    MalformedHeader,
+    #[fail(display = "The message body does not match its signature")]
+    InvalidBody,
+    #[fail(display = "The message has no argument at this index")]
+    ArgumentOutOfRange,
+    #[fail(display = "The message refers to a file descriptor it does not carry")]
+    InvalidUnixFd,
     #[fail(display = "Unknown error")]
     UnknownError,
 }
+
+/// Names of the standard errors, as sent in `ERROR` replies
+pub mod error_name {
+    pub const FAILED: &str = "org.freedesktop.DBus.Error.Failed";
+    pub const SERVICE_UNKNOWN: &str = "org.freedesktop.DBus.Error.ServiceUnknown";
+    pub const NAME_HAS_NO_OWNER: &str = "org.freedesktop.DBus.Error.NameHasNoOwner";
+    pub const INVALID_ARGS: &str = "org.freedesktop.DBus.Error.InvalidArgs";
+    pub const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+    pub const UNKNOWN_OBJECT: &str = "org.freedesktop.DBus.Error.UnknownObject";
+    pub const UNKNOWN_INTERFACE: &str = "org.freedesktop.DBus.Error.UnknownInterface";
+    pub const UNKNOWN_PROPERTY: &str = "org.freedesktop.DBus.Error.UnknownProperty";
+    pub const PROPERTY_READ_ONLY: &str = "org.freedesktop.DBus.Error.PropertyReadOnly";
+    pub const ACCESS_DENIED: &str = "org.freedesktop.DBus.Error.AccessDenied";
+    pub const NOT_SUPPORTED: &str = "org.freedesktop.DBus.Error.NotSupported";
+    pub const MATCH_RULE_INVALID: &str = "org.freedesktop.DBus.Error.MatchRuleInvalid";
+    pub const MATCH_RULE_NOT_FOUND: &str = "org.freedesktop.DBus.Error.MatchRuleNotFound";
+}
@@ -4,19 +4,14 @@ use crate::types::basic::DbusByte;
 use bitflags::bitflags;
 use std::convert::{TryFrom, TryInto};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
 #[repr(u8)]
 pub enum MessageEndianness {
+    #[default]
     LittleEndian = b'l',
     BigEndian = b'B',
 }
 
-impl Default for MessageEndianness {
-    fn default() -> Self {
-        MessageEndianness::LittleEndian
-    }
-}
-
 impl std::convert::TryFrom<u8> for MessageEndianness {
     type Error = DbusParseError;
 
@@ -29,9 +24,10 @@ impl std::convert::TryFrom<u8> for MessageEndianness {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum MessageType {
+    #[default]
     Invalid = 0x00,
     MethodCall = 0x01,
     MethodReturn = 0x02,
@@ -39,12 +35,6 @@ pub enum MessageType {
     Signal = 0x04,
 }
 
-impl Default for MessageType {
-    fn default() -> Self {
-        MessageType::Invalid
-    }
-}
-
 impl std::convert::TryFrom<u8> for MessageType {
     type Error = DbusParseError;
 
@@ -74,9 +64,10 @@ impl From<u8> for MessageFlags {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
 #[repr(u8)]
 pub enum HeaderField {
+    #[default]
     Invalid = 0x00,
     Path = 0x01,
     Interface = 0x02,
@@ -89,12 +80,6 @@ pub enum HeaderField {
     UnixFdCount = 0x09,
 }
 
-impl Default for HeaderField {
-    fn default() -> Self {
-        HeaderField::Invalid
-    }
-}
-
 impl TryFrom<u8> for HeaderField {
     type Error = DbusParseError;
 
@@ -1,14 +1,22 @@
 pub mod components;
+mod patch;
+mod peek;
+
+pub use self::patch::*;
+pub use self::peek::*;
 
 use self::components::*;
 use crate::error::DbusParseError;
-use crate::message::Message;
+use crate::marshal::Marshaller;
+use crate::message::{BorrowedMessage, Message};
+use crate::parse_context::{ParseContext, ParseLimits};
 use crate::signature_type::{Signature, SignatureType};
-use crate::type_container::DbusTypeContainer;
 use crate::types::{basic::*, containers::*};
+use crate::unix_fds::UnixFds;
 use crate::DbusType;
 use nom::branch::alt;
 use nom::{
+    bytes::streaming::take,
     combinator::{map, map_res},
     number::streaming::{be_u32, be_u8, le_u32, le_u8},
     sequence::tuple,
@@ -29,11 +37,7 @@ pub struct FixedHeaderPart {
 impl DbusType for FixedHeaderPart {
     const ALIGNMENT: usize = 0;
 
-    fn unmarshal<'a, 'b>(
-        i: &'b [u8],
-        _: MessageEndianness,
-        _: &'a Signature,
-    ) -> IResult<&'b [u8], Self> {
+    fn unmarshal<'b>(i: &'b [u8], _: &ParseContext, _: &Signature) -> IResult<&'b [u8], Self> {
         let (i, endianness) = map_res(alt((le_u8, be_u8)), MessageEndianness::try_from)(i)?;
 
         let (i, (message_type, flags, protocol_version, msg_len, msg_serial)) = match endianness {
@@ -87,11 +91,8 @@ impl std::ops::DerefMut for RawHeaderFields {
 impl DbusType for RawHeaderFields {
     const ALIGNMENT: usize = 0;
 
-    fn unmarshal<'a, 'b>(
-        buf: &'b [u8],
-        endianness: MessageEndianness,
-        _: &'a Signature,
-    ) -> IResult<&'b [u8], Self> {
+    fn unmarshal<'b>(buf: &'b [u8], ctx: &ParseContext, _: &Signature) -> IResult<&'b [u8], Self> {
+        // The fields are an `a(yv)` on the wire, dict entries share the struct layout
         let signature = Signature::new(vec![
             SignatureType::Array,
             SignatureType::DictStart,
@@ -100,11 +101,25 @@ impl DbusType for RawHeaderFields {
             SignatureType::DictEnd,
         ]);
         let (buf, inner) = map_res(
-            map(
-                |buf| signature.parse_buffer(buf, endianness),
-                DbusDict::from,
-            ),
-            DbusDict::try_into,
+            |buf| signature.parse_buffer(buf, ctx),
+            |mut parts| {
+                let fields = parts.pop().ok_or(DbusParseError::InvalidHeaderField)?;
+                DbusDict::try_from(fields)?
+                    .into_inner()
+                    .into_iter()
+                    .try_fold(
+                        std::collections::HashMap::new(),
+                        |mut fields, entry| -> Result<_, DbusParseError> {
+                            let code: u8 = DbusByte::try_from(entry.0)?.into();
+                            // Fields with unknown codes must be ignored
+                            if let Ok(field) = HeaderField::try_from(code) {
+                                fields.insert(field, DbusVariant::try_from(entry.1)?);
+                            }
+
+                            Ok(fields)
+                        },
+                    )
+            },
         )(buf)?;
         Ok((buf, Self(inner)))
     }
@@ -112,15 +127,15 @@ impl DbusType for RawHeaderFields {
 
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub struct HeaderFields {
-    path: Option<DbusObjectPath>,
-    interface: Option<DbusString>,
-    member: Option<DbusString>,
-    error_name: Option<DbusString>,
-    reply_serial: Option<DbusUint32>,
-    destination: Option<DbusString>,
-    sender: Option<DbusString>,
-    signature: Option<Signature>,
-    unix_fds: Option<DbusUint32>,
+    pub path: Option<DbusObjectPath>,
+    pub interface: Option<DbusString>,
+    pub member: Option<DbusString>,
+    pub error_name: Option<DbusString>,
+    pub reply_serial: Option<DbusUint32>,
+    pub destination: Option<DbusString>,
+    pub sender: Option<DbusString>,
+    pub signature: Option<Signature>,
+    pub unix_fds: Option<DbusUint32>,
 }
 
 impl TryFrom<RawHeaderFields> for HeaderFields {
@@ -195,54 +210,240 @@ impl TryFrom<RawHeaderFields> for HeaderFields {
     }
 }
 
+impl HeaderFields {
+    /// Encodes the fields as the entries of the header field array
+    fn marshal(&self, m: &mut Marshaller, body_signature: &Signature) {
+        fn field(m: &mut Marshaller, field: HeaderField, signature: &str) {
+            m.align(8);
+            m.u8(field as u8);
+            m.signature(signature);
+        }
+
+        if let Some(path) = &self.path {
+            field(m, HeaderField::Path, "o");
+            m.str(path);
+        }
+
+        let names = [
+            (HeaderField::Interface, &self.interface),
+            (HeaderField::Member, &self.member),
+            (HeaderField::ErrorName, &self.error_name),
+        ];
+        for (code, name) in names.iter() {
+            if let Some(name) = name {
+                field(m, *code, "s");
+                m.str(name);
+            }
+        }
+
+        if let Some(reply_serial) = self.reply_serial {
+            field(m, HeaderField::ReplySerial, "u");
+            m.u32(reply_serial.into());
+        }
+
+        let names = [
+            (HeaderField::Destination, &self.destination),
+            (HeaderField::Sender, &self.sender),
+        ];
+        for (code, name) in names.iter() {
+            if let Some(name) = name {
+                field(m, *code, "s");
+                m.str(name);
+            }
+        }
+
+        if !body_signature.is_empty() {
+            field(m, HeaderField::Signature, "g");
+            m.signature(&body_signature.to_string());
+        }
+
+        if let Some(unix_fds) = self.unix_fds {
+            field(m, HeaderField::UnixFdCount, "u");
+            m.u32(unix_fds.into());
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Header {
-    fixed: FixedHeaderPart,
-    fields: HeaderFields,
+    pub fixed: FixedHeaderPart,
+    pub fields: HeaderFields,
 }
 
 impl DbusType for Header {
     const ALIGNMENT: usize = 0;
 
-    fn unmarshal<'a, 'b>(
-        buf: &'b [u8],
-        e: MessageEndianness,
-        s: &'a Signature,
-    ) -> IResult<&'b [u8], Self> {
-        let (buf, fixed) = FixedHeaderPart::unmarshal(buf, e, s)?;
+    fn unmarshal<'b>(buf: &'b [u8], ctx: &ParseContext, s: &Signature) -> IResult<&'b [u8], Self> {
+        let (buf, fixed) = FixedHeaderPart::unmarshal(buf, ctx, s)?;
+        if fixed.protocol_version != 1 {
+            return Err(nom::Err::Failure((buf, nom::error::ErrorKind::Verify)));
+        }
+
+        let ctx = ctx.with_endianness(fixed.endianness);
         let (buf, fields) = map_res(
-            |buf| RawHeaderFields::unmarshal(buf, fixed.endianness, s),
+            |buf| RawHeaderFields::unmarshal(buf, &ctx, s),
             HeaderFields::try_from,
         )(buf)?;
 
+        // The body starts on an 8-byte boundary
+        let (buf, _) = ctx.align(buf, 8)?;
+
+        if ctx.offset(buf) + fixed.msg_len as usize > ctx.limits().max_message_len {
+            return Err(nom::Err::Failure((buf, nom::error::ErrorKind::TooLarge)));
+        }
+
         Ok((buf, Self { fixed, fields }))
     }
 }
 
 impl Header {
-    pub fn parse_message<'a>(self, buf: &'a [u8]) -> nom::IResult<&'a [u8], Message> {
-        if let Some(signature) = &self.fields.signature {
-            signature
-                .parse_buffer(buf, self.fixed.endianness)
-                .map(move |(buf, parts)| {
-                    (
-                        buf,
-                        Message {
-                            header: self,
-                            message: parts,
-                        },
-                    )
-                })
-        } else if buf.len() == 0 {
-            Ok((
-                buf,
-                Message {
-                    header: self,
-                    message: vec![],
-                },
-            ))
-        } else {
-            Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)))
+    /// Header of a new message, whose serial is assigned when sending it
+    pub fn new(message_type: MessageType) -> Self {
+        Self {
+            fixed: FixedHeaderPart {
+                endianness: MessageEndianness::default(),
+                message_type,
+                flags: MessageFlags::empty(),
+                protocol_version: 1,
+                msg_len: 0,
+                msg_serial: 0,
+            },
+            fields: HeaderFields::default(),
+        }
+    }
+
+    pub fn message_type(&self) -> MessageType {
+        self.fixed.message_type
+    }
+
+    pub fn serial(&self) -> u32 {
+        self.fixed.msg_serial
+    }
+
+    pub fn reply_serial(&self) -> Option<u32> {
+        self.fields.reply_serial.map(u32::from)
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        self.fields.path.as_deref()
+    }
+
+    pub fn interface(&self) -> Option<&str> {
+        self.fields.interface.as_deref()
+    }
+
+    pub fn member(&self) -> Option<&str> {
+        self.fields.member.as_deref()
+    }
+
+    pub fn error_name(&self) -> Option<&str> {
+        self.fields.error_name.as_deref()
+    }
+
+    pub fn destination(&self) -> Option<&str> {
+        self.fields.destination.as_deref()
+    }
+
+    pub fn sender(&self) -> Option<&str> {
+        self.fields.sender.as_deref()
+    }
+
+    /// Decodes the header at the start of a message
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        Self::parse_with_limits(buf, ParseLimits::default())
+    }
+
+    /// Decodes a header, rejecting messages longer than `limits` allow
+    pub fn parse_with_limits(buf: &[u8], limits: ParseLimits) -> IResult<&[u8], Self> {
+        Self::unmarshal(
+            buf,
+            &ParseContext::new(MessageEndianness::default(), buf).with_limits(limits),
+            &Signature::default(),
+        )
+    }
+
+    pub fn endianness(&self) -> MessageEndianness {
+        self.fixed.endianness
+    }
+
+    /// Encodes the header of a message whose body has `body_len` bytes
+    pub(crate) fn marshal(
+        &self,
+        endianness: MessageEndianness,
+        body_signature: &Signature,
+        body_len: usize,
+    ) -> Vec<u8> {
+        let mut m = Marshaller::new(endianness);
+        m.u8(endianness as u8);
+        m.u8(self.fixed.message_type as u8);
+        m.u8(self.fixed.flags.bits());
+        m.u8(self.fixed.protocol_version);
+        m.u32(body_len as u32);
+        m.u32(self.fixed.msg_serial);
+        m.array(8, |m| self.fields.marshal(m, body_signature));
+        // The body starts on an 8-byte boundary
+        m.align(8);
+        m.into_inner()
+    }
+
+    /// Signature of the body, empty when the header has no signature field
+    pub(crate) fn body_signature(&self) -> Signature {
+        self.fields.signature.clone().unwrap_or_default()
+    }
+
+    /// Splits the body of the message off `buf`, which starts right after the header
+    pub(crate) fn take_body<'a>(&self, buf: &'a [u8]) -> IResult<&'a [u8], &'a [u8]> {
+        take(self.fixed.msg_len as usize)(buf)
+    }
+
+    pub fn parse_message(self, buf: &[u8]) -> nom::IResult<&[u8], Message> {
+        self.parse_message_with_limits(buf, ParseLimits::default())
+    }
+
+    /// Decodes the body, holding arrays and nesting to `limits`
+    pub fn parse_message_with_limits(
+        self,
+        buf: &[u8],
+        limits: ParseLimits,
+    ) -> nom::IResult<&[u8], Message> {
+        let (buf, body) = self.take_body(buf)?;
+        let ctx = ParseContext::new(self.fixed.endianness, body).with_limits(limits);
+        let signature = self.body_signature();
+        let (rest, parts) = signature.parse_buffer(body, &ctx)?;
+        // The body must hold exactly the values of its signature
+        if !rest.is_empty() {
+            return Err(nom::Err::Error((rest, nom::error::ErrorKind::Verify)));
         }
+
+        let message = Message {
+            header: self,
+            message: parts,
+            fds: UnixFds::default(),
+        };
+        // Descriptors arrive out of band, but their count is known already
+        if message.check_unix_fds().is_err() {
+            return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+        }
+
+        Ok((buf, message))
+    }
+
+    /// Decodes the body without copying strings and byte arrays out of `buf`
+    pub fn parse_message_borrowed(self, buf: &[u8]) -> nom::IResult<&[u8], BorrowedMessage<'_>> {
+        let (buf, body) = self.take_body(buf)?;
+        let ctx = ParseContext::new(self.fixed.endianness, body);
+        let signature = self.body_signature();
+        let (rest, parts) = signature.parse_buffer_borrowed(body, &ctx)?;
+        if !rest.is_empty() {
+            return Err(nom::Err::Error((rest, nom::error::ErrorKind::Verify)));
+        }
+
+        Ok((
+            buf,
+            BorrowedMessage {
+                header: self,
+                message: parts,
+            },
+        ))
     }
 }
@@ -0,0 +1,181 @@
+use super::components::*;
+use super::HeaderPeek;
+use crate::error::DbusParseError;
+use crate::marshal::Marshaller;
+use crate::parse_context::{ParseContext, ParseLimits};
+use crate::signature_type::{Signature, SignatureType};
+use nom::bytes::streaming::take;
+use nom::number::streaming::le_u8;
+use nom::IResult;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+/// Changes to the header of an encoded message, applied without touching its body
+///
+/// The serial and an existing reply serial are overwritten in place; anything
+/// else re-lays out the header field array, copying the other fields verbatim.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HeaderPatch {
+    serial: Option<u32>,
+    reply_serial: Option<u32>,
+    sender: Option<String>,
+    destination: Option<String>,
+}
+
+impl HeaderPatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_serial(self, serial: u32) -> Self {
+        Self {
+            serial: Some(serial),
+            ..self
+        }
+    }
+
+    pub fn with_reply_serial(self, reply_serial: u32) -> Self {
+        Self {
+            reply_serial: Some(reply_serial),
+            ..self
+        }
+    }
+
+    pub fn with_sender<S: Into<String>>(self, sender: S) -> Self {
+        Self {
+            sender: Some(sender.into()),
+            ..self
+        }
+    }
+
+    pub fn with_destination<S: Into<String>>(self, destination: S) -> Self {
+        Self {
+            destination: Some(destination.into()),
+            ..self
+        }
+    }
+
+    /// Applies the changes to the encoded message at the start of `message`
+    pub fn apply(&self, message: &mut Vec<u8>) -> Result<(), DbusParseError> {
+        let (_, peek) =
+            HeaderPeek::from_bytes(message).map_err(|_| DbusParseError::MalformedHeader)?;
+        if message.len() < peek.message_len() {
+            return Err(DbusParseError::MalformedHeader);
+        }
+
+        let endianness = peek.fixed.endianness;
+        let (_, fields) =
+            field_spans(message, endianness).map_err(|_| DbusParseError::MalformedHeader)?;
+        let reply_serial_field = fields
+            .iter()
+            .find(|(code, _)| *code == HeaderField::ReplySerial as u8);
+
+        let relayout = self.sender.is_some()
+            || self.destination.is_some()
+            || (self.reply_serial.is_some() && reply_serial_field.is_none());
+        if relayout {
+            let header = self.header_fields(message, endianness, &fields, peek.header_len)?;
+            message.splice(..peek.header_len, header);
+        } else if let (Some(reply_serial), Some((_, span))) =
+            (self.reply_serial, reply_serial_field)
+        {
+            // The field starts 8-aligned with its code and the `u` signature,
+            // which leaves the value at the next 4-byte boundary
+            write_u32(message, span.start + 4, reply_serial, endianness);
+        }
+
+        if let Some(serial) = self.serial {
+            write_u32(message, 8, serial, endianness);
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the fixed part and the patched field array of the header
+    fn header_fields(
+        &self,
+        message: &[u8],
+        endianness: MessageEndianness,
+        fields: &[(u8, Range<usize>)],
+        header_len: usize,
+    ) -> Result<Vec<u8>, DbusParseError> {
+        let replaced = |code: u8| match HeaderField::try_from(code) {
+            Ok(HeaderField::ReplySerial) => self.reply_serial.is_some(),
+            Ok(HeaderField::Sender) => self.sender.is_some(),
+            Ok(HeaderField::Destination) => self.destination.is_some(),
+            _ => false,
+        };
+
+        let mut m = Marshaller::from_vec(endianness, message[..12].to_vec());
+        m.u32(0);
+        let start = m.len();
+        for (_, span) in fields.iter().filter(|(code, _)| !replaced(*code)) {
+            m.align(8);
+            m.bytes(&message[span.clone()]);
+        }
+
+        if let Some(reply_serial) = self.reply_serial {
+            m.align(8);
+            m.u8(HeaderField::ReplySerial as u8);
+            m.signature("u");
+            m.u32(reply_serial);
+        }
+
+        let names = [
+            (HeaderField::Sender, &self.sender),
+            (HeaderField::Destination, &self.destination),
+        ];
+        for (field, name) in names.iter() {
+            if let Some(name) = name {
+                if name.contains('\0') {
+                    return Err(DbusParseError::InvalidHeaderField);
+                }
+
+                m.align(8);
+                m.u8(*field as u8);
+                m.signature("s");
+                m.str(name);
+            }
+        }
+
+        let len = m.len() - start;
+        m.set_u32(12, len as u32);
+        m.align(8);
+
+        let header = m.into_inner();
+        if header.len() + message.len() - header_len > ParseLimits::default().max_message_len {
+            return Err(DbusParseError::MalformedHeader);
+        }
+
+        Ok(header)
+    }
+}
+
+fn write_u32(message: &mut [u8], offset: usize, v: u32, endianness: MessageEndianness) {
+    let bytes = match endianness {
+        MessageEndianness::BigEndian => v.to_be_bytes(),
+        MessageEndianness::LittleEndian => v.to_le_bytes(),
+    };
+    message[offset..offset + 4].copy_from_slice(&bytes);
+}
+
+/// Locates each `(yv)` entry of the header field array, by its code
+fn field_spans(
+    message: &[u8],
+    endianness: MessageEndianness,
+) -> IResult<&[u8], Vec<(u8, Range<usize>)>> {
+    let ctx = ParseContext::new(endianness, message);
+    let variant = Signature::from(SignatureType::Variant);
+    let (buf, _) = take(12usize)(message)?;
+    let mut spans = vec![];
+    let (buf, _) = ctx.for_each_element(buf, 8, |buf| {
+        let (buf, _) = ctx.align(buf, 8)?;
+        let start = ctx.offset(buf);
+        let (buf, code) = le_u8(buf)?;
+        let (buf, _) = variant.skip(buf, &ctx)?;
+        spans.push((code, start..ctx.offset(buf)));
+        Ok((buf, ()))
+    })?;
+
+    Ok((buf, spans))
+}
@@ -0,0 +1,180 @@
+use super::components::*;
+use super::FixedHeaderPart;
+use crate::parse_context::{padding, ParseContext};
+use crate::signature_type::Signature;
+use crate::skip::skip_variant;
+use crate::types::basic::*;
+use crate::DbusType;
+use nom::number::streaming::le_u8;
+use nom::IResult;
+use std::convert::TryFrom;
+
+/// Length of the fixed part of the header, up to and including the length of the field array
+pub(crate) const FIXED_HEADER_LEN: usize = 16;
+
+/// The header fields of a message, borrowed from its bytes
+///
+/// Unlike [`Header`](super::Header), nothing is copied out of the buffer and
+/// the body is left untouched, which is all a router needs to forward it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderPeek<'a> {
+    pub fixed: FixedHeaderPart,
+    pub path: Option<&'a str>,
+    pub interface: Option<&'a str>,
+    pub member: Option<&'a str>,
+    pub error_name: Option<&'a str>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<&'a str>,
+    pub sender: Option<&'a str>,
+    pub signature: Option<&'a str>,
+    pub unix_fds: Option<u32>,
+    /// Length of the header, including the padding before the body
+    pub header_len: usize,
+}
+
+fn verify(buf: &[u8]) -> nom::Err<(&[u8], nom::error::ErrorKind)> {
+    nom::Err::Error((buf, nom::error::ErrorKind::Verify))
+}
+
+impl<'a> HeaderPeek<'a> {
+    /// Reads the header at the start of `buf`, returning the input positioned at the body
+    ///
+    /// Nothing is allocated, even fields with an unknown code are skipped by
+    /// their signature without decoding them.
+    pub fn from_bytes(buf: &'a [u8]) -> IResult<&'a [u8], Self> {
+        let origin = buf;
+        let ctx = ParseContext::new(MessageEndianness::default(), origin);
+        let (buf, fixed) = FixedHeaderPart::unmarshal(buf, &ctx, &Signature::default())?;
+        if fixed.protocol_version != 1 {
+            return Err(nom::Err::Failure((buf, nom::error::ErrorKind::Verify)));
+        }
+
+        let ctx = ctx.with_endianness(fixed.endianness);
+        let mut peek = Self {
+            fixed,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            signature: None,
+            unix_fds: None,
+            header_len: 0,
+        };
+
+        // The fields are an `a(yv)`, walked without building the variants
+        let (buf, _) = ctx.for_each_element(buf, 8, |buf| {
+            let (buf, _) = ctx.align(buf, 8)?;
+            let (buf, code) = le_u8(buf)?;
+            let (buf, signature) = unmarshal_signature_str(buf)?;
+            peek.read_field(code, signature, buf, &ctx)
+        })?;
+
+        let (buf, _) = ctx.align(buf, 8)?;
+        peek.header_len = ctx.offset(buf);
+        if peek.message_len() > ctx.limits().max_message_len {
+            return Err(nom::Err::Failure((buf, nom::error::ErrorKind::TooLarge)));
+        }
+
+        Ok((buf, peek))
+    }
+
+    fn read_field(
+        &mut self,
+        code: u8,
+        signature: &str,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+    ) -> IResult<&'a [u8], ()> {
+        let field = match HeaderField::try_from(code) {
+            Ok(field) => field,
+            // Unknown fields must be ignored
+            Err(_) => return skip_variant(signature, buf, ctx),
+        };
+
+        let expected = match field {
+            HeaderField::Path => "o",
+            HeaderField::ReplySerial | HeaderField::UnixFdCount => "u",
+            HeaderField::Signature => "g",
+            _ => "s",
+        };
+        if signature != expected {
+            return Err(verify(buf));
+        }
+
+        let (buf, value) = match field {
+            HeaderField::ReplySerial | HeaderField::UnixFdCount => {
+                let (buf, value) = ctx.align_u32(buf)?;
+                match field {
+                    HeaderField::ReplySerial => self.reply_serial = Some(value),
+                    _ => self.unix_fds = Some(value),
+                }
+
+                return Ok((buf, ()));
+            }
+            HeaderField::Signature => unmarshal_signature_str(buf)?,
+            _ => unmarshal_str(buf, ctx)?,
+        };
+
+        match field {
+            HeaderField::Path => {
+                validate_object_path(value).map_err(|_| verify(buf))?;
+                self.path = Some(value);
+            }
+            HeaderField::Interface => self.interface = Some(value),
+            HeaderField::Member => self.member = Some(value),
+            HeaderField::ErrorName => self.error_name = Some(value),
+            HeaderField::Destination => self.destination = Some(value),
+            HeaderField::Sender => self.sender = Some(value),
+            HeaderField::Signature => self.signature = Some(value),
+            _ => return Err(verify(buf)),
+        }
+
+        Ok((buf, ()))
+    }
+
+    /// Length of the message starting `buf`, once enough of it is buffered to tell
+    ///
+    /// Only the fixed header and the length of the field array are read, so
+    /// this is cheap enough to call on every read while framing a stream.
+    /// `None` is also returned when the first byte is not a valid endianness,
+    /// however much is buffered.
+    pub fn frame_len(buf: &[u8]) -> Option<usize> {
+        let prefix = buf.get(..FIXED_HEADER_LEN)?;
+        let from_bytes = match prefix[0] {
+            b'l' => u32::from_le_bytes,
+            b'B' => u32::from_be_bytes,
+            _ => return None,
+        };
+        let read_u32 = |offset: usize| {
+            from_bytes([
+                prefix[offset],
+                prefix[offset + 1],
+                prefix[offset + 2],
+                prefix[offset + 3],
+            ])
+        };
+
+        let header_len = FIXED_HEADER_LEN + read_u32(12) as usize;
+        Some(header_len + padding(header_len, 8) + read_u32(4) as usize)
+    }
+
+    pub fn message_type(&self) -> MessageType {
+        self.fixed.message_type
+    }
+
+    pub fn serial(&self) -> u32 {
+        self.fixed.msg_serial
+    }
+
+    pub fn body_len(&self) -> usize {
+        self.fixed.msg_len as usize
+    }
+
+    /// Length of the whole message, header and body
+    pub fn message_len(&self) -> usize {
+        self.header_len + self.body_len()
+    }
+}
@@ -0,0 +1,278 @@
+use crate::introspection::{self, Annotation, Arg, Direction, Node, Signal};
+use crate::object_manager::OBJECT_MANAGER_INTERFACE;
+use crate::peer::PEER_INTERFACE;
+use crate::properties::PROPERTIES_INTERFACE;
+use crate::signature_type::Signature;
+use crate::{
+    error_name, DbusTypeContainer, EmitsChanged, Interface, Message, MethodError, ObjectServer,
+};
+use std::collections::BTreeSet;
+
+pub const INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+
+fn arg(signature: &str, name: &str, direction: Direction) -> Arg {
+    let signature = signature.parse().expect("the signature is valid");
+    Arg::new(signature, direction).with_name(name)
+}
+
+fn method(name: &str, args: Vec<Arg>) -> introspection::Method {
+    introspection::Method {
+        args,
+        ..introspection::Method::new(name)
+    }
+}
+
+fn signal(name: &str, args: Vec<Arg>) -> Signal {
+    Signal {
+        args,
+        ..Signal::new(name)
+    }
+}
+
+fn peer() -> introspection::Interface {
+    introspection::Interface {
+        methods: vec![
+            method("Ping", vec![]),
+            method(
+                "GetMachineId",
+                vec![arg("s", "machine_uuid", Direction::Out)],
+            ),
+        ],
+        ..introspection::Interface::new(PEER_INTERFACE)
+    }
+}
+
+fn introspectable() -> introspection::Interface {
+    introspection::Interface {
+        methods: vec![method(
+            "Introspect",
+            vec![arg("s", "xml_data", Direction::Out)],
+        )],
+        ..introspection::Interface::new(INTROSPECTABLE_INTERFACE)
+    }
+}
+
+fn properties() -> introspection::Interface {
+    introspection::Interface {
+        methods: vec![
+            method(
+                "Get",
+                vec![
+                    arg("s", "interface_name", Direction::In),
+                    arg("s", "property_name", Direction::In),
+                    arg("v", "value", Direction::Out),
+                ],
+            ),
+            method(
+                "GetAll",
+                vec![
+                    arg("s", "interface_name", Direction::In),
+                    arg("a{sv}", "props", Direction::Out),
+                ],
+            ),
+            method(
+                "Set",
+                vec![
+                    arg("s", "interface_name", Direction::In),
+                    arg("s", "property_name", Direction::In),
+                    arg("v", "value", Direction::In),
+                ],
+            ),
+        ],
+        signals: vec![signal(
+            "PropertiesChanged",
+            vec![
+                arg("s", "interface_name", Direction::Out),
+                arg("a{sv}", "changed_properties", Direction::Out),
+                arg("as", "invalidated_properties", Direction::Out),
+            ],
+        )],
+        ..introspection::Interface::new(PROPERTIES_INTERFACE)
+    }
+}
+
+fn object_manager() -> introspection::Interface {
+    introspection::Interface {
+        methods: vec![method(
+            "GetManagedObjects",
+            vec![arg(
+                "a{oa{sa{sv}}}",
+                "objpath_interfaces_and_properties",
+                Direction::Out,
+            )],
+        )],
+        signals: vec![
+            signal(
+                "InterfacesAdded",
+                vec![
+                    arg("o", "object_path", Direction::Out),
+                    arg("a{sa{sv}}", "interfaces_and_properties", Direction::Out),
+                ],
+            ),
+            signal(
+                "InterfacesRemoved",
+                vec![
+                    arg("o", "object_path", Direction::Out),
+                    arg("as", "interfaces", Direction::Out),
+                ],
+            ),
+        ],
+        ..introspection::Interface::new(OBJECT_MANAGER_INTERFACE)
+    }
+}
+
+/// One unnamed argument per single complete type of `signature`
+///
+/// Signatures built with `Signature::new` are not checked when registered,
+/// so an invalid one fails the introspection instead.
+fn args(signature: &Signature, direction: Direction) -> Result<Vec<Arg>, MethodError> {
+    let types = signature
+        .split_complete_types()
+        .map_err(|_| MethodError::failed(format!("Invalid signature \"{}\"", signature)))?;
+    Ok(types
+        .into_iter()
+        .map(|signature| Arg::new(signature, direction))
+        .collect())
+}
+
+/// The description of a registered interface
+fn describe(interface: &Interface) -> Result<introspection::Interface, MethodError> {
+    let methods = interface
+        .methods()
+        .map(|(name, m)| {
+            let mut method_args = args(m.in_signature(), Direction::In)?;
+            method_args.extend(args(m.out_signature(), Direction::Out)?);
+            Ok(method(name, method_args))
+        })
+        .collect::<Result<_, MethodError>>()?;
+    let signals = interface
+        .signals()
+        .map(|(name, signature)| Ok(signal(name, args(signature, Direction::Out)?)))
+        .collect::<Result<_, MethodError>>()?;
+    let properties = interface
+        .properties()
+        .map(|(name, p)| {
+            let mut property =
+                introspection::Property::new(name, p.signature().clone(), p.access());
+            let emits_changed = match p.emits_changed() {
+                EmitsChanged::True => None,
+                EmitsChanged::Invalidates => Some("invalidates"),
+                EmitsChanged::Const => Some("const"),
+                EmitsChanged::False => Some("false"),
+            };
+            if let Some(value) = emits_changed {
+                property.annotations.push(Annotation::new(
+                    "org.freedesktop.DBus.Property.EmitsChangedSignal",
+                    value,
+                ));
+            }
+            property
+        })
+        .collect();
+
+    Ok(introspection::Interface {
+        methods,
+        signals,
+        properties,
+        ..introspection::Interface::new(interface.name())
+    })
+}
+
+/// Names of the nodes right below `path`, whether they are objects or lead to some
+fn children<'a>(server: &'a ObjectServer, path: &str) -> BTreeSet<&'a str> {
+    server
+        .paths()
+        .chain(server.object_managers())
+        .filter_map(|p| {
+            let rest = if path == "/" {
+                p.strip_prefix('/')
+            } else {
+                p.strip_prefix(path)?.strip_prefix('/')
+            }?;
+            rest.split('/').next().filter(|child| !child.is_empty())
+        })
+        .collect()
+}
+
+/// The introspection XML of the node at `path`
+///
+/// Besides the objects, nodes on the way to them can be introspected too,
+/// so that tools can walk the tree from `/`. Paths with neither objects nor
+/// object managers at or below them are unknown objects.
+pub(crate) fn introspect(server: &ObjectServer, path: &str) -> Result<String, MethodError> {
+    let children = children(server, path);
+    let is_object = server.has_object(path);
+    let is_manager = server.object_managers().any(|manager| manager == path);
+    if path != "/" && !is_object && !is_manager && children.is_empty() {
+        return Err(MethodError::new(
+            error_name::UNKNOWN_OBJECT,
+            format!("No object at {}", path),
+        ));
+    }
+
+    // Interfaces the server implements, unless the object has its own
+    let standard = [
+        (PEER_INTERFACE, true),
+        (INTROSPECTABLE_INTERFACE, true),
+        (PROPERTIES_INTERFACE, is_object),
+        (OBJECT_MANAGER_INTERFACE, is_manager),
+    ];
+
+    let mut node = Node::new();
+    for (name, implemented) in standard.iter() {
+        let overridden = server
+            .interfaces(path)
+            .any(|interface| interface.name() == *name);
+        if !*implemented || overridden {
+            continue;
+        }
+
+        node.interfaces.push(match *name {
+            PEER_INTERFACE => peer(),
+            INTROSPECTABLE_INTERFACE => introspectable(),
+            PROPERTIES_INTERFACE => properties(),
+            _ => object_manager(),
+        });
+    }
+
+    for interface in server.interfaces(path) {
+        node.interfaces.push(describe(interface)?);
+    }
+    node.nodes = children
+        .into_iter()
+        .map(|child| Node {
+            name: Some(child.to_string()),
+            ..Node::default()
+        })
+        .collect();
+    Ok(node.to_xml())
+}
+
+/// Answers a call of `org.freedesktop.DBus.Introspectable`
+pub(crate) fn handle(server: &ObjectServer, call: &Message) -> Result<Vec<Message>, MethodError> {
+    let header = &call.header;
+    let path = header.path().unwrap_or_default();
+    let member = header.member().unwrap_or_default();
+    if member != "Introspect" {
+        return Err(MethodError::new(
+            error_name::UNKNOWN_METHOD,
+            format!(
+                "No method {} on interface {}",
+                member, INTROSPECTABLE_INTERFACE
+            ),
+        ));
+    }
+
+    let signature = call.body_signature();
+    if signature != Signature::default() {
+        return Err(MethodError::invalid_args(format!(
+            "{} expects no arguments, not \"{}\"",
+            member, signature
+        )));
+    }
+
+    let xml = introspect(server, path)?;
+    Ok(vec![
+        Message::method_return(call).with_body(vec![DbusTypeContainer::String(xml.into())])
+    ])
+}
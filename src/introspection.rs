@@ -0,0 +1,760 @@
+//! The D-Bus introspection format, as returned by `org.freedesktop.DBus.Introspectable.Introspect`
+//!
+//! [`Node`] parses the XML with [`str::parse`] and writes it back with
+//! [`Node::to_xml`]. Elements outside the format, such as documentation, are
+//! skipped when parsing.
+
+use crate::signature_type::Signature;
+use crate::PropertyAccess;
+use failure_derive::Fail;
+use std::fmt::{self, Write};
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum IntrospectionError {
+    #[fail(display = "The XML is malformed at byte {}", _0)]
+    MalformedXml(usize),
+    #[fail(
+        display = "The closing tag {} does not match the open element {}",
+        _0, _1
+    )]
+    MismatchedTag(String, String),
+    #[fail(display = "The document root is {}, not node", _0)]
+    UnexpectedRoot(String),
+    #[fail(display = "The {} element requires the attribute {}", _0, _1)]
+    MissingAttribute(String, String),
+    #[fail(display = "The attribute {} has the invalid value {}", _0, _1)]
+    InvalidValue(String, String),
+    #[fail(display = "The type {} is not a single complete type", _0)]
+    InvalidSignature(String),
+    #[fail(display = "Elements are nested more than 64 deep at byte {}", _0)]
+    TooDeep(usize),
+}
+
+/// How deep elements can be nested, far more than any real document needs
+///
+/// [`IntrospectionError::TooDeep`] spells the value out in its message.
+const MAX_DEPTH: usize = 64;
+
+pub const DOCTYPE: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub name: String,
+    pub value: String,
+}
+
+impl Annotation {
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+/// An argument of a method or signal, of a single complete type
+///
+/// Signal arguments are always [`Direction::Out`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Arg {
+    pub name: Option<String>,
+    pub signature: Signature,
+    pub direction: Direction,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Arg {
+    pub fn new(signature: Signature, direction: Direction) -> Self {
+        Self {
+            name: None,
+            signature,
+            direction,
+            annotations: vec![],
+        }
+    }
+
+    pub fn with_name<S: Into<String>>(self, name: S) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+}
+
+/// The signature of the `args` going in `direction`, one after the other
+fn args_signature(args: &[Arg], direction: Direction) -> Signature {
+    let mut signature = Signature::default();
+    for arg in args.iter().filter(|arg| arg.direction == direction) {
+        signature.extend_from_slice(&arg.signature);
+    }
+
+    signature
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Method {
+    pub name: String,
+    pub args: Vec<Arg>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Method {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            args: vec![],
+            annotations: vec![],
+        }
+    }
+
+    /// Signature calls must have
+    pub fn in_signature(&self) -> Signature {
+        args_signature(&self.args, Direction::In)
+    }
+
+    /// Signature of the replies
+    pub fn out_signature(&self) -> Signature {
+        args_signature(&self.args, Direction::Out)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signal {
+    pub name: String,
+    pub args: Vec<Arg>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Signal {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            args: vec![],
+            annotations: vec![],
+        }
+    }
+
+    pub fn signature(&self) -> Signature {
+        args_signature(&self.args, Direction::Out)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub name: String,
+    pub signature: Signature,
+    pub access: PropertyAccess,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Property {
+    pub fn new<S: Into<String>>(name: S, signature: Signature, access: PropertyAccess) -> Self {
+        Self {
+            name: name.into(),
+            signature,
+            access,
+            annotations: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub methods: Vec<Method>,
+    pub signals: Vec<Signal>,
+    pub properties: Vec<Property>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Interface {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            methods: vec![],
+            signals: vec![],
+            properties: vec![],
+            annotations: vec![],
+        }
+    }
+
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.iter().find(|signal| signal.name == name)
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties
+            .iter()
+            .find(|property| property.name == name)
+    }
+}
+
+/// An object, or a node leading to objects, with the nodes right below it
+///
+/// Child nodes usually only have a name, relative to their parent, and are
+/// introspected on their own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Node {
+    pub name: Option<String>,
+    pub interfaces: Vec<Interface>,
+    pub nodes: Vec<Node>,
+}
+
+impl Node {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interface(&self, name: &str) -> Option<&Interface> {
+        self.interfaces
+            .iter()
+            .find(|interface| interface.name == name)
+    }
+
+    /// The document, with the standard doctype, as sent in reply to `Introspect`
+    pub fn to_xml(&self) -> String {
+        format!("{}{}", DOCTYPE, self)
+    }
+}
+
+/// An element of the document, before it is given a meaning
+struct Element<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, String)>,
+    children: Vec<Element<'a>>,
+}
+
+impl<'a> Element<'a> {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn required(&self, name: &str) -> Result<&str, IntrospectionError> {
+        self.attribute(name).ok_or_else(|| {
+            IntrospectionError::MissingAttribute(self.name.to_string(), name.to_string())
+        })
+    }
+
+    fn children(&self, name: &'static str) -> impl Iterator<Item = &Element<'a>> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+enum Tag<'a> {
+    /// A start tag, and whether it also ends the element
+    Start(Element<'a>, bool),
+    End(&'a str),
+}
+
+/// Replaces the predefined entities and character references of `s`
+fn unescape(s: &str, offset: usize) -> Result<String, IntrospectionError> {
+    let mut ret = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        ret.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(';')
+            .ok_or(IntrospectionError::MalformedXml(offset))?;
+        let c = match &rest[1..end] {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            reference => {
+                let code = match reference.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => reference
+                        .strip_prefix('#')
+                        .ok_or(IntrospectionError::MalformedXml(offset))?
+                        .parse(),
+                };
+                code.ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or(IntrospectionError::MalformedXml(offset))?
+            }
+        };
+        ret.push(c);
+        rest = &rest[end + 1..];
+    }
+
+    ret.push_str(rest);
+    Ok(ret)
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Reads the tags of a document, skipping text, comments, declarations and CDATA
+struct Reader<'a> {
+    xml: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn rest(&self) -> &'a str {
+        &self.xml[self.pos..]
+    }
+
+    fn error(&self) -> IntrospectionError {
+        IntrospectionError::MalformedXml(self.pos)
+    }
+
+    /// Moves past the next `terminator`
+    fn skip_past(&mut self, terminator: &str) -> Result<(), IntrospectionError> {
+        let end = self.rest().find(terminator).ok_or_else(|| self.error())?;
+        self.pos += end + terminator.len();
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Reads a tag or attribute name
+    fn name(&mut self) -> Result<&'a str, IntrospectionError> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || "/>=<\"'".contains(c))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error());
+        }
+
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// Skips `<!DOCTYPE ...>`, along with its internal subset
+    fn skip_declaration(&mut self) -> Result<(), IntrospectionError> {
+        let mut depth = 0;
+        let mut quote = None;
+        for (i, c) in self.rest().char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (None, '[') => depth += 1,
+                (None, ']') => depth -= 1,
+                (None, '>') if depth == 0 => {
+                    self.pos += i + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        Err(self.error())
+    }
+
+    fn start_tag(&mut self) -> Result<Tag<'a>, IntrospectionError> {
+        let name = self.name()?;
+        let mut attributes: Vec<(&str, String)> = vec![];
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("/>") || rest.starts_with('>') {
+                let empty = rest.starts_with('/');
+                self.pos += if empty { 2 } else { 1 };
+                let element = Element {
+                    name,
+                    attributes,
+                    children: vec![],
+                };
+                return Ok(Tag::Start(element, empty));
+            }
+
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(self.error());
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+
+            let quote = self.rest().chars().next().ok_or_else(|| self.error())?;
+            if quote != '"' && quote != '\'' {
+                return Err(self.error());
+            }
+            let start = self.pos + 1;
+            let len = self.xml[start..].find(quote).ok_or_else(|| self.error())?;
+            let value = &self.xml[start..start + len];
+            if value.contains('<') || attributes.iter().any(|(k, _)| *k == key) {
+                return Err(self.error());
+            }
+
+            attributes.push((key, unescape(value, start)?));
+            self.pos = start + len + 1;
+        }
+    }
+
+    /// The next tag, or nothing at the end of the document
+    fn next_tag(&mut self) -> Result<Option<Tag<'a>>, IntrospectionError> {
+        loop {
+            match self.rest().find('<') {
+                Some(start) => self.pos += start,
+                None => {
+                    self.pos = self.xml.len();
+                    return Ok(None);
+                }
+            }
+
+            let rest = self.rest();
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.skip_past("]]>")?;
+            } else if rest.starts_with("<!") {
+                self.skip_declaration()?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.name()?;
+                self.skip_whitespace();
+                if !self.rest().starts_with('>') {
+                    return Err(self.error());
+                }
+                self.pos += 1;
+                return Ok(Some(Tag::End(name)));
+            } else {
+                self.pos += 1;
+                return self.start_tag().map(Some);
+            }
+        }
+    }
+
+    /// Reads the whole document into its root element
+    fn document(mut self) -> Result<Element<'a>, IntrospectionError> {
+        let mut open: Vec<Element> = vec![];
+        let mut root = None;
+        while let Some(tag) = self.next_tag()? {
+            let element = match tag {
+                Tag::Start(_, _) if root.is_some() => return Err(self.error()),
+                Tag::Start(_, false) if open.len() == MAX_DEPTH => {
+                    return Err(IntrospectionError::TooDeep(self.pos));
+                }
+                Tag::Start(element, false) => {
+                    open.push(element);
+                    continue;
+                }
+                Tag::Start(element, true) => element,
+                Tag::End(name) => {
+                    let element = open.pop().ok_or_else(|| self.error())?;
+                    if element.name != name {
+                        return Err(IntrospectionError::MismatchedTag(
+                            name.to_string(),
+                            element.name.to_string(),
+                        ));
+                    }
+                    element
+                }
+            };
+
+            match open.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None => root = Some(element),
+            }
+        }
+
+        match root {
+            Some(root) if open.is_empty() => Ok(root),
+            _ => Err(self.error()),
+        }
+    }
+}
+
+fn annotations(element: &Element) -> Result<Vec<Annotation>, IntrospectionError> {
+    element
+        .children("annotation")
+        .map(|annotation| {
+            Ok(Annotation::new(
+                annotation.required("name")?,
+                annotation.required("value")?,
+            ))
+        })
+        .collect()
+}
+
+/// Parses a type that has to be a single complete type
+fn single_type(s: &str) -> Result<Signature, IntrospectionError> {
+    let invalid = || IntrospectionError::InvalidSignature(s.to_string());
+    let signature: Signature = s.parse().map_err(|_| invalid())?;
+    match signature.split_complete_types() {
+        Ok(ref types) if types.len() == 1 => Ok(signature),
+        _ => Err(invalid()),
+    }
+}
+
+/// The arguments of a method, which are `in` by default, or of a signal, which can only be `out`
+fn args(element: &Element, default: Direction) -> Result<Vec<Arg>, IntrospectionError> {
+    element
+        .children("arg")
+        .map(|arg| {
+            let direction = match (arg.attribute("direction"), default) {
+                (None, direction) => direction,
+                (Some("out"), _) => Direction::Out,
+                (Some("in"), Direction::In) => Direction::In,
+                (Some(value), _) => {
+                    return Err(IntrospectionError::InvalidValue(
+                        "direction".to_string(),
+                        value.to_string(),
+                    ))
+                }
+            };
+
+            Ok(Arg {
+                name: arg.attribute("name").map(String::from),
+                signature: single_type(arg.required("type")?)?,
+                direction,
+                annotations: annotations(arg)?,
+            })
+        })
+        .collect()
+}
+
+fn interface(element: &Element) -> Result<Interface, IntrospectionError> {
+    let methods = element
+        .children("method")
+        .map(|method| {
+            Ok(Method {
+                name: method.required("name")?.to_string(),
+                args: args(method, Direction::In)?,
+                annotations: annotations(method)?,
+            })
+        })
+        .collect::<Result<_, IntrospectionError>>()?;
+    let signals = element
+        .children("signal")
+        .map(|signal| {
+            Ok(Signal {
+                name: signal.required("name")?.to_string(),
+                args: args(signal, Direction::Out)?,
+                annotations: annotations(signal)?,
+            })
+        })
+        .collect::<Result<_, IntrospectionError>>()?;
+    let properties = element
+        .children("property")
+        .map(|property| {
+            let access = match property.required("access")? {
+                "read" => PropertyAccess::Read,
+                "write" => PropertyAccess::Write,
+                "readwrite" => PropertyAccess::ReadWrite,
+                value => {
+                    return Err(IntrospectionError::InvalidValue(
+                        "access".to_string(),
+                        value.to_string(),
+                    ))
+                }
+            };
+
+            Ok(Property {
+                name: property.required("name")?.to_string(),
+                signature: single_type(property.required("type")?)?,
+                access,
+                annotations: annotations(property)?,
+            })
+        })
+        .collect::<Result<_, IntrospectionError>>()?;
+
+    Ok(Interface {
+        name: element.required("name")?.to_string(),
+        methods,
+        signals,
+        properties,
+        annotations: annotations(element)?,
+    })
+}
+
+fn node(element: &Element) -> Result<Node, IntrospectionError> {
+    Ok(Node {
+        name: element.attribute("name").map(String::from),
+        interfaces: element
+            .children("interface")
+            .map(interface)
+            .collect::<Result<_, _>>()?,
+        nodes: element
+            .children("node")
+            .map(node)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+impl std::str::FromStr for Node {
+    type Err = IntrospectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let root = Reader { xml: s, pos: 0 }.document()?;
+        if root.name != "node" {
+            return Err(IntrospectionError::UnexpectedRoot(root.name.to_string()));
+        }
+
+        node(&root)
+    }
+}
+
+fn write_annotations<W: Write>(
+    out: &mut W,
+    annotations: &[Annotation],
+    indent: &str,
+) -> fmt::Result {
+    for annotation in annotations {
+        writeln!(
+            out,
+            "{}<annotation name=\"{}\" value=\"{}\"/>",
+            indent,
+            escape(&annotation.name),
+            escape(&annotation.value)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes a method or a signal, whose arguments only have a direction for methods
+fn write_member<W: Write>(
+    out: &mut W,
+    tag: &str,
+    name: &str,
+    args: &[Arg],
+    annotations: &[Annotation],
+    indent: &str,
+) -> fmt::Result {
+    write!(out, "{}<{} name=\"{}\"", indent, tag, escape(name))?;
+    if args.is_empty() && annotations.is_empty() {
+        return out.write_str("/>\n");
+    }
+
+    out.write_str(">\n")?;
+    let inner = format!("{}  ", indent);
+    for arg in args {
+        write!(
+            out,
+            "{}<arg type=\"{}\"",
+            inner,
+            escape(&arg.signature.to_string())
+        )?;
+        if let Some(name) = &arg.name {
+            write!(out, " name=\"{}\"", escape(name))?;
+        }
+        match (tag, arg.direction) {
+            ("method", Direction::In) => out.write_str(" direction=\"in\"")?,
+            ("method", Direction::Out) => out.write_str(" direction=\"out\"")?,
+            _ => {}
+        }
+
+        if arg.annotations.is_empty() {
+            out.write_str("/>\n")?;
+        } else {
+            out.write_str(">\n")?;
+            write_annotations(out, &arg.annotations, &format!("{}  ", inner))?;
+            writeln!(out, "{}</arg>", inner)?;
+        }
+    }
+
+    write_annotations(out, annotations, &inner)?;
+    writeln!(out, "{}</{}>", indent, tag)
+}
+
+fn write_interface<W: Write>(out: &mut W, interface: &Interface, indent: &str) -> fmt::Result {
+    writeln!(
+        out,
+        "{}<interface name=\"{}\">",
+        indent,
+        escape(&interface.name)
+    )?;
+    let inner = format!("{}  ", indent);
+    for method in &interface.methods {
+        let (name, args) = (&method.name, &method.args);
+        write_member(out, "method", name, args, &method.annotations, &inner)?;
+    }
+
+    for signal in &interface.signals {
+        let (name, args) = (&signal.name, &signal.args);
+        write_member(out, "signal", name, args, &signal.annotations, &inner)?;
+    }
+
+    for property in &interface.properties {
+        let access = match property.access {
+            PropertyAccess::Read => "read",
+            PropertyAccess::Write => "write",
+            PropertyAccess::ReadWrite => "readwrite",
+        };
+        write!(
+            out,
+            "{}<property name=\"{}\" type=\"{}\" access=\"{}\"",
+            inner,
+            escape(&property.name),
+            escape(&property.signature.to_string()),
+            access
+        )?;
+        if property.annotations.is_empty() {
+            out.write_str("/>\n")?;
+        } else {
+            out.write_str(">\n")?;
+            write_annotations(out, &property.annotations, &format!("{}  ", inner))?;
+            writeln!(out, "{}</property>", inner)?;
+        }
+    }
+
+    write_annotations(out, &interface.annotations, &inner)?;
+    writeln!(out, "{}</interface>", indent)
+}
+
+fn write_node<W: Write>(out: &mut W, node: &Node, indent: &str) -> fmt::Result {
+    write!(out, "{}<node", indent)?;
+    if let Some(name) = &node.name {
+        write!(out, " name=\"{}\"", escape(name))?;
+    }
+    if node.interfaces.is_empty() && node.nodes.is_empty() {
+        return out.write_str("/>\n");
+    }
+
+    out.write_str(">\n")?;
+    let inner = format!("{}  ", indent);
+    for interface in &node.interfaces {
+        write_interface(out, interface, &inner)?;
+    }
+    for child in &node.nodes {
+        write_node(out, child, &inner)?;
+    }
+    writeln!(out, "{}</node>", indent)
+}
+
+/// The XML of the node, without the doctype [`Node::to_xml`] adds
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_node(f, self, "")
+    }
+}
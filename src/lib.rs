@@ -1,25 +1,76 @@
-use crate::header::components::MessageEndianness;
+// `failure_derive` expands its impls inside anonymous constants
+#![allow(non_local_definitions)]
+
 use nom::IResult;
+use rand::RngCore;
 
+mod address;
+#[cfg(feature = "tokio")]
+mod async_connection;
+mod bus;
+#[cfg(feature = "tokio")]
+mod codec;
+mod compiled_signature;
+mod connection;
 mod error;
 mod header;
+mod introspectable;
+pub mod introspection;
+mod marshal;
+mod match_index;
+mod match_rule;
 mod message;
+mod name_registry;
+mod object_manager;
+mod object_server;
+mod parse_context;
+mod peer;
+mod properties;
 mod signature_type;
+mod skip;
 
+pub mod sasl;
 mod type_container;
-mod types;
+pub mod types;
+mod unix_fds;
+pub use self::address::*;
+#[cfg(feature = "tokio")]
+pub use self::async_connection::*;
+pub use self::bus::*;
+#[cfg(feature = "tokio")]
+pub use self::codec::*;
+pub use self::compiled_signature::*;
+pub use self::connection::*;
 pub use self::error::*;
 pub use self::header::*;
+pub use self::introspectable::*;
+pub use self::match_index::*;
+pub use self::match_rule::*;
 pub use self::message::*;
+pub use self::name_registry::*;
+pub use self::object_manager::*;
+pub use self::object_server::*;
+pub use self::parse_context::*;
+pub use self::peer::*;
+pub use self::properties::*;
 pub use self::signature_type::*;
 pub use self::type_container::*;
+pub use self::unix_fds::*;
 
 pub trait DbusType: std::fmt::Debug + Clone + PartialEq {
     const ALIGNMENT: usize;
 
-    fn unmarshal<'a, 'b>(
+    /// Decodes a value, first skipping the padding up to `ALIGNMENT`
+    fn unmarshal<'b>(
         buf: &'b [u8],
-        endianness: MessageEndianness,
-        signature: &'a Signature,
+        ctx: &ParseContext,
+        signature: &Signature,
     ) -> IResult<&'b [u8], Self>;
 }
+
+/// Random bytes as lowercase hex
+pub(crate) fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
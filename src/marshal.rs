@@ -0,0 +1,140 @@
+use crate::header::components::MessageEndianness;
+use crate::parse_context::padding;
+use crate::type_container::DbusTypeContainer;
+use crate::types::containers::DbusFixedType;
+
+/// Appends encoded values to a buffer, aligning relative to its start
+#[derive(Debug, Clone)]
+pub(crate) struct Marshaller {
+    endianness: MessageEndianness,
+    buf: Vec<u8>,
+}
+
+impl Marshaller {
+    pub fn new(endianness: MessageEndianness) -> Self {
+        Self::from_vec(endianness, vec![])
+    }
+
+    /// Continues encoding after `buf`, which is the start of a message
+    pub fn from_vec(endianness: MessageEndianness, buf: Vec<u8>) -> Self {
+        Self { endianness, buf }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Pads with zero bytes up to the next `alignment` boundary
+    pub fn align(&mut self, alignment: usize) {
+        let pad = padding(self.buf.len(), alignment);
+        self.buf.resize(self.buf.len() + pad, 0);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.fixed(v);
+    }
+
+    /// Encodes a number, aligned to its size
+    pub fn fixed<T: DbusFixedType>(&mut self, v: T) {
+        self.align(T::SIZE);
+        match self.endianness {
+            MessageEndianness::BigEndian => v.extend_be(&mut self.buf),
+            MessageEndianness::LittleEndian => v.extend_le(&mut self.buf),
+        }
+    }
+
+    /// Overwrites a previously reserved `u32`, such as an array length
+    pub fn set_u32(&mut self, offset: usize, v: u32) {
+        let bytes = match self.endianness {
+            MessageEndianness::BigEndian => v.to_be_bytes(),
+            MessageEndianness::LittleEndian => v.to_le_bytes(),
+        };
+        self.buf[offset..offset + 4].copy_from_slice(&bytes);
+    }
+
+    /// Encodes a string or object path
+    pub fn str(&mut self, v: &str) {
+        self.u32(v.len() as u32);
+        self.buf.extend_from_slice(v.as_bytes());
+        self.buf.push(0);
+    }
+
+    pub fn signature(&mut self, v: &str) {
+        self.u8(v.len() as u8);
+        self.buf.extend_from_slice(v.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Encodes an array whose `alignment`-aligned elements are written by `elements`
+    pub fn array<F: FnOnce(&mut Self)>(&mut self, alignment: usize, elements: F) {
+        self.u32(0);
+        let len_offset = self.len() - 4;
+        // The padding before the first element is not part of the length
+        self.align(alignment);
+        let start = self.len();
+        elements(self);
+        let len = self.len() - start;
+        self.set_u32(len_offset, len as u32);
+    }
+
+    fn fixed_array<T: DbusFixedType>(&mut self, values: &[T]) {
+        self.array(T::SIZE, |m| values.iter().for_each(|v| m.fixed(*v)));
+    }
+
+    pub fn value(&mut self, value: &DbusTypeContainer) {
+        match value {
+            DbusTypeContainer::Boolean(v) => self.u32(bool::from(*v) as u32),
+            DbusTypeContainer::Byte(v) => self.u8(u8::from(*v)),
+            DbusTypeContainer::Uint16(v) => self.fixed(u16::from(*v)),
+            DbusTypeContainer::Int16(v) => self.fixed(i16::from(*v)),
+            DbusTypeContainer::Uint32(v) => self.fixed(u32::from(*v)),
+            DbusTypeContainer::Int32(v) => self.fixed(i32::from(*v)),
+            DbusTypeContainer::Uint64(v) => self.fixed(u64::from(*v)),
+            DbusTypeContainer::Int64(v) => self.fixed(i64::from(*v)),
+            DbusTypeContainer::Double(v) => self.fixed(f64::from(*v)),
+            DbusTypeContainer::UnixFd(v) => self.fixed(u32::from(*v)),
+            DbusTypeContainer::Signature(v) => self.signature(v),
+            DbusTypeContainer::String(v) => self.str(v),
+            DbusTypeContainer::ObjectPath(v) => self.str(v),
+            DbusTypeContainer::Variant(v) => {
+                self.signature(v.signature());
+                self.value(&v.inner);
+            }
+            DbusTypeContainer::Array(array) => {
+                let alignment = array.element_signature()[0].alignment();
+                self.array(alignment, |m| array.iter().for_each(|v| m.value(v)));
+            }
+            DbusTypeContainer::Struct(structure) => {
+                self.align(8);
+                structure.iter().for_each(|v| self.value(v));
+            }
+            DbusTypeContainer::Dict(dict) => self.array(8, |m| {
+                dict.iter().for_each(|entry| {
+                    m.align(8);
+                    m.value(entry.key());
+                    m.value(entry.value());
+                })
+            }),
+            DbusTypeContainer::ByteArray(v) => self.fixed_array(v),
+            DbusTypeContainer::Uint16Array(v) => self.fixed_array(v),
+            DbusTypeContainer::Int16Array(v) => self.fixed_array(v),
+            DbusTypeContainer::Uint32Array(v) => self.fixed_array(v),
+            DbusTypeContainer::Int32Array(v) => self.fixed_array(v),
+            DbusTypeContainer::Uint64Array(v) => self.fixed_array(v),
+            DbusTypeContainer::Int64Array(v) => self.fixed_array(v),
+            DbusTypeContainer::DoubleArray(v) => self.fixed_array(v),
+        }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+}
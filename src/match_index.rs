@@ -0,0 +1,244 @@
+use crate::header::components::MessageType;
+use crate::{MatchRule, Message};
+use std::collections::HashMap;
+
+/// Handle of a rule in a [`MatchIndex`], valid until the rule is removed
+///
+/// Slots of removed rules are reused, but under a new generation, so a
+/// stale handle never reaches the rule inserted after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuleId {
+    index: usize,
+    generation: u64,
+}
+
+/// The header field a rule is filed under
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Bucket {
+    Member(String),
+    Path(String),
+    Interface(String),
+    Sender(String),
+    Type(MessageType),
+    /// Rules testing none of the indexed fields, evaluated for every message
+    Any,
+}
+
+impl Bucket {
+    /// Files a rule under its most selective field
+    fn of(rule: &MatchRule) -> Self {
+        if let Some(member) = &rule.member {
+            Bucket::Member(member.clone())
+        } else if let Some(path) = &rule.path {
+            Bucket::Path(path.clone())
+        } else if let Some(interface) = &rule.interface {
+            Bucket::Interface(interface.clone())
+        } else if let Some(sender) = &rule.sender {
+            Bucket::Sender(sender.clone())
+        } else if let Some(message_type) = rule.message_type {
+            Bucket::Type(message_type)
+        } else {
+            Bucket::Any
+        }
+    }
+}
+
+/// Rules by the value of the field they are filed under
+#[derive(Debug, Clone, Default)]
+struct Buckets {
+    member: HashMap<String, Vec<RuleId>>,
+    path: HashMap<String, Vec<RuleId>>,
+    interface: HashMap<String, Vec<RuleId>>,
+    sender: HashMap<String, Vec<RuleId>>,
+    message_type: HashMap<MessageType, Vec<RuleId>>,
+    any: Vec<RuleId>,
+}
+
+impl Buckets {
+    fn insert(&mut self, bucket: &Bucket, id: RuleId) {
+        match bucket {
+            Bucket::Member(v) => self.member.entry(v.clone()).or_default().push(id),
+            Bucket::Path(v) => self.path.entry(v.clone()).or_default().push(id),
+            Bucket::Interface(v) => self.interface.entry(v.clone()).or_default().push(id),
+            Bucket::Sender(v) => self.sender.entry(v.clone()).or_default().push(id),
+            Bucket::Type(v) => self.message_type.entry(*v).or_default().push(id),
+            Bucket::Any => self.any.push(id),
+        }
+    }
+
+    fn remove(&mut self, bucket: &Bucket, id: RuleId) {
+        fn remove_from<K: std::hash::Hash + Eq>(
+            map: &mut HashMap<K, Vec<RuleId>>,
+            key: &K,
+            id: RuleId,
+        ) {
+            if let Some(ids) = map.get_mut(key) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    map.remove(key);
+                }
+            }
+        }
+
+        match bucket {
+            Bucket::Member(v) => remove_from(&mut self.member, v, id),
+            Bucket::Path(v) => remove_from(&mut self.path, v, id),
+            Bucket::Interface(v) => remove_from(&mut self.interface, v, id),
+            Bucket::Sender(v) => remove_from(&mut self.sender, v, id),
+            Bucket::Type(v) => remove_from(&mut self.message_type, v, id),
+            Bucket::Any => self.any.retain(|other| *other != id),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    rule: MatchRule,
+    value: T,
+    bucket: Bucket,
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u64,
+    entry: Option<Entry<T>>,
+}
+
+/// Match rules indexed by the header fields they test
+///
+/// Each rule is filed under a single field, the member when it has one,
+/// then the path, interface, sender and message type. A message only has
+/// the rules filed under its own fields evaluated, along with the rules
+/// testing none of them, instead of every rule in the index.
+#[derive(Debug, Clone)]
+pub struct MatchIndex<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    buckets: Buckets,
+    len: usize,
+}
+
+impl<T> Default for MatchIndex<T> {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            buckets: Buckets::default(),
+            len: 0,
+        }
+    }
+}
+
+impl<T> MatchIndex<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds `rule`, reporting matches along with `value`
+    pub fn insert(&mut self, rule: MatchRule, value: T) -> RuleId {
+        let bucket = Bucket::of(&rule);
+        let entry = Entry {
+            rule,
+            value,
+            bucket: bucket.clone(),
+        };
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.entry = Some(entry);
+                RuleId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: Some(entry),
+                });
+                RuleId {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        self.buckets.insert(&bucket, id);
+        self.len += 1;
+        id
+    }
+
+    /// Takes a rule out of the index, along with its value
+    pub fn remove(&mut self, id: RuleId) -> Option<(MatchRule, T)> {
+        let slot = self
+            .slots
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)?;
+        let entry = slot.entry.take()?;
+        slot.generation += 1;
+        self.buckets.remove(&entry.bucket, id);
+
+        self.free.push(id.index);
+        self.len -= 1;
+        Some((entry.rule, entry.value))
+    }
+
+    pub fn get(&self, id: RuleId) -> Option<(&MatchRule, &T)> {
+        self.entry(id).map(|entry| (&entry.rule, &entry.value))
+    }
+
+    /// Every rule with its value, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (RuleId, &MatchRule, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = RuleId {
+                index,
+                generation: slot.generation,
+            };
+            slot.entry
+                .as_ref()
+                .map(|entry| (id, &entry.rule, &entry.value))
+        })
+    }
+
+    /// The rules matching `message`, in no particular order
+    pub fn matches<'a>(&'a self, message: &'a Message) -> impl Iterator<Item = (RuleId, &'a T)> {
+        let header = &message.header;
+        let buckets = &self.buckets;
+        let field = |map: &'a HashMap<String, Vec<RuleId>>, value: Option<&str>| {
+            value.and_then(|value| map.get(value))
+        };
+        let candidates = [
+            field(&buckets.member, header.member()),
+            field(&buckets.path, header.path()),
+            field(&buckets.interface, header.interface()),
+            field(&buckets.sender, header.sender()),
+            buckets.message_type.get(&header.message_type()),
+            Some(&buckets.any),
+        ];
+
+        // Each rule sits in a single bucket, so none is reported twice
+        IntoIterator::into_iter(candidates)
+            .flatten()
+            .flatten()
+            .filter_map(move |id| {
+                let entry = self.entry(*id)?;
+                entry.rule.matches(message).then_some((*id, &entry.value))
+            })
+    }
+
+    fn entry(&self, id: RuleId) -> Option<&Entry<T>> {
+        self.slots
+            .get(id.index)
+            .filter(|slot| slot.generation == id.generation)?
+            .entry
+            .as_ref()
+    }
+}
@@ -0,0 +1,338 @@
+use crate::header::components::MessageType;
+use crate::types::basic::DbusObjectPath;
+use crate::{DbusTypeContainer, Message};
+use failure_derive::Fail;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Highest argument index a rule may test
+pub const MAX_MATCH_ARG: u8 = 63;
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum MatchRuleError {
+    #[fail(display = "The match rule holds an invalid key/value pair")]
+    MalformedPair,
+    #[fail(display = "The match rule ends inside a quoted value")]
+    UnterminatedQuote,
+    #[fail(display = "The key {} is not a known match rule key", _0)]
+    UnknownKey(String),
+    #[fail(display = "The key {} appears twice in a match rule", _0)]
+    DuplicateKey(String),
+    #[fail(display = "The value of the key {} is invalid", _0)]
+    InvalidValue(String),
+    #[fail(display = "A match rule cannot test both path and path_namespace")]
+    PathAndNamespace,
+}
+
+/// The `type` value of `message_type`, `None` for the invalid type which has none
+fn message_type_name(message_type: MessageType) -> Option<&'static str> {
+    match message_type {
+        MessageType::MethodCall => Some("method_call"),
+        MessageType::MethodReturn => Some("method_return"),
+        MessageType::Error => Some("error"),
+        MessageType::Signal => Some("signal"),
+        MessageType::Invalid => None,
+    }
+}
+
+/// Quotes a value so that it reads back verbatim
+///
+/// Backslashes are literal inside quotes, so apostrophes are written by
+/// closing the quotes, escaping them, and opening the quotes again.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Splits a rule into its raw keys and unquoted values
+fn split_pairs(rule: &str) -> Result<Vec<(&str, String)>, MatchRuleError> {
+    let mut pairs = vec![];
+    let mut rest = rule.trim_start();
+    while !rest.is_empty() {
+        let eq = rest.find('=').ok_or(MatchRuleError::MalformedPair)?;
+        let key = rest[..eq].trim();
+        if key.is_empty() || key.contains(',') {
+            return Err(MatchRuleError::MalformedPair);
+        }
+
+        let mut value = String::new();
+        let mut quoted = false;
+        let mut chars = rest[eq + 1..].char_indices();
+        let mut end = None;
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\'' => quoted = !quoted,
+                ',' if !quoted => {
+                    end = Some(eq + 1 + i + 1);
+                    break;
+                }
+                '\\' if !quoted && rest[eq + 1 + i + 1..].starts_with('\'') => {
+                    chars.next();
+                    value.push('\'');
+                }
+                c => value.push(c),
+            }
+        }
+
+        if quoted {
+            return Err(MatchRuleError::UnterminatedQuote);
+        }
+
+        pairs.push((key, value));
+        rest = end.map_or("", |end| rest[end..].trim_start());
+    }
+
+    Ok(pairs)
+}
+
+/// Checks a value given to `path` or `path_namespace`
+fn object_path(key: &str, value: String) -> Result<String, MatchRuleError> {
+    DbusObjectPath::try_from(value.as_str())
+        .map(|_| value)
+        .map_err(|_| MatchRuleError::InvalidValue(key.to_string()))
+}
+
+/// Whether `path` is `namespace` or one of its descendants
+fn in_path_namespace(path: &str, namespace: &str) -> bool {
+    namespace == "/"
+        || path
+            .strip_prefix(namespace)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The `argNpath` comparison: equal, or one a prefix of the other ending in a slash
+fn arg_path_matches(arg: &str, rule: &str) -> bool {
+    arg == rule
+        || (rule.ends_with('/') && arg.starts_with(rule))
+        || (arg.ends_with('/') && rule.starts_with(arg))
+}
+
+/// Whether `name` is `namespace` or a name below it, as in `arg0namespace`
+fn in_name_namespace(name: &str, namespace: &str) -> bool {
+    name.strip_prefix(namespace)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// A rule selecting messages, as given to `AddMatch`
+///
+/// Unset keys match anything. `sender` is compared literally: resolving a
+/// well-known name to its owner is up to the bus.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MatchRule {
+    /// `MessageType::Invalid` has no name in a rule, so it matches anything like `None`
+    pub message_type: Option<MessageType>,
+    pub sender: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub path: Option<String>,
+    /// Matches this object path and every path below it
+    pub path_namespace: Option<String>,
+    pub destination: Option<String>,
+    /// `argN` keys: string argument `N` equals the value
+    pub args: BTreeMap<u8, String>,
+    /// `argNpath` keys: string or object path argument `N` is the value,
+    /// or one of them is a prefix of the other that ends in a slash
+    pub arg_paths: BTreeMap<u8, String>,
+    /// String argument 0 is this bus name or a name below it
+    pub arg0_namespace: Option<String>,
+    pub eavesdrop: Option<bool>,
+}
+
+impl MatchRule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `message` satisfies every key of the rule
+    pub fn matches(&self, message: &Message) -> bool {
+        let header = &message.header;
+        let field = |rule: &Option<String>, value: Option<&str>| {
+            rule.as_deref().is_none_or(|rule| value == Some(rule))
+        };
+
+        if self
+            .message_type
+            .is_some_and(|t| t != MessageType::Invalid && t != header.message_type())
+        {
+            return false;
+        }
+
+        if !field(&self.sender, header.sender())
+            || !field(&self.interface, header.interface())
+            || !field(&self.member, header.member())
+            || !field(&self.path, header.path())
+            || !field(&self.destination, header.destination())
+        {
+            return false;
+        }
+
+        if let Some(namespace) = &self.path_namespace {
+            if !header
+                .path()
+                .is_some_and(|path| in_path_namespace(path, namespace))
+            {
+                return false;
+            }
+        }
+
+        let string_arg = |n: u8| match message.message.get(n as usize) {
+            Some(DbusTypeContainer::String(s)) => Some(&**s),
+            _ => None,
+        };
+        let path_arg = |n: u8| match message.message.get(n as usize) {
+            Some(DbusTypeContainer::String(s)) => Some(&**s),
+            Some(DbusTypeContainer::ObjectPath(p)) => Some(&**p),
+            _ => None,
+        };
+
+        self.args
+            .iter()
+            .all(|(n, value)| string_arg(*n) == Some(value.as_str()))
+            && self
+                .arg_paths
+                .iter()
+                .all(|(n, value)| path_arg(*n).is_some_and(|arg| arg_path_matches(arg, value)))
+            && self.arg0_namespace.as_deref().is_none_or(|namespace| {
+                string_arg(0).is_some_and(|arg| in_name_namespace(arg, namespace))
+            })
+    }
+
+    fn set(&mut self, key: &str, value: String) -> Result<(), MatchRuleError> {
+        let invalid = || MatchRuleError::InvalidValue(key.to_string());
+        let slot = match key {
+            "type" => {
+                if self.message_type.is_some() {
+                    return Err(MatchRuleError::DuplicateKey(key.to_string()));
+                }
+
+                self.message_type = Some(match value.as_str() {
+                    "signal" => MessageType::Signal,
+                    "method_call" => MessageType::MethodCall,
+                    "method_return" => MessageType::MethodReturn,
+                    "error" => MessageType::Error,
+                    _ => return Err(invalid()),
+                });
+                return Ok(());
+            }
+            "eavesdrop" => {
+                if self.eavesdrop.is_some() {
+                    return Err(MatchRuleError::DuplicateKey(key.to_string()));
+                }
+
+                self.eavesdrop = Some(match value.as_str() {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(invalid()),
+                });
+                return Ok(());
+            }
+            "sender" => &mut self.sender,
+            "interface" => &mut self.interface,
+            "member" => &mut self.member,
+            "path" => &mut self.path,
+            "path_namespace" => &mut self.path_namespace,
+            "destination" => &mut self.destination,
+            "arg0namespace" => &mut self.arg0_namespace,
+            _ => return self.set_arg(key, value),
+        };
+
+        if slot.is_some() {
+            return Err(MatchRuleError::DuplicateKey(key.to_string()));
+        }
+
+        *slot = Some(match key {
+            "path" | "path_namespace" => object_path(key, value)?,
+            _ => value,
+        });
+        Ok(())
+    }
+
+    /// Handles the `argN` and `argNpath` keys
+    fn set_arg(&mut self, key: &str, value: String) -> Result<(), MatchRuleError> {
+        let unknown = || MatchRuleError::UnknownKey(key.to_string());
+        let index = key.strip_prefix("arg").ok_or_else(unknown)?;
+        let (index, args) = match index.strip_suffix("path") {
+            Some(index) => (index, &mut self.arg_paths),
+            None => (index, &mut self.args),
+        };
+
+        // Indices are plain decimal, without sign or leading zeros
+        let n = match index.parse::<u8>() {
+            Ok(n) if n <= MAX_MATCH_ARG && index == n.to_string() => n,
+            _ => return Err(unknown()),
+        };
+        if args.insert(n, value).is_some() {
+            return Err(MatchRuleError::DuplicateKey(key.to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for MatchRule {
+    type Err = MatchRuleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rule = MatchRule::new();
+        for (key, value) in split_pairs(s)? {
+            rule.set(key, value)?;
+        }
+
+        if rule.path.is_some() && rule.path_namespace.is_some() {
+            return Err(MatchRuleError::PathAndNamespace);
+        }
+
+        Ok(rule)
+    }
+}
+
+impl std::fmt::Display for MatchRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut pairs = vec![];
+        if let Some(name) = self.message_type.and_then(message_type_name) {
+            pairs.push(("type".to_string(), name));
+        }
+
+        let fields = [
+            ("sender", &self.sender),
+            ("interface", &self.interface),
+            ("member", &self.member),
+            ("path", &self.path),
+            ("path_namespace", &self.path_namespace),
+            ("destination", &self.destination),
+        ];
+        for (key, value) in fields.iter() {
+            if let Some(value) = value {
+                pairs.push((key.to_string(), value));
+            }
+        }
+
+        for (n, value) in self.args.iter() {
+            pairs.push((format!("arg{}", n), value));
+        }
+
+        for (n, value) in self.arg_paths.iter() {
+            pairs.push((format!("arg{}path", n), value));
+        }
+
+        if let Some(namespace) = &self.arg0_namespace {
+            pairs.push(("arg0namespace".to_string(), namespace));
+        }
+
+        if let Some(eavesdrop) = self.eavesdrop {
+            pairs.push((
+                "eavesdrop".to_string(),
+                if eavesdrop { "true" } else { "false" },
+            ));
+        }
+
+        for (i, (key, value)) in pairs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+
+            write!(f, "{}={}", key, quote(value))?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,174 @@
-use crate::header::Header;
+use crate::error::DbusParseError;
+use crate::header::components::{MessageEndianness, MessageType};
+use crate::header::{Header, HeaderPeek};
+use crate::marshal::Marshaller;
+use crate::parse_context::{ParseContext, ParseLimits};
+use crate::signature_type::{Signature, SignatureType};
 use crate::type_container::DbusTypeContainer;
+use crate::types::basic::{DbusObjectPath, DbusString, DbusUnixFd};
+use crate::types::borrowed::DbusBorrowedTypeContainer;
+use crate::unix_fds::{visit_unix_fds, UnixFds};
+use nom::IResult;
+use std::cell::OnceCell;
+use std::convert::TryFrom;
+use std::os::unix::io::OwnedFd;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Message {
     pub header: Header,
     pub message: Vec<DbusTypeContainer>,
+    /// Descriptors passed along with the message, which `h` values index into
+    pub fds: UnixFds,
+}
+
+impl Message {
+    fn new(header: Header) -> Self {
+        Self {
+            header,
+            message: vec![],
+            fds: UnixFds::default(),
+        }
+    }
+
+    /// A call of `member` on the object at `path`, which must be a valid object path
+    pub fn method_call(
+        destination: Option<&str>,
+        path: &str,
+        interface: Option<&str>,
+        member: &str,
+    ) -> Result<Self, DbusParseError> {
+        let mut header = Header::new(MessageType::MethodCall);
+        header.fields.destination = destination.map(DbusString::from);
+        header.fields.path = Some(DbusObjectPath::try_from(path)?);
+        header.fields.interface = interface.map(DbusString::from);
+        header.fields.member = Some(member.into());
+        Ok(Self::new(header))
+    }
+
+    /// A signal emitted by the object at `path`, which must be a valid object path
+    pub fn signal(path: &str, interface: &str, member: &str) -> Result<Self, DbusParseError> {
+        let mut header = Header::new(MessageType::Signal);
+        header.fields.path = Some(DbusObjectPath::try_from(path)?);
+        header.fields.interface = Some(interface.into());
+        header.fields.member = Some(member.into());
+        Ok(Self::new(header))
+    }
+
+    /// A successful reply to `call`, addressed to its sender
+    pub fn method_return(call: &Message) -> Self {
+        Self::new(Self::reply_header(call, MessageType::MethodReturn))
+    }
+
+    /// An error reply to `call`, with `text` as the human readable explanation
+    pub fn error(call: &Message, name: &str, text: &str) -> Self {
+        let mut header = Self::reply_header(call, MessageType::Error);
+        header.fields.error_name = Some(name.into());
+        Self::new(header).with_body(vec![DbusTypeContainer::String(text.into())])
+    }
+
+    fn reply_header(call: &Message, message_type: MessageType) -> Header {
+        let mut header = Header::new(message_type);
+        header.fields.reply_serial = Some(call.header.serial().into());
+        header.fields.destination = call.header.fields.sender.clone();
+        header
+    }
+
+    /// Replaces the body, keeping the signature header field in sync
+    pub fn with_body(mut self, body: Vec<DbusTypeContainer>) -> Self {
+        self.message = body;
+        let signature = self.body_signature();
+        self.header.fields.signature = if signature.is_empty() {
+            None
+        } else {
+            Some(signature)
+        };
+        self
+    }
+
+    /// Attaches file descriptors, keeping the unix fds header field in sync
+    pub fn with_fds(mut self, fds: Vec<OwnedFd>) -> Self {
+        self.fds = UnixFds::new(fds);
+        self.header.fields.unix_fds = match self.fds.len() {
+            0 => None,
+            n => Some((n as u32).into()),
+        };
+        self
+    }
+
+    /// Resolves an `h` value of the body to the descriptor it stands for
+    pub fn fd(&self, index: DbusUnixFd) -> Result<&OwnedFd, DbusParseError> {
+        self.fds.get(index).ok_or(DbusParseError::InvalidUnixFd)
+    }
+
+    /// Checks every `h` value of the body against the unix fds header field
+    pub fn check_unix_fds(&self) -> Result<(), DbusParseError> {
+        let count = self.header.fields.unix_fds.map_or(0, u32::from);
+        let mut valid = true;
+        for value in self.message.iter() {
+            visit_unix_fds(value, &mut |fd| valid &= u32::from(fd) < count);
+        }
+
+        if valid {
+            Ok(())
+        } else {
+            Err(DbusParseError::InvalidUnixFd)
+        }
+    }
+
+    /// Decodes a whole message, header and body
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        Self::parse_with_limits(buf, ParseLimits::default())
+    }
+
+    /// Decodes a whole message from untrusted input, within `limits`
+    pub fn parse_with_limits(buf: &[u8], limits: ParseLimits) -> IResult<&[u8], Self> {
+        let (buf, header) = Header::parse_with_limits(buf, limits)?;
+        header.parse_message_with_limits(buf, limits)
+    }
+
+    /// Finds where argument `n` of the encoded message in `buf` starts
+    ///
+    /// The offset is relative to the start of the message and points past the
+    /// padding in front of the argument. The preceding arguments are skipped
+    /// without being decoded.
+    pub fn arg_offset(buf: &[u8], n: usize) -> IResult<&[u8], usize> {
+        let (rest, header) = Header::parse(buf)?;
+        let body_offset = buf.len() - rest.len();
+        let (_, body) = header.take_body(rest)?;
+        let ctx = ParseContext::new(header.endianness(), body);
+        let (arg, _) = header.body_signature().skip_to(body, &ctx, n)?;
+
+        Ok((arg, body_offset + ctx.offset(arg)))
+    }
+
+    /// Encodes the message in its own byte order
+    pub fn marshal(&self) -> Vec<u8> {
+        self.to_endianness(self.header.endianness())
+    }
+
+    /// Encodes the message in the byte order `endianness`
+    pub fn to_endianness(&self, endianness: MessageEndianness) -> Vec<u8> {
+        // The header is padded to 8 bytes, so the body aligns the same on its own
+        let mut body = Marshaller::new(endianness);
+        self.message.iter().for_each(|value| body.value(value));
+        let body = body.into_inner();
+
+        let mut ret = self
+            .header
+            .marshal(endianness, &self.body_signature(), body.len());
+        ret.extend_from_slice(&body);
+        ret
+    }
+
+    /// Computes the signature of the message body from its values
+    pub fn body_signature(&self) -> Signature {
+        self.message
+            .iter()
+            .fold(Signature::default(), |mut signature, value| {
+                signature.extend_from_slice(&value.signature());
+                signature
+            })
+    }
 }
 
 impl std::ops::Deref for Message {
@@ -19,3 +183,213 @@ impl std::ops::DerefMut for Message {
         &mut self.message
     }
 }
+
+/// Converts an encoded message to the other byte order, without decoding its values
+///
+/// Every number, length and header field is byte-swapped in a copy of the
+/// message, as the layout of the values does not depend on the byte order.
+pub fn swap_endianness(message: &[u8]) -> Result<Vec<u8>, DbusParseError> {
+    let (_, peek) = HeaderPeek::from_bytes(message).map_err(|_| DbusParseError::MalformedHeader)?;
+    let message = message
+        .get(..peek.message_len())
+        .ok_or(DbusParseError::MalformedHeader)?;
+    let body_signature: Signature = peek.signature.unwrap_or_default().parse()?;
+
+    let mut swapped = message.to_vec();
+    swapped[0] = match peek.fixed.endianness {
+        MessageEndianness::BigEndian => MessageEndianness::LittleEndian,
+        MessageEndianness::LittleEndian => MessageEndianness::BigEndian,
+    } as u8;
+    let mut swap = |offset: usize, size: usize| swapped[offset..offset + size].reverse();
+
+    // Body length and serial
+    swap(4, 4);
+    swap(8, 4);
+
+    let ctx = ParseContext::new(peek.fixed.endianness, message);
+    let fields = Signature::new(vec![
+        SignatureType::Array,
+        SignatureType::StructStart,
+        SignatureType::Byte,
+        SignatureType::Variant,
+        SignatureType::StructEnd,
+    ]);
+    fields
+        .walk(&message[12..], &ctx, &mut swap)
+        .map_err(|_| DbusParseError::MalformedHeader)?;
+    let (rest, _) = body_signature
+        .walk(&message[peek.header_len..], &ctx, &mut swap)
+        .map_err(|_| DbusParseError::InvalidBody)?;
+    if !rest.is_empty() {
+        return Err(DbusParseError::InvalidBody);
+    }
+
+    Ok(swapped)
+}
+
+/// A message whose body borrows strings and byte arrays from the decoded buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct BorrowedMessage<'a> {
+    pub header: Header,
+    pub message: Vec<DbusBorrowedTypeContainer<'a>>,
+}
+
+impl<'a> BorrowedMessage<'a> {
+    /// Decodes a whole message, header and body
+    pub fn parse(buf: &'a [u8]) -> IResult<&'a [u8], Self> {
+        let (buf, header) = Header::parse(buf)?;
+        header.parse_message_borrowed(buf)
+    }
+
+    /// Copies the body out of the decoded buffer
+    pub fn into_owned(self) -> Message {
+        Message {
+            header: self.header,
+            message: self
+                .message
+                .into_iter()
+                .map(DbusBorrowedTypeContainer::into_owned)
+                .collect(),
+            fds: UnixFds::default(),
+        }
+    }
+}
+
+impl<'a> std::ops::Deref for BorrowedMessage<'a> {
+    type Target = Vec<DbusBorrowedTypeContainer<'a>>;
+    fn deref(&self) -> &Self::Target {
+        &self.message
+    }
+}
+
+/// A message whose body is kept encoded until its arguments are asked for
+///
+/// Decoded arguments are cached, so each one is decoded at most once.
+#[derive(Debug, Clone)]
+pub struct LazyMessage<'a> {
+    pub header: Header,
+    raw: &'a [u8],
+    body_offset: usize,
+    signatures: Vec<Signature>,
+    /// Start of each argument in the body, and the end of the last one
+    offsets: Vec<OnceCell<usize>>,
+    args: Vec<OnceCell<DbusTypeContainer>>,
+}
+
+impl<'a> LazyMessage<'a> {
+    /// Decodes the header only, keeping the body as raw bytes
+    pub fn parse(buf: &'a [u8]) -> IResult<&'a [u8], Self> {
+        let (rest, header) = Header::parse(buf)?;
+        let body_offset = buf.len() - rest.len();
+        let (rest, _) = header.take_body(rest)?;
+        let signatures = header
+            .body_signature()
+            .split_complete_types()
+            .map_err(|_| nom::Err::Failure((rest, nom::error::ErrorKind::Verify)))?;
+
+        Ok((
+            rest,
+            Self {
+                header,
+                raw: &buf[..buf.len() - rest.len()],
+                body_offset,
+                offsets: std::iter::once(OnceCell::from(0))
+                    .chain(signatures.iter().map(|_| OnceCell::new()))
+                    .collect(),
+                args: signatures.iter().map(|_| OnceCell::new()).collect(),
+                signatures,
+            },
+        ))
+    }
+
+    /// The whole encoded message, for forwarding it untouched
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// The encoded body
+    pub fn body(&self) -> &'a [u8] {
+        &self.raw[self.body_offset..]
+    }
+
+    /// Number of arguments in the body
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// Signature of argument `n`
+    pub fn arg_signature(&self, n: usize) -> Option<&Signature> {
+        self.signatures.get(n)
+    }
+
+    fn context(&self) -> ParseContext {
+        ParseContext::new(self.header.endianness(), self.body())
+    }
+
+    /// Offset in the body where argument `n` starts, skipping the ones before it
+    fn offset(&self, n: usize) -> Result<usize, DbusParseError> {
+        // Resume from the closest argument whose offset is already known
+        let (mut known, mut offset) = (0..=n)
+            .rev()
+            .find_map(|i| self.offsets[i].get().map(|offset| (i, *offset)))
+            .unwrap_or((0, 0));
+        let body = self.body();
+        let ctx = self.context();
+        while known < n {
+            let (rest, _) = self.signatures[known]
+                .skip(&body[offset..], &ctx)
+                .map_err(|_| DbusParseError::InvalidBody)?;
+            offset = body.len() - rest.len();
+            known += 1;
+            let _ = self.offsets[known].set(offset);
+        }
+
+        Ok(offset)
+    }
+
+    /// Decodes argument `n`, or returns it from the cache
+    pub fn arg(&self, n: usize) -> Result<&DbusTypeContainer, DbusParseError> {
+        if n >= self.len() {
+            return Err(DbusParseError::ArgumentOutOfRange);
+        }
+
+        if let Some(arg) = self.args[n].get() {
+            return Ok(arg);
+        }
+
+        let body = self.body();
+        let offset = self.offset(n)?;
+        let (rest, arg) = self.signatures[n]
+            .parse_complete_type(&body[offset..], &self.context())
+            .map_err(|_| DbusParseError::InvalidBody)?;
+        let _ = self.offsets[n + 1].set(body.len() - rest.len());
+
+        Ok(self.args[n].get_or_init(|| arg))
+    }
+
+    /// Decodes the arguments not decoded yet, and checks the body holds nothing else
+    pub fn into_message(self) -> Result<Message, DbusParseError> {
+        for n in 0..self.len() {
+            self.arg(n)?;
+        }
+
+        // The body must hold exactly the values of its signature
+        if self.offset(self.len())? != self.body().len() {
+            return Err(DbusParseError::InvalidBody);
+        }
+
+        Ok(Message {
+            header: self.header,
+            message: self
+                .args
+                .into_iter()
+                .map(|arg| arg.into_inner().expect("every argument was decoded"))
+                .collect(),
+            fds: UnixFds::default(),
+        })
+    }
+}
@@ -0,0 +1,295 @@
+use crate::connection::{BUS_NAME, BUS_PATH};
+use crate::{DbusTypeContainer, Message};
+use bitflags::bitflags;
+use failure_derive::Fail;
+use std::collections::{HashMap, VecDeque};
+
+bitflags! {
+    /// Flags of `RequestName`
+    #[derive(Default)]
+    pub struct RequestNameFlags: u32 {
+        /// Lets another client take the name over with `REPLACE_EXISTING`
+        const ALLOW_REPLACEMENT = 1;
+        /// Takes the name over when the owner allows it
+        const REPLACE_EXISTING = 2;
+        /// Gives up instead of waiting in the queue, when requesting or when replaced
+        const DO_NOT_QUEUE = 4;
+    }
+}
+
+impl From<u32> for RequestNameFlags {
+    fn from(value: u32) -> Self {
+        RequestNameFlags::from_bits_truncate(value)
+    }
+}
+
+/// Outcome of `RequestName`, as sent in its reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum RequestNameReply {
+    PrimaryOwner = 1,
+    InQueue = 2,
+    Exists = 3,
+    AlreadyOwner = 4,
+}
+
+/// Outcome of `ReleaseName`, as sent in its reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum ReleaseNameReply {
+    Released = 1,
+    NonExistent = 2,
+    NotOwner = 3,
+}
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum NameError {
+    #[fail(display = "{} is not a valid well-known bus name", _0)]
+    InvalidName(String),
+    #[fail(display = "The name {} belongs to the bus itself", _0)]
+    Reserved(String),
+}
+
+/// Whether `name` is a valid well-known bus name, such as `com.example.App`
+///
+/// Unique names, which start with a colon, are assigned by the bus and are
+/// not well-known names.
+pub fn is_valid_well_known_name(name: &str) -> bool {
+    name.len() <= 255
+        && name.split('.').count() >= 2
+        && name.split('.').all(|element| {
+            !element.is_empty()
+                && !element.starts_with(|c: char| c.is_ascii_digit())
+                && element
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+        })
+}
+
+fn signal(member: &str, body: Vec<&str>) -> Message {
+    Message::signal(BUS_PATH, BUS_NAME, member)
+        .expect("the bus path is valid")
+        .with_body(
+            body.into_iter()
+                .map(|arg| DbusTypeContainer::String(arg.into()))
+                .collect(),
+        )
+}
+
+/// `NameOwnerChanged`, for every client with a matching rule
+pub(crate) fn name_owner_changed(name: &str, old: Option<&str>, new: Option<&str>) -> Message {
+    signal(
+        "NameOwnerChanged",
+        vec![name, old.unwrap_or_default(), new.unwrap_or_default()],
+    )
+}
+
+/// `NameAcquired` or `NameLost`, for the client concerned only
+pub(crate) fn name_signal(member: &str, name: &str, client: &str) -> Message {
+    let mut message = signal(member, vec![name]);
+    message.header.fields.destination = Some(client.into());
+    message
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Owner {
+    unique_name: String,
+    flags: RequestNameFlags,
+}
+
+/// Owners of the well-known names of a bus, with the clients queued for them
+///
+/// Requests and releases return the signals the bus has to send along with
+/// the reply: `NameOwnerChanged` without a destination, and `NameLost` and
+/// `NameAcquired` addressed to the client concerned. Their sender and serial
+/// are left for the bus to fill in.
+#[derive(Debug, Clone, Default)]
+pub struct NameRegistry {
+    /// Clients by name, the primary owner first
+    names: HashMap<String, VecDeque<Owner>>,
+}
+
+impl NameRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The unique name of the primary owner of `name`
+    pub fn owner(&self, name: &str) -> Option<&str> {
+        self.names
+            .get(name)
+            .and_then(|queue| queue.front())
+            .map(|owner| owner.unique_name.as_str())
+    }
+
+    /// Every owned name, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.keys().map(String::as_str)
+    }
+
+    /// The names `unique_name` is the primary owner of, in no particular order
+    pub fn names_owned_by<'a>(&'a self, unique_name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.names
+            .iter()
+            .filter(move |(_, queue)| queue[0].unique_name == unique_name)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The primary owner of `name` followed by the clients queued for it
+    pub fn queued_owners(&self, name: &str) -> Vec<&str> {
+        self.names.get(name).map_or(vec![], |queue| {
+            queue
+                .iter()
+                .map(|owner| owner.unique_name.as_str())
+                .collect()
+        })
+    }
+
+    /// Handles `RequestName` from the client `unique_name`
+    pub fn request_name(
+        &mut self,
+        name: &str,
+        unique_name: &str,
+        flags: RequestNameFlags,
+    ) -> Result<(RequestNameReply, Vec<Message>), NameError> {
+        if name == BUS_NAME {
+            return Err(NameError::Reserved(name.to_string()));
+        }
+
+        if !is_valid_well_known_name(name) {
+            return Err(NameError::InvalidName(name.to_string()));
+        }
+
+        let requester = Owner {
+            unique_name: unique_name.to_string(),
+            flags,
+        };
+        let queue = match self.names.get_mut(name) {
+            Some(queue) => queue,
+            None => {
+                self.names.insert(name.to_string(), vec![requester].into());
+                let signals = vec![
+                    name_owner_changed(name, None, Some(unique_name)),
+                    name_signal("NameAcquired", name, unique_name),
+                ];
+                return Ok((RequestNameReply::PrimaryOwner, signals));
+            }
+        };
+
+        if queue[0].unique_name == unique_name {
+            queue[0].flags = flags;
+            return Ok((RequestNameReply::AlreadyOwner, vec![]));
+        }
+
+        let queued = queue
+            .iter()
+            .position(|owner| owner.unique_name == unique_name);
+        let replaces = flags.contains(RequestNameFlags::REPLACE_EXISTING)
+            && queue[0].flags.contains(RequestNameFlags::ALLOW_REPLACEMENT);
+
+        if !replaces {
+            if flags.contains(RequestNameFlags::DO_NOT_QUEUE) {
+                // A client queued by an earlier request leaves the queue
+                if let Some(i) = queued {
+                    queue.remove(i);
+                }
+
+                return Ok((RequestNameReply::Exists, vec![]));
+            }
+
+            match queued {
+                Some(i) => queue[i].flags = flags,
+                None => queue.push_back(requester),
+            }
+            return Ok((RequestNameReply::InQueue, vec![]));
+        }
+
+        if let Some(i) = queued {
+            queue.remove(i);
+        }
+
+        // The previous owner waits first in line, unless it asked not to be queued
+        let previous = queue.pop_front().expect("owned names have an owner");
+        let signals = vec![
+            name_signal("NameLost", name, &previous.unique_name),
+            name_owner_changed(name, Some(&previous.unique_name), Some(unique_name)),
+            name_signal("NameAcquired", name, unique_name),
+        ];
+        if !previous.flags.contains(RequestNameFlags::DO_NOT_QUEUE) {
+            queue.push_front(previous);
+        }
+        queue.push_front(requester);
+
+        Ok((RequestNameReply::PrimaryOwner, signals))
+    }
+
+    /// Handles `ReleaseName` from the client `unique_name`
+    ///
+    /// When the primary owner leaves, the next client in the queue gets the name.
+    pub fn release_name(
+        &mut self,
+        name: &str,
+        unique_name: &str,
+    ) -> Result<(ReleaseNameReply, Vec<Message>), NameError> {
+        if name == BUS_NAME {
+            return Err(NameError::Reserved(name.to_string()));
+        }
+
+        if !is_valid_well_known_name(name) {
+            return Err(NameError::InvalidName(name.to_string()));
+        }
+
+        let queue = match self.names.get_mut(name) {
+            Some(queue) => queue,
+            None => return Ok((ReleaseNameReply::NonExistent, vec![])),
+        };
+
+        let i = match queue
+            .iter()
+            .position(|owner| owner.unique_name == unique_name)
+        {
+            Some(i) => i,
+            None => return Ok((ReleaseNameReply::NotOwner, vec![])),
+        };
+
+        queue.remove(i);
+        if i > 0 {
+            // Leaving the queue goes unnoticed
+            return Ok((ReleaseNameReply::Released, vec![]));
+        }
+
+        let next = queue.front().map(|owner| owner.unique_name.clone());
+        let mut signals = vec![
+            name_signal("NameLost", name, unique_name),
+            name_owner_changed(name, Some(unique_name), next.as_deref()),
+        ];
+        match next {
+            Some(next) => signals.push(name_signal("NameAcquired", name, &next)),
+            None => {
+                self.names.remove(name);
+            }
+        }
+
+        Ok((ReleaseNameReply::Released, signals))
+    }
+
+    /// Releases every name `unique_name` owns or waits for, as when it disconnects
+    pub fn release_all(&mut self, unique_name: &str) -> Vec<Message> {
+        let mut names: Vec<String> = self
+            .names
+            .iter()
+            .filter(|(_, queue)| queue.iter().any(|owner| owner.unique_name == unique_name))
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+
+        names
+            .iter()
+            .flat_map(|name| {
+                self.release_name(name, unique_name)
+                    .map(|(_, signals)| signals)
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
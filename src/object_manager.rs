@@ -0,0 +1,316 @@
+use crate::header::components::MessageType;
+use crate::properties::{property_dict, property_values, PROPERTIES_INTERFACE};
+use crate::signature_type::{Signature, SignatureType};
+use crate::types::basic::DbusObjectPath;
+use crate::types::containers::{DbusArray, DbusDict, DbusDictEntry, DbusVariant};
+use crate::{
+    error_name, DbusParseError, DbusTypeContainer, Interface, MatchRule, Message, MethodError,
+};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+pub const OBJECT_MANAGER_INTERFACE: &str = "org.freedesktop.DBus.ObjectManager";
+
+/// Whether the object at `path` is managed by an object manager at `manager`
+///
+/// A manager manages the objects below it, but not itself.
+pub(crate) fn is_managed_by(path: &str, manager: &str) -> bool {
+    path != manager
+        && path
+            .strip_prefix(manager)
+            .is_some_and(|rest| manager == "/" || rest.starts_with('/'))
+}
+
+fn string(value: &str) -> DbusTypeContainer {
+    DbusTypeContainer::String(value.into())
+}
+
+fn object_path(path: &str) -> DbusTypeContainer {
+    DbusTypeContainer::ObjectPath(DbusObjectPath::try_from(path).expect("exported paths are valid"))
+}
+
+fn signature(s: &str) -> Signature {
+    s.parse().expect("the signature is valid")
+}
+
+/// The `a{sa{sv}}` dictionary of the interfaces of an object, with their properties
+///
+/// Properties that fail to be read are left out unless `strict`, where the
+/// first failure is returned instead.
+fn interface_dict<'a, I>(interfaces: I, strict: bool) -> Result<DbusTypeContainer, MethodError>
+where
+    I: IntoIterator<Item = &'a mut Interface>,
+{
+    let mut entries = vec![];
+    for interface in interfaces {
+        let mut values = vec![];
+        for (name, value) in property_values(interface) {
+            match value {
+                Ok(value) => values.push((name, value)),
+                Err(e) if strict => return Err(e),
+                Err(_) => {}
+            }
+        }
+
+        let values = property_dict(values);
+        entries.push(DbusDictEntry::new(string(&interface.name), values));
+    }
+
+    let dict = DbusDict::new(SignatureType::String.into(), signature("a{sv}"), entries)
+        .expect("entries are strings to property dicts");
+    Ok(DbusTypeContainer::Dict(dict))
+}
+
+/// `InterfacesAdded`, sent by the object manager at `manager` for `interfaces` of the object at `path`
+pub(crate) fn interfaces_added<'a, I>(manager: &str, path: &str, interfaces: I) -> Option<Message>
+where
+    I: IntoIterator<Item = &'a mut Interface>,
+{
+    let interfaces = interface_dict(interfaces, false).ok()?;
+    let signal = Message::signal(manager, OBJECT_MANAGER_INTERFACE, "InterfacesAdded")
+        .ok()?
+        .with_body(vec![object_path(path), interfaces]);
+    Some(signal)
+}
+
+/// `InterfacesRemoved`, sent by the object manager at `manager` for interfaces the object at `path` lost
+pub(crate) fn interfaces_removed(manager: &str, path: &str, names: &[&str]) -> Option<Message> {
+    let names = names.iter().map(|name| string(name)).collect();
+    let names =
+        DbusArray::new(SignatureType::String.into(), names).expect("every element is a string");
+    let signal = Message::signal(manager, OBJECT_MANAGER_INTERFACE, "InterfacesRemoved")
+        .ok()?
+        .with_body(vec![object_path(path), DbusTypeContainer::Array(names)]);
+    Some(signal)
+}
+
+/// Answers a call of `org.freedesktop.DBus.ObjectManager` on the object manager at `manager`
+pub(crate) fn handle(
+    objects: &mut BTreeMap<String, BTreeMap<String, Interface>>,
+    manager: &str,
+    call: &Message,
+) -> Result<Vec<Message>, MethodError> {
+    let member = call.header.member().unwrap_or_default();
+    if member != "GetManagedObjects" {
+        return Err(MethodError::new(
+            error_name::UNKNOWN_METHOD,
+            format!(
+                "No method {} on interface {}",
+                member, OBJECT_MANAGER_INTERFACE
+            ),
+        ));
+    }
+
+    let actual = call.body_signature();
+    if actual != Signature::default() {
+        return Err(MethodError::invalid_args(format!(
+            "{} expects no arguments, not \"{}\"",
+            member, actual
+        )));
+    }
+
+    let mut entries = vec![];
+    for (path, interfaces) in objects.iter_mut() {
+        if is_managed_by(path, manager) {
+            let interfaces = interface_dict(interfaces.values_mut(), true)?;
+            entries.push(DbusDictEntry::new(object_path(path), interfaces));
+        }
+    }
+
+    let dict = DbusDict::new(
+        SignatureType::ObjectPath.into(),
+        signature("a{sa{sv}}"),
+        entries,
+    )
+    .expect("entries are object paths to interface dicts");
+    Ok(vec![
+        Message::method_return(call).with_body(vec![DbusTypeContainer::Dict(dict)])
+    ])
+}
+
+/// Property values by name
+pub type PropertyValues = BTreeMap<String, DbusTypeContainer>;
+
+/// Properties by interface name, as an object manager describes an object
+pub type ObjectInterfaces = BTreeMap<String, PropertyValues>;
+
+fn dict_entries(value: DbusTypeContainer) -> Result<Vec<DbusDictEntry>, DbusParseError> {
+    Ok(DbusDict::try_from(value)?.into_inner())
+}
+
+fn decode_string(value: DbusTypeContainer) -> Result<String, DbusParseError> {
+    match value {
+        DbusTypeContainer::String(s) => Ok(s.into()),
+        DbusTypeContainer::ObjectPath(p) => Ok(p.into()),
+        _ => Err(DbusParseError::InvalidContainerVariantTarget),
+    }
+}
+
+/// Decodes `a{sv}`, unwrapping the values from their variants
+fn decode_properties(value: DbusTypeContainer) -> Result<PropertyValues, DbusParseError> {
+    dict_entries(value)?
+        .into_iter()
+        .map(|DbusDictEntry(name, value)| {
+            let value = DbusVariant::try_from(value)?.into_inner();
+            Ok((decode_string(name)?, value))
+        })
+        .collect()
+}
+
+/// Decodes `a{sa{sv}}`
+fn decode_interfaces(value: DbusTypeContainer) -> Result<ObjectInterfaces, DbusParseError> {
+    dict_entries(value)?
+        .into_iter()
+        .map(|DbusDictEntry(name, properties)| {
+            Ok((decode_string(name)?, decode_properties(properties)?))
+        })
+        .collect()
+}
+
+/// The arguments of `message`, after checking they match `signature`
+fn body(message: &Message, signature: &str) -> Result<Vec<DbusTypeContainer>, DbusParseError> {
+    if message.body_signature().to_string() != signature {
+        return Err(DbusParseError::InvalidBody);
+    }
+
+    Ok(message.message.clone())
+}
+
+/// A local copy of the objects a remote object manager exports
+///
+/// The mirror starts from the reply to `GetManagedObjects`, then follows
+/// `InterfacesAdded`, `InterfacesRemoved` and `PropertiesChanged` given to
+/// [`ManagedObjects::update`]. [`ManagedObjects::match_rule`] selects these
+/// signals, and should be added before calling `GetManagedObjects` so that
+/// no change is missed in between.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ManagedObjects {
+    objects: BTreeMap<String, ObjectInterfaces>,
+}
+
+impl ManagedObjects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `GetManagedObjects` call for the object manager at `path` of `destination`
+    pub fn call(destination: &str, path: &str) -> Result<Message, DbusParseError> {
+        Message::method_call(
+            Some(destination),
+            path,
+            Some(OBJECT_MANAGER_INTERFACE),
+            "GetManagedObjects",
+        )
+    }
+
+    /// Signals of `sender` that [`ManagedObjects::update`] follows for the object manager at `path`
+    pub fn match_rule(sender: &str, path: &str) -> MatchRule {
+        MatchRule {
+            message_type: Some(MessageType::Signal),
+            sender: Some(sender.to_string()),
+            path_namespace: Some(path.to_string()),
+            ..MatchRule::default()
+        }
+    }
+
+    /// Decodes the `a{oa{sa{sv}}}` reply to `GetManagedObjects`
+    pub fn from_reply(reply: &Message) -> Result<Self, DbusParseError> {
+        let mut body = body(reply, "a{oa{sa{sv}}}")?;
+        let objects = dict_entries(body.remove(0))?
+            .into_iter()
+            .map(|DbusDictEntry(path, interfaces)| {
+                Ok((decode_string(path)?, decode_interfaces(interfaces)?))
+            })
+            .collect::<Result<_, DbusParseError>>()?;
+        Ok(Self { objects })
+    }
+
+    /// Applies a signal to the mirror, returning whether it changed anything
+    ///
+    /// Messages other than the followed signals are ignored, and so are
+    /// `PropertiesChanged` for interfaces the mirror does not know.
+    pub fn update(&mut self, signal: &Message) -> Result<bool, DbusParseError> {
+        let header = &signal.header;
+        if header.message_type() != MessageType::Signal {
+            return Ok(false);
+        }
+
+        match (header.interface(), header.member()) {
+            (Some(OBJECT_MANAGER_INTERFACE), Some("InterfacesAdded")) => {
+                let mut body = body(signal, "oa{sa{sv}}")?.into_iter();
+                let path = decode_string(body.next().unwrap())?;
+                let added = decode_interfaces(body.next().unwrap())?;
+                let changed = !added.is_empty();
+                self.objects.entry(path).or_default().extend(added);
+                Ok(changed)
+            }
+            (Some(OBJECT_MANAGER_INTERFACE), Some("InterfacesRemoved")) => {
+                let mut body = body(signal, "oas")?.into_iter();
+                let path = decode_string(body.next().unwrap())?;
+                let names = DbusArray::try_from(body.next().unwrap())?.into_inner();
+                let interfaces = match self.objects.get_mut(&path) {
+                    Some(interfaces) => interfaces,
+                    None => return Ok(false),
+                };
+
+                let mut changed = false;
+                for name in names {
+                    changed |= interfaces.remove(&decode_string(name)?).is_some();
+                }
+                if interfaces.is_empty() {
+                    self.objects.remove(&path);
+                }
+
+                Ok(changed)
+            }
+            (Some(PROPERTIES_INTERFACE), Some("PropertiesChanged")) => {
+                let mut body = body(signal, "sa{sv}as")?.into_iter();
+                let interface = decode_string(body.next().unwrap())?;
+                let changed = decode_properties(body.next().unwrap())?;
+                let invalidated = DbusArray::try_from(body.next().unwrap())?.into_inner();
+                let properties = match header
+                    .path()
+                    .and_then(|path| self.objects.get_mut(path))
+                    .and_then(|interfaces| interfaces.get_mut(&interface))
+                {
+                    Some(properties) => properties,
+                    None => return Ok(false),
+                };
+
+                let updated = !changed.is_empty() || !invalidated.is_empty();
+                properties.extend(changed);
+                for name in invalidated {
+                    properties.remove(&decode_string(name)?);
+                }
+
+                Ok(updated)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Paths of the objects, sorted
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.objects.keys().map(String::as_str)
+    }
+
+    pub fn object(&self, path: &str) -> Option<&ObjectInterfaces> {
+        self.objects.get(path)
+    }
+
+    /// The last known value of a property
+    ///
+    /// Properties invalidated by `PropertiesChanged` have no value until they
+    /// are read again.
+    pub fn property(&self, path: &str, interface: &str, name: &str) -> Option<&DbusTypeContainer> {
+        self.objects.get(path)?.get(interface)?.get(name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+}
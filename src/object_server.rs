@@ -0,0 +1,450 @@
+use crate::header::components::{MessageFlags, MessageType};
+use crate::introspectable::{self, INTROSPECTABLE_INTERFACE};
+use crate::object_manager::{self, OBJECT_MANAGER_INTERFACE};
+use crate::peer::{self, PEER_INTERFACE};
+use crate::properties::{self, PROPERTIES_INTERFACE};
+use crate::signature_type::Signature;
+use crate::types::basic::DbusObjectPath;
+use crate::{error_name, DbusTypeContainer, Message, Property};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+/// Error reply of a method handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MethodError {
+    pub name: String,
+    pub message: String,
+}
+
+impl MethodError {
+    pub fn new<N: Into<String>, M: Into<String>>(name: N, message: M) -> Self {
+        Self {
+            name: name.into(),
+            message: message.into(),
+        }
+    }
+
+    /// `org.freedesktop.DBus.Error.Failed`, the error for anything else
+    pub fn failed<M: Into<String>>(message: M) -> Self {
+        Self::new(error_name::FAILED, message)
+    }
+
+    pub fn invalid_args<M: Into<String>>(message: M) -> Self {
+        Self::new(error_name::INVALID_ARGS, message)
+    }
+}
+
+/// What a method handler returns: the body of the reply, or an error
+pub type MethodResult = Result<Vec<DbusTypeContainer>, MethodError>;
+
+type Handler = Box<dyn FnMut(&Message) -> MethodResult + Send>;
+
+/// A method of an interface, along with the handler answering its calls
+pub struct Method {
+    in_signature: Signature,
+    out_signature: Signature,
+    handler: Handler,
+}
+
+impl Method {
+    /// Signature calls must have
+    pub fn in_signature(&self) -> &Signature {
+        &self.in_signature
+    }
+
+    /// Signature of the replies
+    pub fn out_signature(&self) -> &Signature {
+        &self.out_signature
+    }
+}
+
+impl std::fmt::Debug for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Method")
+            .field("in_signature", &self.in_signature)
+            .field("out_signature", &self.out_signature)
+            .finish()
+    }
+}
+
+/// A named set of methods, signals and properties an object implements
+#[derive(Debug)]
+pub struct Interface {
+    pub(crate) name: String,
+    methods: BTreeMap<String, Method>,
+    /// Signatures of the signals, by name
+    signals: BTreeMap<String, Signature>,
+    pub(crate) properties: BTreeMap<String, Property>,
+}
+
+impl Interface {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            methods: BTreeMap::new(),
+            signals: BTreeMap::new(),
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a method, which `handler` answers once the call is checked against `in_signature`
+    ///
+    /// The body `handler` returns has to match `out_signature`, otherwise
+    /// the caller gets a `Failed` error instead.
+    pub fn with_method<S, F>(
+        mut self,
+        name: S,
+        in_signature: Signature,
+        out_signature: Signature,
+        handler: F,
+    ) -> Self
+    where
+        S: Into<String>,
+        F: FnMut(&Message) -> MethodResult + Send + 'static,
+    {
+        self.methods.insert(
+            name.into(),
+            Method {
+                in_signature,
+                out_signature,
+                handler: Box::new(handler),
+            },
+        );
+        self
+    }
+
+    /// Declares a signal the interface emits, for introspection
+    pub fn with_signal<S: Into<String>>(mut self, name: S, signature: Signature) -> Self {
+        self.signals.insert(name.into(), signature);
+        self
+    }
+
+    /// Adds a property, served through `org.freedesktop.DBus.Properties`
+    pub fn with_property<S: Into<String>>(mut self, name: S, property: Property) -> Self {
+        self.properties.insert(name.into(), property);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn method(&self, name: &str) -> Option<&Method> {
+        self.methods.get(name)
+    }
+
+    /// Every method with its name, sorted by name
+    pub fn methods(&self) -> impl Iterator<Item = (&str, &Method)> {
+        self.methods
+            .iter()
+            .map(|(name, method)| (name.as_str(), method))
+    }
+
+    pub fn signal(&self, name: &str) -> Option<&Signature> {
+        self.signals.get(name)
+    }
+
+    /// Every signal with its signature, sorted by name
+    pub fn signals(&self) -> impl Iterator<Item = (&str, &Signature)> {
+        self.signals
+            .iter()
+            .map(|(name, signature)| (name.as_str(), signature))
+    }
+
+    pub fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.get(name)
+    }
+
+    /// Every property with its name, sorted by name
+    pub fn properties(&self) -> impl Iterator<Item = (&str, &Property)> {
+        self.properties
+            .iter()
+            .map(|(name, property)| (name.as_str(), property))
+    }
+}
+
+/// Objects exported by a service, dispatching the calls they receive to their handlers
+///
+/// The server does not own a connection: messages read from one are handed
+/// to [`ObjectServer::dispatch`], which returns the messages to send back.
+/// The server also implements the standard interfaces, unless an object
+/// registers one of them itself:
+///
+/// - `org.freedesktop.DBus.Peer` on every path
+/// - `org.freedesktop.DBus.Introspectable` on objects and the nodes leading
+///   to them, from the registered interfaces
+/// - `org.freedesktop.DBus.Properties` on every object, for the properties of
+///   its interfaces
+/// - `org.freedesktop.DBus.ObjectManager` on paths added with
+///   [`ObjectServer::add_object_manager`], for the objects below them
+#[derive(Debug, Default)]
+pub struct ObjectServer {
+    /// Interfaces by name, by object path
+    objects: BTreeMap<String, BTreeMap<String, Interface>>,
+    /// Paths of the object managers
+    managers: BTreeSet<String>,
+    /// File `GetMachineId` reads instead of the defaults
+    machine_id_path: Option<PathBuf>,
+}
+
+impl ObjectServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the machine id from `path` rather than from [`MACHINE_ID_PATHS`](crate::MACHINE_ID_PATHS)
+    pub fn with_machine_id_path<P: Into<PathBuf>>(self, path: P) -> Self {
+        Self {
+            machine_id_path: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Exports `interface` on the object at `path`, returning the interface it replaces
+    pub fn add_interface(
+        &mut self,
+        path: &DbusObjectPath,
+        interface: Interface,
+    ) -> Option<Interface> {
+        self.objects
+            .entry(path.to_string())
+            .or_default()
+            .insert(interface.name.clone(), interface)
+    }
+
+    /// Stops exporting an interface, and the object once it has none left
+    pub fn remove_interface(&mut self, path: &str, interface: &str) -> Option<Interface> {
+        let interfaces = self.objects.get_mut(path)?;
+        let removed = interfaces.remove(interface);
+        if interfaces.is_empty() {
+            self.objects.remove(path);
+        }
+
+        removed
+    }
+
+    /// Stops exporting the object at `path`, returning its interfaces
+    pub fn remove_object(&mut self, path: &str) -> Vec<Interface> {
+        self.objects
+            .remove(path)
+            .map_or(vec![], |interfaces| interfaces.into_values().collect())
+    }
+
+    pub fn has_object(&self, path: &str) -> bool {
+        self.objects.contains_key(path)
+    }
+
+    /// Paths of the exported objects, sorted
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.objects.keys().map(String::as_str)
+    }
+
+    /// The interfaces of the object at `path`, sorted by name
+    pub fn interfaces(&self, path: &str) -> impl Iterator<Item = &Interface> {
+        self.objects
+            .get(path)
+            .into_iter()
+            .flat_map(|interfaces| interfaces.values())
+    }
+
+    /// Makes the object at `path` an object manager for every object below it
+    ///
+    /// The path does not need to have interfaces of its own.
+    pub fn add_object_manager(&mut self, path: &DbusObjectPath) {
+        self.managers.insert(path.to_string());
+    }
+
+    /// Stops the object manager at `path`, returning whether there was one
+    pub fn remove_object_manager(&mut self, path: &str) -> bool {
+        self.managers.remove(path)
+    }
+
+    /// Paths of the object managers, sorted
+    pub fn object_managers(&self) -> impl Iterator<Item = &str> {
+        self.managers.iter().map(String::as_str)
+    }
+
+    /// The object manager responsible for the object at `path`, the closest above it
+    pub fn object_manager_of(&self, path: &str) -> Option<&str> {
+        self.managers
+            .iter()
+            .rev()
+            .find(|manager| object_manager::is_managed_by(path, manager))
+            .map(String::as_str)
+    }
+
+    /// The introspection XML of the node at `path`, as `Introspect` returns it
+    ///
+    /// Nodes without objects but with objects below them can be introspected
+    /// too, and so can `/`. Other paths are unknown objects, and the error
+    /// is the one `Introspect` replies with.
+    pub fn introspect(&self, path: &str) -> Result<String, MethodError> {
+        introspectable::introspect(self, path)
+    }
+
+    /// `InterfacesAdded` for interfaces the object at `path` was given, with their properties
+    ///
+    /// Nothing is returned when no object manager is responsible for the
+    /// object, or when it has none of the interfaces.
+    pub fn interfaces_added(&mut self, path: &str, names: &[&str]) -> Option<Message> {
+        let manager = self.object_manager_of(path)?.to_string();
+        let interfaces: Vec<_> = self
+            .objects
+            .get_mut(path)?
+            .iter_mut()
+            .filter(|(name, _)| names.contains(&name.as_str()))
+            .map(|(_, interface)| interface)
+            .collect();
+        if interfaces.is_empty() {
+            return None;
+        }
+
+        object_manager::interfaces_added(&manager, path, interfaces)
+    }
+
+    /// `InterfacesRemoved` for interfaces the object at `path` lost
+    ///
+    /// Nothing is returned when no object manager is responsible for the
+    /// object.
+    pub fn interfaces_removed(&self, path: &str, names: &[&str]) -> Option<Message> {
+        let manager = self.object_manager_of(path)?;
+        object_manager::interfaces_removed(manager, path, names)
+    }
+
+    /// `PropertiesChanged` for properties of `interface` the service changed on its own
+    ///
+    /// Nothing is returned when none of them emits changes. See
+    /// [`EmitsChanged`].
+    pub fn properties_changed(
+        &mut self,
+        path: &str,
+        interface: &str,
+        names: &[&str],
+    ) -> Option<Message> {
+        let interface = self.objects.get_mut(path)?.get_mut(interface)?;
+        properties::properties_changed(path, interface, names)
+    }
+
+    /// Runs the handler of a method call, returning the reply followed by the signals it caused
+    ///
+    /// Calls to missing objects, interfaces or methods, and calls with
+    /// unexpected arguments, are answered with the matching standard error.
+    /// A call without an interface goes to the first interface of the object,
+    /// by name, that has the method. Nothing is returned for messages other
+    /// than method calls, and only the signals for calls flagged
+    /// `NO_REPLY_EXPECTED`.
+    pub fn dispatch(&mut self, call: &Message) -> Vec<Message> {
+        let header = &call.header;
+        if header.message_type() != MessageType::MethodCall {
+            return vec![];
+        }
+
+        let mut messages = self
+            .call(call)
+            .unwrap_or_else(|e| vec![Message::error(call, &e.name, &e.message)]);
+
+        let no_reply = header.fixed.flags.contains(MessageFlags::NO_REPLY_EXPECTED);
+        if no_reply {
+            messages.retain(|message| message.header.reply_serial().is_none());
+        }
+
+        messages
+    }
+
+    fn call(&mut self, call: &Message) -> Result<Vec<Message>, MethodError> {
+        let header = &call.header;
+        let path = header.path().unwrap_or_default();
+        let member = header.member().unwrap_or_default();
+        // Standard interfaces the object does not implement itself
+        let standard = match header.interface() {
+            Some(name)
+                if self
+                    .interfaces(path)
+                    .any(|interface| interface.name == name) =>
+            {
+                None
+            }
+            Some(name) => Some(name),
+            None => {
+                let has_method = self
+                    .interfaces(path)
+                    .any(|interface| interface.methods.contains_key(member));
+                match member {
+                    _ if has_method => None,
+                    "Ping" | "GetMachineId" => Some(PEER_INTERFACE),
+                    "Introspect" => Some(INTROSPECTABLE_INTERFACE),
+                    "GetManagedObjects" => Some(OBJECT_MANAGER_INTERFACE),
+                    "Get" | "GetAll" | "Set" => Some(PROPERTIES_INTERFACE),
+                    _ => None,
+                }
+            }
+        };
+        match standard {
+            Some(PEER_INTERFACE) => {
+                return peer::handle(self.machine_id_path.as_deref(), call);
+            }
+            Some(INTROSPECTABLE_INTERFACE) => return introspectable::handle(self, call),
+            Some(OBJECT_MANAGER_INTERFACE) if self.managers.contains(path) => {
+                return object_manager::handle(&mut self.objects, path, call);
+            }
+            _ => {}
+        }
+
+        let interfaces = self.objects.get_mut(path).ok_or_else(|| {
+            MethodError::new(error_name::UNKNOWN_OBJECT, format!("No object at {}", path))
+        })?;
+
+        if standard == Some(PROPERTIES_INTERFACE) {
+            return properties::handle(interfaces, call);
+        }
+
+        let method = match header.interface() {
+            Some(name) => interfaces
+                .get_mut(name)
+                .ok_or_else(|| {
+                    MethodError::new(
+                        error_name::UNKNOWN_INTERFACE,
+                        format!("The object at {} does not implement {}", path, name),
+                    )
+                })?
+                .methods
+                .get_mut(member),
+            None => interfaces
+                .values_mut()
+                .find_map(|interface| interface.methods.get_mut(member)),
+        };
+        let method = match method {
+            Some(method) => method,
+            None => {
+                return Err(MethodError::new(
+                    error_name::UNKNOWN_METHOD,
+                    format!(
+                        "No method {} on interface {} of the object at {}",
+                        member,
+                        header.interface().unwrap_or("*"),
+                        path
+                    ),
+                ))
+            }
+        };
+
+        let signature = call.body_signature();
+        if signature != method.in_signature {
+            return Err(MethodError::invalid_args(format!(
+                "{} expects the signature \"{}\", not \"{}\"",
+                member, method.in_signature, signature
+            )));
+        }
+
+        let reply = Message::method_return(call).with_body((method.handler)(call)?);
+        let signature = reply.body_signature();
+        if signature != method.out_signature {
+            return Err(MethodError::failed(format!(
+                "{} returned the signature \"{}\" instead of \"{}\"",
+                member, signature, method.out_signature
+            )));
+        }
+
+        Ok(vec![reply])
+    }
+}
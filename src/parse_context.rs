@@ -0,0 +1,193 @@
+use crate::header::components::MessageEndianness;
+use nom::bytes::streaming::take;
+use nom::combinator::map;
+use nom::number::streaming::{be_u32, le_u32};
+use nom::IResult;
+
+/// Upper bounds enforced while decoding untrusted input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// Maximum length of a whole message, header included
+    pub max_message_len: usize,
+    /// Maximum length in bytes of a single array
+    pub max_array_len: usize,
+    /// Maximum nesting of arrays, structs, dict entries and variants
+    pub max_depth: usize,
+}
+
+impl Default for ParseLimits {
+    /// The limits set by the D-Bus specification
+    fn default() -> Self {
+        Self {
+            max_message_len: 1 << 27,
+            max_array_len: 1 << 26,
+            max_depth: 64,
+        }
+    }
+}
+
+/// Decoding state shared by every value of a message
+///
+/// D-Bus aligns values relative to the start of the message, so the context
+/// remembers the length of the buffer it was created from and derives the
+/// current offset from the length of the remaining input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseContext {
+    endianness: MessageEndianness,
+    origin_len: usize,
+    limits: ParseLimits,
+    depth: usize,
+}
+
+impl ParseContext {
+    /// Creates a context whose alignment is relative to the start of `origin`
+    pub fn new(endianness: MessageEndianness, origin: &[u8]) -> Self {
+        Self {
+            endianness,
+            origin_len: origin.len(),
+            limits: ParseLimits::default(),
+            depth: 0,
+        }
+    }
+
+    pub fn with_limits(self, limits: ParseLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    pub fn limits(&self) -> &ParseLimits {
+        &self.limits
+    }
+
+    /// Context for the values inside a container, failing once too deeply nested
+    pub fn nest<'b>(&self, buf: &'b [u8]) -> IResult<&'b [u8], Self> {
+        if self.depth >= self.limits.max_depth {
+            return Err(nom::Err::Failure((buf, nom::error::ErrorKind::TooLarge)));
+        }
+
+        Ok((
+            buf,
+            Self {
+                depth: self.depth + 1,
+                ..*self
+            },
+        ))
+    }
+
+    pub fn endianness(&self) -> MessageEndianness {
+        self.endianness
+    }
+
+    /// Same origin, different byte order
+    pub fn with_endianness(self, endianness: MessageEndianness) -> Self {
+        Self { endianness, ..self }
+    }
+
+    /// Offset of `buf` from the origin; `buf` must be a suffix of the origin buffer
+    pub fn offset(&self, buf: &[u8]) -> usize {
+        self.origin_len.saturating_sub(buf.len())
+    }
+
+    /// Skips the zeroed padding bytes up to the next `alignment` boundary
+    pub fn align<'b>(&self, buf: &'b [u8], alignment: usize) -> IResult<&'b [u8], ()> {
+        let pad = padding(self.offset(buf), alignment);
+        if pad == 0 {
+            return Ok((buf, ()));
+        }
+
+        let (buf, padding) = take(pad)(buf)?;
+        if padding.iter().any(|b| *b != 0) {
+            return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+        }
+
+        Ok((buf, ()))
+    }
+
+    /// Reads a length or count field in the context byte order
+    pub fn u32<'b>(&self, buf: &'b [u8]) -> IResult<&'b [u8], u32> {
+        match self.endianness {
+            MessageEndianness::BigEndian => be_u32(buf),
+            MessageEndianness::LittleEndian => le_u32(buf),
+        }
+    }
+
+    /// Parses the elements of an array whose elements are `alignment`-aligned
+    pub fn array<'b, T, F>(
+        &self,
+        buf: &'b [u8],
+        alignment: usize,
+        mut element: F,
+    ) -> IResult<&'b [u8], Vec<T>>
+    where
+        F: FnMut(&'b [u8]) -> IResult<&'b [u8], T>,
+    {
+        let mut ret = vec![];
+        let (buf, _) = self.for_each_element(buf, alignment, |buf| {
+            let (buf, value) = element(buf)?;
+            ret.push(value);
+            Ok((buf, ()))
+        })?;
+
+        Ok((buf, ret))
+    }
+
+    /// Walks the elements of an array whose elements are `alignment`-aligned
+    ///
+    /// The array length only covers the elements, not the padding between the
+    /// length and the first element, which is skipped even for empty arrays.
+    pub fn for_each_element<'b, F>(
+        &self,
+        buf: &'b [u8],
+        alignment: usize,
+        mut element: F,
+    ) -> IResult<&'b [u8], ()>
+    where
+        F: FnMut(&'b [u8]) -> IResult<&'b [u8], ()>,
+    {
+        let (buf, len) = self.array_len(buf)?;
+        let (mut buf, _) = self.align(buf, alignment)?;
+        // Make sure the whole array is available before walking it
+        take(len)(buf)?;
+
+        let end = buf.len() - len;
+        while buf.len() > end {
+            let (rest, _) = element(buf)?;
+            if rest.len() == buf.len() {
+                return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+            }
+
+            buf = rest;
+        }
+
+        // The last element overran the array length
+        if buf.len() != end {
+            return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+        }
+
+        Ok((buf, ()))
+    }
+
+    /// Reads the length of an array, checking it against the limits
+    pub fn array_len<'b>(&self, buf: &'b [u8]) -> IResult<&'b [u8], usize> {
+        let (buf, len) = map(|buf| self.align_u32(buf), |v| v as usize)(buf)?;
+        if len > self.limits.max_array_len {
+            return Err(nom::Err::Failure((buf, nom::error::ErrorKind::TooLarge)));
+        }
+
+        Ok((buf, len))
+    }
+
+    /// Reads a 4-aligned length or count field
+    pub fn align_u32<'b>(&self, buf: &'b [u8]) -> IResult<&'b [u8], u32> {
+        let (buf, _) = self.align(buf, 4)?;
+        self.u32(buf)
+    }
+}
+
+/// Number of padding bytes needed at `offset` to reach the next `alignment` boundary
+pub fn padding(offset: usize, alignment: usize) -> usize {
+    if alignment <= 1 {
+        return 0;
+    }
+
+    (alignment - offset % alignment) % alignment
+}
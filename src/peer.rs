@@ -0,0 +1,78 @@
+use crate::signature_type::Signature;
+use crate::{error_name, DbusTypeContainer, Message, MethodError};
+use std::fs;
+use std::path::Path;
+
+pub const PEER_INTERFACE: &str = "org.freedesktop.DBus.Peer";
+
+/// Files the machine id is read from, in order, unless another one is configured
+pub const MACHINE_ID_PATHS: [&str; 2] = ["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// Reads the machine id from `path`: 32 hexadecimal digits, optionally followed by a newline
+fn read_machine_id(path: &Path) -> Result<String, MethodError> {
+    let contents = fs::read_to_string(path).map_err(|e| {
+        MethodError::failed(format!(
+            "Cannot read the machine id from {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+
+    let id = contents.trim_end();
+    if id.len() != 32 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(MethodError::failed(format!(
+            "{} does not hold a valid machine id",
+            path.display()
+        )));
+    }
+
+    Ok(id.to_ascii_lowercase())
+}
+
+fn machine_id(path: Option<&Path>) -> Result<String, MethodError> {
+    if let Some(path) = path {
+        return read_machine_id(path);
+    }
+
+    let mut error = None;
+    for path in MACHINE_ID_PATHS.iter() {
+        match read_machine_id(Path::new(path)) {
+            Ok(id) => return Ok(id),
+            Err(e) => error = error.or(Some(e)),
+        }
+    }
+
+    Err(error.expect("there is at least one default path"))
+}
+
+/// Answers a call of `org.freedesktop.DBus.Peer`, which every path implements
+///
+/// `machine_id_path` replaces [`MACHINE_ID_PATHS`] when set.
+pub(crate) fn handle(
+    machine_id_path: Option<&Path>,
+    call: &Message,
+) -> Result<Vec<Message>, MethodError> {
+    let member = call.header.member().unwrap_or_default();
+    if member != "Ping" && member != "GetMachineId" {
+        return Err(MethodError::new(
+            error_name::UNKNOWN_METHOD,
+            format!("No method {} on interface {}", member, PEER_INTERFACE),
+        ));
+    }
+
+    let signature = call.body_signature();
+    if signature != Signature::default() {
+        return Err(MethodError::invalid_args(format!(
+            "{} expects no arguments, not \"{}\"",
+            member, signature
+        )));
+    }
+
+    let body = match member {
+        "GetMachineId" => vec![DbusTypeContainer::String(
+            machine_id(machine_id_path)?.into(),
+        )],
+        _ => vec![],
+    };
+    Ok(vec![Message::method_return(call).with_body(body)])
+}
@@ -0,0 +1,322 @@
+use crate::signature_type::{Signature, SignatureType};
+use crate::types::containers::{DbusArray, DbusDict, DbusDictEntry, DbusVariant};
+use crate::{error_name, DbusTypeContainer, Interface, Message, MethodError};
+use std::collections::BTreeMap;
+
+pub const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+type Getter = Box<dyn FnMut() -> Result<DbusTypeContainer, MethodError> + Send>;
+type Setter = Box<dyn FnMut(DbusTypeContainer) -> Result<(), MethodError> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PropertyAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl PropertyAccess {
+    pub fn is_readable(self) -> bool {
+        self != PropertyAccess::Write
+    }
+
+    pub fn is_writable(self) -> bool {
+        self != PropertyAccess::Read
+    }
+}
+
+/// How clients learn about new values, as in the `EmitsChangedSignal` annotation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum EmitsChanged {
+    /// `PropertiesChanged` carries the new value
+    #[default]
+    True,
+    /// `PropertiesChanged` only names the property, which clients have to get again
+    Invalidates,
+    /// The value never changes
+    Const,
+    /// Nothing tells clients about changes
+    False,
+}
+
+/// A property of an interface, read and written through handlers
+pub struct Property {
+    signature: Signature,
+    emits_changed: EmitsChanged,
+    getter: Option<Getter>,
+    setter: Option<Setter>,
+}
+
+impl Property {
+    /// A property that `getter` reads, which clients cannot set
+    pub fn read_only<G>(signature: Signature, getter: G) -> Self
+    where
+        G: FnMut() -> Result<DbusTypeContainer, MethodError> + Send + 'static,
+    {
+        Self {
+            signature,
+            emits_changed: EmitsChanged::default(),
+            getter: Some(Box::new(getter)),
+            setter: None,
+        }
+    }
+
+    /// A property that `getter` reads and `setter` writes
+    ///
+    /// Values are checked against `signature` before `setter` sees them.
+    pub fn read_write<G, S>(signature: Signature, getter: G, setter: S) -> Self
+    where
+        G: FnMut() -> Result<DbusTypeContainer, MethodError> + Send + 'static,
+        S: FnMut(DbusTypeContainer) -> Result<(), MethodError> + Send + 'static,
+    {
+        Self {
+            signature,
+            emits_changed: EmitsChanged::default(),
+            getter: Some(Box::new(getter)),
+            setter: Some(Box::new(setter)),
+        }
+    }
+
+    /// A property that `setter` writes, which clients cannot get
+    pub fn write_only<S>(signature: Signature, setter: S) -> Self
+    where
+        S: FnMut(DbusTypeContainer) -> Result<(), MethodError> + Send + 'static,
+    {
+        Self {
+            signature,
+            emits_changed: EmitsChanged::default(),
+            getter: None,
+            setter: Some(Box::new(setter)),
+        }
+    }
+
+    pub fn with_emits_changed(self, emits_changed: EmitsChanged) -> Self {
+        Self {
+            emits_changed,
+            ..self
+        }
+    }
+
+    pub fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    pub fn access(&self) -> PropertyAccess {
+        match (&self.getter, &self.setter) {
+            (Some(_), Some(_)) => PropertyAccess::ReadWrite,
+            (None, Some(_)) => PropertyAccess::Write,
+            _ => PropertyAccess::Read,
+        }
+    }
+
+    pub fn emits_changed(&self) -> EmitsChanged {
+        self.emits_changed
+    }
+
+    fn get(&mut self, name: &str) -> Result<DbusTypeContainer, MethodError> {
+        let getter = self.getter.as_mut().ok_or_else(|| {
+            MethodError::new(
+                error_name::ACCESS_DENIED,
+                format!("The property {} is write-only", name),
+            )
+        })?;
+
+        let value = getter()?;
+        if value.signature() != self.signature {
+            return Err(MethodError::failed(format!(
+                "The property {} has the signature \"{}\", not \"{}\"",
+                name,
+                self.signature,
+                value.signature()
+            )));
+        }
+
+        Ok(value)
+    }
+
+    fn set(&mut self, name: &str, value: DbusTypeContainer) -> Result<(), MethodError> {
+        let setter = self.setter.as_mut().ok_or_else(|| {
+            MethodError::new(
+                error_name::PROPERTY_READ_ONLY,
+                format!("The property {} is read-only", name),
+            )
+        })?;
+
+        if value.signature() != self.signature {
+            return Err(MethodError::invalid_args(format!(
+                "The property {} has the signature \"{}\", not \"{}\"",
+                name,
+                self.signature,
+                value.signature()
+            )));
+        }
+
+        setter(value)
+    }
+}
+
+impl std::fmt::Debug for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Property")
+            .field("signature", &self.signature)
+            .field("access", &self.access())
+            .field("emits_changed", &self.emits_changed)
+            .finish()
+    }
+}
+
+fn string(value: &str) -> DbusTypeContainer {
+    DbusTypeContainer::String(value.into())
+}
+
+fn variant(value: DbusTypeContainer) -> DbusTypeContainer {
+    DbusTypeContainer::Variant(Box::new(DbusVariant::new(value)))
+}
+
+/// An `a{sv}` dictionary, as properties are exchanged
+pub(crate) fn property_dict(entries: Vec<(&str, DbusTypeContainer)>) -> DbusTypeContainer {
+    let entries = entries
+        .into_iter()
+        .map(|(name, value)| DbusDictEntry::new(string(name), variant(value)))
+        .collect();
+    let dict = DbusDict::new(
+        SignatureType::String.into(),
+        SignatureType::Variant.into(),
+        entries,
+    )
+    .expect("entries are strings to variants");
+    DbusTypeContainer::Dict(dict)
+}
+
+/// The values of the readable properties of `interface`, sorted by name
+pub(crate) fn property_values(
+    interface: &mut Interface,
+) -> Vec<(&str, Result<DbusTypeContainer, MethodError>)> {
+    interface
+        .properties
+        .iter_mut()
+        .filter(|(_, property)| property.access().is_readable())
+        .map(|(name, property)| (name.as_str(), property.get(name)))
+        .collect()
+}
+
+/// `PropertiesChanged` for the properties of `interface` in `names`
+///
+/// Properties that do not emit changes are left out, and properties that
+/// cannot be read are listed as invalidated. Returns nothing when no
+/// property is left.
+pub(crate) fn properties_changed(
+    path: &str,
+    interface: &mut Interface,
+    names: &[&str],
+) -> Option<Message> {
+    let mut changed = vec![];
+    let mut invalidated = vec![];
+    for name in names.iter() {
+        let property = match interface.properties.get_mut(*name) {
+            Some(property) => property,
+            None => continue,
+        };
+
+        match property.emits_changed {
+            EmitsChanged::True => match property.get(name) {
+                Ok(value) => changed.push((*name, value)),
+                Err(_) => invalidated.push(string(name)),
+            },
+            EmitsChanged::Invalidates => invalidated.push(string(name)),
+            EmitsChanged::Const | EmitsChanged::False => {}
+        }
+    }
+
+    if changed.is_empty() && invalidated.is_empty() {
+        return None;
+    }
+
+    let invalidated = DbusArray::new(SignatureType::String.into(), invalidated)
+        .expect("every element is a string");
+    let signal = Message::signal(path, PROPERTIES_INTERFACE, "PropertiesChanged")
+        .ok()?
+        .with_body(vec![
+            string(&interface.name),
+            property_dict(changed),
+            DbusTypeContainer::Array(invalidated),
+        ]);
+    Some(signal)
+}
+
+/// Answers a call of `org.freedesktop.DBus.Properties` on the object made of `interfaces`
+///
+/// Returns the reply followed by the signals the call caused.
+pub(crate) fn handle(
+    interfaces: &mut BTreeMap<String, Interface>,
+    call: &Message,
+) -> Result<Vec<Message>, MethodError> {
+    let header = &call.header;
+    let path = header.path().unwrap_or_default();
+    let member = header.member().unwrap_or_default();
+    let signature = match member {
+        "Get" => "ss",
+        "GetAll" => "s",
+        "Set" => "ssv",
+        _ => {
+            return Err(MethodError::new(
+                error_name::UNKNOWN_METHOD,
+                format!("No method {} on interface {}", member, PROPERTIES_INTERFACE),
+            ))
+        }
+    };
+
+    let actual = call.body_signature();
+    if actual.to_string() != signature {
+        return Err(MethodError::invalid_args(format!(
+            "{} expects the signature \"{}\", not \"{}\"",
+            member, signature, actual
+        )));
+    }
+
+    let arg = |n: usize| match &call.message[n] {
+        DbusTypeContainer::String(s) => &**s,
+        _ => unreachable!("the signature was checked"),
+    };
+    let interface = interfaces.get_mut(arg(0)).ok_or_else(|| {
+        MethodError::new(
+            error_name::UNKNOWN_INTERFACE,
+            format!("The object at {} does not implement {}", path, arg(0)),
+        )
+    })?;
+
+    if member == "GetAll" {
+        let values = property_values(interface)
+            .into_iter()
+            .map(|(name, value)| value.map(|value| (name, value)))
+            .collect::<Result<_, _>>()?;
+
+        let reply = Message::method_return(call).with_body(vec![property_dict(values)]);
+        return Ok(vec![reply]);
+    }
+
+    let name = arg(1);
+    let property = interface.properties.get_mut(name).ok_or_else(|| {
+        MethodError::new(
+            error_name::UNKNOWN_PROPERTY,
+            format!("The interface {} has no property {}", arg(0), name),
+        )
+    })?;
+
+    if member == "Get" {
+        let value = property.get(name)?;
+        return Ok(vec![
+            Message::method_return(call).with_body(vec![variant(value)])
+        ]);
+    }
+
+    let value = match &call.message[2] {
+        DbusTypeContainer::Variant(value) => (**value).clone().into_inner(),
+        _ => unreachable!("the signature was checked"),
+    };
+    property.set(name, value)?;
+
+    let mut messages = vec![Message::method_return(call)];
+    messages.extend(properties_changed(path, interface, &[name]));
+    Ok(messages)
+}
@@ -0,0 +1,71 @@
+use super::{ClientMechanism, ServerMechanism, ServerStep};
+
+const NAME: &str = "ANONYMOUS";
+
+/// Client side of ANONYMOUS, sending an optional free-form trace
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AnonymousClient {
+    trace: String,
+}
+
+impl AnonymousClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends `trace` along, which servers may log but never check
+    pub fn with_trace<S: Into<String>>(trace: S) -> Self {
+        Self {
+            trace: trace.into(),
+        }
+    }
+}
+
+impl ClientMechanism for AnonymousClient {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        Some(self.trace.clone().into_bytes())
+    }
+
+    fn challenge(&mut self, _: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(self.trace.clone().into_bytes())
+    }
+}
+
+/// Server side of ANONYMOUS, accepting any client
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AnonymousServer {
+    trace: Option<String>,
+}
+
+impl AnonymousServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trace the client sent, if it was valid UTF-8
+    pub fn trace(&self) -> Option<&str> {
+        self.trace.as_deref()
+    }
+}
+
+impl ServerMechanism for AnonymousServer {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn start(&mut self, initial_response: Option<&[u8]>) -> ServerStep {
+        match initial_response {
+            Some(data) => self.response(data),
+            None => ServerStep::Challenge(vec![]),
+        }
+    }
+
+    fn response(&mut self, data: &[u8]) -> ServerStep {
+        self.trace = String::from_utf8(data.to_vec()).ok();
+        ServerStep::Accept
+    }
+}
@@ -0,0 +1,133 @@
+use super::{ClientMechanism, Command, SaslError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientState {
+    Start,
+    WaitingForData,
+    WaitingForReject,
+    WaitingForAgreeUnixFd,
+    Authenticated,
+}
+
+/// Client side of the handshake, trying its mechanisms in order
+pub struct SaslClient {
+    mechanisms: Vec<Box<dyn ClientMechanism>>,
+    current: usize,
+    state: ClientState,
+    negotiate_unix_fd: bool,
+    unix_fd: bool,
+    guid: Option<String>,
+}
+
+impl SaslClient {
+    pub fn new(mechanisms: Vec<Box<dyn ClientMechanism>>) -> Self {
+        Self {
+            mechanisms,
+            current: 0,
+            state: ClientState::Start,
+            negotiate_unix_fd: false,
+            unix_fd: false,
+            guid: None,
+        }
+    }
+
+    /// Asks the server for file descriptor passing once authenticated
+    pub fn with_unix_fd_negotiation(self) -> Self {
+        Self {
+            negotiate_unix_fd: true,
+            ..self
+        }
+    }
+
+    /// Command opening the handshake, sent right after the nul byte
+    pub fn start(&mut self) -> Result<Command, SaslError> {
+        if self.state != ClientState::Start {
+            return Err(SaslError::Finished);
+        }
+
+        self.auth()
+    }
+
+    /// Handles a line from the server, returning the line to answer with
+    ///
+    /// The answer to `OK`, or to `AGREE_UNIX_FD` when negotiating file
+    /// descriptor passing, is `BEGIN`, after which messages flow.
+    pub fn handle(&mut self, command: Command) -> Result<Command, SaslError> {
+        match (self.state, command) {
+            (ClientState::Authenticated, _) => Err(SaslError::Finished),
+            (ClientState::WaitingForData, Command::Data(data)) => {
+                match self.mechanisms[self.current].challenge(&data) {
+                    Ok(response) => Ok(Command::Data(response)),
+                    Err(_) => self.cancel(),
+                }
+            }
+            (ClientState::WaitingForData, Command::Ok(guid)) => {
+                self.guid = Some(guid);
+                if self.negotiate_unix_fd {
+                    self.state = ClientState::WaitingForAgreeUnixFd;
+                    Ok(Command::NegotiateUnixFd)
+                } else {
+                    self.state = ClientState::Authenticated;
+                    Ok(Command::Begin)
+                }
+            }
+            (
+                ClientState::WaitingForData | ClientState::WaitingForReject,
+                Command::Rejected(supported),
+            ) => self.next_mechanism(&supported),
+            (ClientState::WaitingForData, Command::Error(_)) => self.cancel(),
+            (ClientState::WaitingForData, _) => {
+                Ok(Command::Error("Unexpected command".to_string()))
+            }
+            (ClientState::WaitingForAgreeUnixFd, command @ Command::AgreeUnixFd)
+            | (ClientState::WaitingForAgreeUnixFd, command @ Command::Error(_)) => {
+                self.unix_fd = command == Command::AgreeUnixFd;
+                self.state = ClientState::Authenticated;
+                Ok(Command::Begin)
+            }
+            (_, command) => Err(SaslError::UnexpectedCommand(command.to_string())),
+        }
+    }
+
+    fn auth(&mut self) -> Result<Command, SaslError> {
+        let mechanism = self
+            .mechanisms
+            .get_mut(self.current)
+            .ok_or(SaslError::NoMechanismLeft)?;
+        self.state = ClientState::WaitingForData;
+        Ok(Command::Auth {
+            mechanism: Some(mechanism.name().to_string()),
+            initial_response: mechanism.initial_response(),
+        })
+    }
+
+    fn cancel(&mut self) -> Result<Command, SaslError> {
+        self.state = ClientState::WaitingForReject;
+        Ok(Command::Cancel)
+    }
+
+    /// Moves on to the next mechanism the server supports
+    fn next_mechanism(&mut self, supported: &[String]) -> Result<Command, SaslError> {
+        self.current = (self.current + 1..self.mechanisms.len())
+            .find(|i| {
+                let name = self.mechanisms[*i].name();
+                supported.is_empty() || supported.iter().any(|s| s == name)
+            })
+            .ok_or(SaslError::NoMechanismLeft)?;
+        self.auth()
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.state == ClientState::Authenticated
+    }
+
+    /// GUID of the server, known once it accepted the client
+    pub fn guid(&self) -> Option<&str> {
+        self.guid.as_deref()
+    }
+
+    /// Whether the server agreed to pass file descriptors
+    pub fn unix_fd_agreed(&self) -> bool {
+        self.unix_fd
+    }
+}
@@ -0,0 +1,138 @@
+use nom::bytes::streaming::tag;
+use nom::IResult;
+
+/// A line of the SASL handshake, in either direction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `AUTH [mechanism [initial-response]]`, sent by the client
+    Auth {
+        mechanism: Option<String>,
+        initial_response: Option<Vec<u8>>,
+    },
+    /// `CANCEL`, sent by the client
+    Cancel,
+    /// `BEGIN`, sent by the client
+    Begin,
+    /// `DATA <data>`, sent by both sides
+    Data(Vec<u8>),
+    /// `ERROR [message]`, sent by both sides
+    Error(String),
+    /// `NEGOTIATE_UNIX_FD`, sent by the client
+    NegotiateUnixFd,
+    /// `REJECTED <mechanisms>`, sent by the server
+    Rejected(Vec<String>),
+    /// `OK <guid>`, sent by the server
+    Ok(String),
+    /// `AGREE_UNIX_FD`, sent by the server
+    AgreeUnixFd,
+}
+
+/// Longest line accepted, without its `\r\n`
+///
+/// Commands are short, so a peer sending more without ending the line is
+/// rejected rather than buffered without bound.
+pub const MAX_LINE_LEN: usize = 16 * 1024;
+
+fn verify(buf: &[u8]) -> nom::Err<(&[u8], nom::error::ErrorKind)> {
+    nom::Err::Error((buf, nom::error::ErrorKind::Verify))
+}
+
+impl Command {
+    /// Parses one `\r\n`-terminated line of at most [`MAX_LINE_LEN`] bytes
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        let end = match buf
+            .windows(2)
+            .take(MAX_LINE_LEN + 1)
+            .position(|w| w == b"\r\n")
+        {
+            Some(end) => end,
+            None if buf.len() >= MAX_LINE_LEN + 2 => {
+                return Err(nom::Err::Failure((buf, nom::error::ErrorKind::TooLarge)));
+            }
+            None => return Err(nom::Err::Incomplete(nom::Needed::Size(2))),
+        };
+        let (line, buf) = (&buf[..end], &buf[end + 2..]);
+        let line = std::str::from_utf8(line).map_err(|_| verify(buf))?;
+        if !line.is_ascii() {
+            return Err(verify(buf));
+        }
+
+        let (command, args) = match line.find(' ') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => (line, ""),
+        };
+        let mut words = args.split(' ').filter(|word| !word.is_empty());
+        let data = |word: &str| hex::decode(word).map_err(|_| verify(buf));
+
+        let command = match command {
+            "AUTH" => Command::Auth {
+                mechanism: words.next().map(String::from),
+                initial_response: words.next().map(data).transpose()?,
+            },
+            "CANCEL" => Command::Cancel,
+            "BEGIN" => Command::Begin,
+            // An empty challenge or response is sent as a bare `DATA`
+            "DATA" => Command::Data(words.next().map(data).transpose()?.unwrap_or_default()),
+            // The explanation is free-form text
+            "ERROR" => return Ok((buf, Command::Error(args.to_string()))),
+            "NEGOTIATE_UNIX_FD" => Command::NegotiateUnixFd,
+            "REJECTED" => Command::Rejected(words.by_ref().map(String::from).collect()),
+            "OK" => Command::Ok(words.next().ok_or_else(|| verify(buf))?.to_string()),
+            "AGREE_UNIX_FD" => Command::AgreeUnixFd,
+            _ => return Err(verify(buf)),
+        };
+
+        if words.next().is_some() {
+            return Err(verify(buf));
+        }
+
+        Ok((buf, command))
+    }
+
+    /// Encodes the command as a `\r\n`-terminated line
+    pub fn to_bytes(&self) -> Vec<u8> {
+        format!("{}\r\n", self).into_bytes()
+    }
+}
+
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Command::Auth {
+                mechanism,
+                initial_response,
+            } => {
+                write!(f, "AUTH")?;
+                if let Some(mechanism) = mechanism {
+                    write!(f, " {}", mechanism)?;
+                    if let Some(initial_response) = initial_response {
+                        write!(f, " {}", hex::encode(initial_response))?;
+                    }
+                }
+
+                Ok(())
+            }
+            Command::Cancel => write!(f, "CANCEL"),
+            Command::Begin => write!(f, "BEGIN"),
+            Command::Data(data) if data.is_empty() => write!(f, "DATA"),
+            Command::Data(data) => write!(f, "DATA {}", hex::encode(data)),
+            Command::Error(message) if message.is_empty() => write!(f, "ERROR"),
+            Command::Error(message) => write!(f, "ERROR {}", message),
+            Command::NegotiateUnixFd => write!(f, "NEGOTIATE_UNIX_FD"),
+            Command::Rejected(mechanisms) => {
+                write!(f, "REJECTED")?;
+                mechanisms.iter().try_for_each(|m| write!(f, " {}", m))
+            }
+            Command::Ok(guid) => write!(f, "OK {}", guid),
+            Command::AgreeUnixFd => write!(f, "AGREE_UNIX_FD"),
+        }
+    }
+}
+
+/// Reads the nul byte a client sends before the first command
+///
+/// On Unix sockets this byte also carries the client credentials.
+pub fn parse_nul_byte(buf: &[u8]) -> IResult<&[u8], ()> {
+    let (buf, _) = tag(b"\0")(buf)?;
+    Ok((buf, ()))
+}
@@ -0,0 +1,380 @@
+use super::{ClientMechanism, ServerMechanism, ServerStep};
+use crate::random_hex;
+use sha1::{Digest, Sha1};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const NAME: &str = "DBUS_COOKIE_SHA1";
+
+/// Context used by the reference implementation
+pub const DEFAULT_CONTEXT: &str = "org_freedesktop_general";
+
+/// Cookies older than this are not handed out for new authentications
+const MAX_COOKIE_AGE: u64 = 5 * 60;
+/// Cookies older than this are removed from the keyring
+const EXPIRE_COOKIE_AGE: u64 = 7 * 60;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// The proof of knowledge of a cookie exchanged by both sides
+fn digest(server_challenge: &str, client_challenge: &str, cookie: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(format!(
+        "{}:{}:{}",
+        server_challenge, client_challenge, cookie
+    ));
+    hex::encode(sha1.finalize())
+}
+
+/// Contexts name keyring files, so they cannot hold path separators or dots
+pub fn is_valid_context(context: &str) -> bool {
+    !context.is_empty()
+        && !context
+            .chars()
+            .any(|c| c == '/' || c == '\\' || c == '.' || c.is_whitespace())
+}
+
+/// A secret shared through the keyring of the user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub id: u32,
+    /// Creation time, in seconds since the Unix epoch
+    pub created: u64,
+    /// The secret, as hex
+    pub value: String,
+}
+
+impl Cookie {
+    fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split(' ');
+        let cookie = Cookie {
+            id: words.next()?.parse().ok()?,
+            created: words.next()?.parse().ok()?,
+            value: words.next()?.to_string(),
+        };
+
+        match words.next() {
+            Some(_) => None,
+            None => Some(cookie),
+        }
+    }
+}
+
+/// Removes the keyring lock file when dropped
+struct KeyringLock(PathBuf);
+
+impl Drop for KeyringLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The `~/.dbus-keyrings` directory, holding one cookie file per context
+///
+/// Each line of a cookie file is `<id> <creation time> <cookie>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keyring {
+    dir: PathBuf,
+}
+
+impl Keyring {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The keyring of the current user, in `$HOME/.dbus-keyrings`
+    pub fn user_default() -> Option<Self> {
+        let home = std::env::var_os("HOME")?;
+        Some(Self::new(Path::new(&home).join(".dbus-keyrings")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, context: &str) -> io::Result<PathBuf> {
+        if !is_valid_context(context) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid keyring context",
+            ));
+        }
+
+        Ok(self.dir.join(context))
+    }
+
+    /// Fails unless only its owner can access the keyring directory, as the specification requires
+    fn check_permissions(&self) -> io::Result<()> {
+        let mode = fs::metadata(&self.dir)?.permissions().mode();
+        if mode & 0o077 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "keyring directory is accessible to other users",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Reads the cookies of `context`, skipping malformed lines
+    pub fn cookies(&self, context: &str) -> io::Result<Vec<Cookie>> {
+        let path = self.path(context)?;
+        match self.check_permissions() {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            result => result?,
+        }
+
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(contents.lines().filter_map(Cookie::parse).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn cookie(&self, context: &str, id: u32) -> io::Result<Option<Cookie>> {
+        Ok(self
+            .cookies(context)?
+            .into_iter()
+            .find(|cookie| cookie.id == id))
+    }
+
+    /// Returns a cookie recent enough for a new authentication, adding one if needed
+    ///
+    /// Expired cookies are dropped from the file at the same time.
+    pub fn fresh_cookie(&self, context: &str) -> io::Result<Cookie> {
+        let path = self.path(context)?;
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&self.dir)?;
+        self.check_permissions()?;
+        let _lock = self.lock(context)?;
+
+        let now = now();
+        let mut cookies: Vec<_> = self
+            .cookies(context)?
+            .into_iter()
+            // Cookies from the future are as suspicious as expired ones
+            .filter(|c| c.created <= now + MAX_COOKIE_AGE)
+            .filter(|c| now < c.created + EXPIRE_COOKIE_AGE)
+            .collect();
+        if let Some(cookie) = cookies.iter().find(|c| now < c.created + MAX_COOKIE_AGE) {
+            return Ok(cookie.clone());
+        }
+
+        let cookie = Cookie {
+            id: cookies
+                .iter()
+                .map(|c| c.id)
+                .max()
+                .map_or(Some(0), |id| id.checked_add(1))
+                .ok_or_else(|| io::Error::other("keyring cookie ids are exhausted"))?,
+            created: now,
+            value: random_hex(24),
+        };
+        cookies.push(cookie.clone());
+
+        // Replace the file atomically so readers never see a partial keyring
+        let tmp = self.dir.join(format!("{}.{}.tmp", context, random_hex(4)));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp)?;
+        for c in cookies.iter() {
+            writeln!(file, "{} {} {}", c.id, c.created, c.value)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(cookie)
+    }
+
+    /// Takes the lock of `context`, breaking it when it looks abandoned
+    fn lock(&self, context: &str) -> io::Result<KeyringLock> {
+        let path = self.dir.join(format!("{}.lock", context));
+        for attempt in 0..32 {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(_) => return Ok(KeyringLock(path)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    if attempt == 16 {
+                        let _ = fs::remove_file(&path);
+                    }
+
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "keyring is locked",
+        ))
+    }
+}
+
+/// Client side of DBUS_COOKIE_SHA1, proving it can read the server user's keyring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CookieSha1Client {
+    keyring: Keyring,
+    username: String,
+}
+
+impl CookieSha1Client {
+    /// Authenticates as `username`, with the cookies found in `keyring`
+    pub fn new<S: Into<String>>(keyring: Keyring, username: S) -> Self {
+        Self {
+            keyring,
+            username: username.into(),
+        }
+    }
+}
+
+impl ClientMechanism for CookieSha1Client {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        Some(self.username.clone().into_bytes())
+    }
+
+    fn challenge(&mut self, data: &[u8]) -> Result<Vec<u8>, String> {
+        if data.is_empty() {
+            return Ok(self.username.clone().into_bytes());
+        }
+
+        let challenge = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        let mut words = challenge.split(' ');
+        let (context, id, server_challenge) = match (words.next(), words.next(), words.next()) {
+            (Some(context), Some(id), Some(server_challenge)) => (context, id, server_challenge),
+            _ => return Err("malformed challenge".to_string()),
+        };
+        let id = id.parse().map_err(|_| "malformed cookie id".to_string())?;
+        let cookie = self
+            .keyring
+            .cookie(context, id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| "unknown cookie".to_string())?;
+
+        let client_challenge = random_hex(16);
+        let response = digest(server_challenge, &client_challenge, &cookie.value);
+        Ok(format!("{} {}", client_challenge, response).into_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CookieSha1State {
+    WaitingForUsername,
+    WaitingForResponse {
+        server_challenge: String,
+        cookie: String,
+    },
+    Done,
+}
+
+/// Server side of DBUS_COOKIE_SHA1, challenging clients with a cookie of its keyring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CookieSha1Server {
+    keyring: Keyring,
+    context: String,
+    state: CookieSha1State,
+    username: Option<String>,
+}
+
+impl CookieSha1Server {
+    pub fn new(keyring: Keyring) -> Self {
+        Self::with_context(keyring, DEFAULT_CONTEXT)
+    }
+
+    pub fn with_context<S: Into<String>>(keyring: Keyring, context: S) -> Self {
+        Self {
+            keyring,
+            context: context.into(),
+            state: CookieSha1State::WaitingForUsername,
+            username: None,
+        }
+    }
+
+    /// User name the client claimed
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    fn challenge(&mut self, username: &[u8]) -> ServerStep {
+        let username = match String::from_utf8(username.to_vec()) {
+            Ok(username) if !username.is_empty() => username,
+            _ => return ServerStep::Reject,
+        };
+        let cookie = match self.keyring.fresh_cookie(&self.context) {
+            Ok(cookie) => cookie,
+            Err(_) => return ServerStep::Reject,
+        };
+
+        let server_challenge = random_hex(16);
+        let challenge = format!("{} {} {}", self.context, cookie.id, server_challenge);
+        self.username = Some(username);
+        self.state = CookieSha1State::WaitingForResponse {
+            server_challenge,
+            cookie: cookie.value,
+        };
+        ServerStep::Challenge(challenge.into_bytes())
+    }
+}
+
+impl ServerMechanism for CookieSha1Server {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn start(&mut self, initial_response: Option<&[u8]>) -> ServerStep {
+        self.state = CookieSha1State::WaitingForUsername;
+        self.username = None;
+        match initial_response {
+            Some(username) => self.challenge(username),
+            None => ServerStep::Challenge(vec![]),
+        }
+    }
+
+    fn response(&mut self, data: &[u8]) -> ServerStep {
+        let (server_challenge, cookie) = match &self.state {
+            CookieSha1State::WaitingForUsername => return self.challenge(data),
+            CookieSha1State::WaitingForResponse {
+                server_challenge,
+                cookie,
+            } => (server_challenge, cookie),
+            CookieSha1State::Done => return ServerStep::Reject,
+        };
+
+        let response = std::str::from_utf8(data).unwrap_or_default();
+        let mut words = response.split(' ');
+        let accepted = match (words.next(), words.next(), words.next()) {
+            (Some(client_challenge), Some(hash), None) => {
+                !client_challenge.is_empty()
+                    && digest(server_challenge, client_challenge, cookie) == hash
+            }
+            _ => false,
+        };
+
+        self.state = CookieSha1State::Done;
+        if accepted {
+            ServerStep::Accept
+        } else {
+            ServerStep::Reject
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use super::{ClientMechanism, ServerMechanism, ServerStep};
+
+const NAME: &str = "EXTERNAL";
+
+/// Effective user id of the current process
+pub fn current_uid() -> u32 {
+    // SAFETY: `geteuid` has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+/// Parses the identity sent by the client, the decimal uid as ASCII
+fn parse_uid(data: &[u8]) -> Option<u32> {
+    std::str::from_utf8(data).ok()?.parse().ok()
+}
+
+/// Client side of EXTERNAL, relying on the credentials the transport passes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalClient {
+    uid: u32,
+}
+
+impl ExternalClient {
+    /// Authenticates as the user running the process
+    pub fn new() -> Self {
+        Self::with_uid(current_uid())
+    }
+
+    pub fn with_uid(uid: u32) -> Self {
+        Self { uid }
+    }
+}
+
+impl Default for ExternalClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientMechanism for ExternalClient {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn initial_response(&mut self) -> Option<Vec<u8>> {
+        Some(self.uid.to_string().into_bytes())
+    }
+
+    fn challenge(&mut self, data: &[u8]) -> Result<Vec<u8>, String> {
+        // An empty challenge asks for the identity when it was not sent with `AUTH`
+        if data.is_empty() {
+            Ok(self.uid.to_string().into_bytes())
+        } else {
+            Err("EXTERNAL takes no challenge".to_string())
+        }
+    }
+}
+
+/// Server side of EXTERNAL, checking the claimed uid against the peer credentials
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExternalServer {
+    peer_uid: Option<u32>,
+    uid: Option<u32>,
+}
+
+impl ExternalServer {
+    /// `peer_uid` is the uid of the peer as reported by the transport, if any
+    pub fn new(peer_uid: Option<u32>) -> Self {
+        Self {
+            peer_uid,
+            uid: None,
+        }
+    }
+
+    fn authorize(&mut self, data: &[u8]) -> ServerStep {
+        let peer_uid = match self.peer_uid {
+            Some(peer_uid) => peer_uid,
+            None => return ServerStep::Reject,
+        };
+
+        // An empty identity means the one from the credentials
+        if data.is_empty() || parse_uid(data) == Some(peer_uid) {
+            self.uid = Some(peer_uid);
+            ServerStep::Accept
+        } else {
+            ServerStep::Reject
+        }
+    }
+}
+
+impl ServerMechanism for ExternalServer {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn start(&mut self, initial_response: Option<&[u8]>) -> ServerStep {
+        self.uid = None;
+        match initial_response {
+            Some(data) => self.authorize(data),
+            None => ServerStep::Challenge(vec![]),
+        }
+    }
+
+    fn response(&mut self, data: &[u8]) -> ServerStep {
+        self.authorize(data)
+    }
+
+    fn uid(&self) -> Option<u32> {
+        self.uid
+    }
+}
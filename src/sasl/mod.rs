@@ -0,0 +1,70 @@
+//! The line-based SASL handshake run before any message is exchanged
+//!
+//! The state machines only produce and consume [`Command`]s, leaving the
+//! transport to the caller.
+
+mod anonymous;
+mod client;
+mod command;
+mod cookie_sha1;
+mod external;
+mod server;
+
+pub use self::anonymous::*;
+pub use self::client::*;
+pub use self::command::*;
+pub use self::cookie_sha1::*;
+pub use self::external::*;
+pub use self::server::*;
+
+use failure_derive::Fail;
+
+#[derive(Debug, Fail)]
+pub enum SaslError {
+    #[fail(display = "The peer sent a command unexpected at this point: {}", _0)]
+    UnexpectedCommand(String),
+    #[fail(display = "The server accepted none of the available mechanisms")]
+    NoMechanismLeft,
+    #[fail(display = "The client began the session without being authenticated")]
+    NotAuthenticated,
+    #[fail(display = "The handshake is already over")]
+    Finished,
+}
+
+/// Client side of an authentication mechanism
+pub trait ClientMechanism {
+    /// Name of the mechanism in `AUTH` and `REJECTED` commands
+    fn name(&self) -> &str;
+
+    /// Response sent along with `AUTH`, if the mechanism has one
+    fn initial_response(&mut self) -> Option<Vec<u8>>;
+
+    /// Answers a challenge from the server, or explains why it cannot
+    fn challenge(&mut self, data: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+/// Outcome of the server side of a mechanism handling a client response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerStep {
+    /// Sends another challenge to the client
+    Challenge(Vec<u8>),
+    Accept,
+    Reject,
+}
+
+/// Server side of an authentication mechanism
+pub trait ServerMechanism {
+    /// Name of the mechanism in `AUTH` and `REJECTED` commands
+    fn name(&self) -> &str;
+
+    /// Starts an exchange, with the initial response of the `AUTH` command if any
+    fn start(&mut self, initial_response: Option<&[u8]>) -> ServerStep;
+
+    /// Handles the client answer to the last challenge
+    fn response(&mut self, data: &[u8]) -> ServerStep;
+
+    /// Uid the client was authenticated as, for mechanisms that establish one
+    fn uid(&self) -> Option<u32> {
+        None
+    }
+}
@@ -0,0 +1,136 @@
+use super::{Command, SaslError, ServerMechanism, ServerStep};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ServerState {
+    WaitingForAuth,
+    WaitingForData,
+    WaitingForBegin,
+    Authenticated,
+}
+
+/// Server side of the handshake, offering a set of mechanisms
+pub struct SaslServer {
+    guid: String,
+    mechanisms: Vec<Box<dyn ServerMechanism>>,
+    current: Option<usize>,
+    state: ServerState,
+    unix_fd_supported: bool,
+    unix_fd: bool,
+}
+
+impl SaslServer {
+    /// Creates a server announcing `guid` to the clients it accepts
+    pub fn new(guid: String, mechanisms: Vec<Box<dyn ServerMechanism>>) -> Self {
+        Self {
+            guid,
+            mechanisms,
+            current: None,
+            state: ServerState::WaitingForAuth,
+            unix_fd_supported: false,
+            unix_fd: false,
+        }
+    }
+
+    /// Agrees to pass file descriptors when the client asks for it
+    pub fn with_unix_fd_support(self) -> Self {
+        Self {
+            unix_fd_supported: true,
+            ..self
+        }
+    }
+
+    /// Handles a line from the client, returning the line to answer with
+    ///
+    /// Nothing is answered to `BEGIN`, after which messages flow.
+    pub fn handle(&mut self, command: Command) -> Result<Option<Command>, SaslError> {
+        match (self.state, command) {
+            (ServerState::Authenticated, _) => Err(SaslError::Finished),
+            (_, Command::Begin) if self.state != ServerState::WaitingForBegin => {
+                Err(SaslError::NotAuthenticated)
+            }
+            (
+                ServerState::WaitingForAuth,
+                Command::Auth {
+                    mechanism: Some(name),
+                    initial_response,
+                },
+            ) => match self.mechanisms.iter().position(|m| m.name() == name) {
+                Some(i) => {
+                    self.current = Some(i);
+                    let step = self.mechanisms[i].start(initial_response.as_deref());
+                    Ok(Some(self.step(step)))
+                }
+                None => Ok(Some(self.rejected())),
+            },
+            (ServerState::WaitingForAuth, Command::Auth { .. })
+            | (ServerState::WaitingForAuth, Command::Error(_))
+            | (ServerState::WaitingForData, Command::Cancel)
+            | (ServerState::WaitingForData, Command::Error(_))
+            | (ServerState::WaitingForBegin, Command::Cancel)
+            | (ServerState::WaitingForBegin, Command::Error(_)) => Ok(Some(self.rejected())),
+            (ServerState::WaitingForData, Command::Data(data)) => {
+                let step = match self.current {
+                    Some(i) => self.mechanisms[i].response(&data),
+                    None => ServerStep::Reject,
+                };
+                Ok(Some(self.step(step)))
+            }
+            (ServerState::WaitingForBegin, Command::Begin) => {
+                self.state = ServerState::Authenticated;
+                Ok(None)
+            }
+            (ServerState::WaitingForBegin, Command::NegotiateUnixFd) if self.unix_fd_supported => {
+                self.unix_fd = true;
+                Ok(Some(Command::AgreeUnixFd))
+            }
+            (ServerState::WaitingForBegin, Command::NegotiateUnixFd) => Ok(Some(Command::Error(
+                "File descriptor passing is not supported".to_string(),
+            ))),
+            (_, _) => Ok(Some(Command::Error("Unexpected command".to_string()))),
+        }
+    }
+
+    fn step(&mut self, step: ServerStep) -> Command {
+        match step {
+            ServerStep::Challenge(data) => {
+                self.state = ServerState::WaitingForData;
+                Command::Data(data)
+            }
+            ServerStep::Accept => {
+                self.state = ServerState::WaitingForBegin;
+                Command::Ok(self.guid.clone())
+            }
+            ServerStep::Reject => self.rejected(),
+        }
+    }
+
+    fn rejected(&mut self) -> Command {
+        self.state = ServerState::WaitingForAuth;
+        self.current = None;
+        Command::Rejected(
+            self.mechanisms
+                .iter()
+                .map(|m| m.name().to_string())
+                .collect(),
+        )
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.state == ServerState::Authenticated
+    }
+
+    /// Mechanism the client authenticated with
+    pub fn mechanism(&self) -> Option<&dyn ServerMechanism> {
+        match self.state {
+            ServerState::WaitingForBegin | ServerState::Authenticated => {
+                self.current.map(|i| &*self.mechanisms[i])
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the client negotiated file descriptor passing
+    pub fn unix_fd_agreed(&self) -> bool {
+        self.unix_fd
+    }
+}
@@ -1,8 +1,8 @@
 use crate::error::DbusParseError;
-use crate::header::components::MessageEndianness;
-use crate::types::basic::*;
+use crate::parse_context::ParseContext;
+use crate::types::{basic::*, borrowed::DbusBorrowedTypeContainer, containers::*};
 use crate::{DbusType, DbusTypeContainer};
-use nom::combinator::map_res;
+use nom::combinator::{map, map_res};
 use std::convert::TryFrom;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,7 +17,7 @@ pub enum SignatureType {
     Int32 = b'i',
     Uint64 = b't',
     Int64 = b'x',
-    Double = b'f',
+    Double = b'd',
     UnixFd = b'h',
     Signature = b'g',
     String = b's',
@@ -50,7 +50,7 @@ impl TryFrom<u8> for SignatureType {
             b'i' => Ok(SignatureType::Int32),
             b't' => Ok(SignatureType::Uint64),
             b'x' => Ok(SignatureType::Int64),
-            b'f' => Ok(SignatureType::Double),
+            b'd' => Ok(SignatureType::Double),
             b'h' => Ok(SignatureType::UnixFd),
             b'g' => Ok(SignatureType::Signature),
             b's' => Ok(SignatureType::String),
@@ -73,72 +73,65 @@ impl TryFrom<u8> for SignatureType {
 }
 
 impl SignatureType {
+    /// Decodes a basic type, returns `None` for container types
     fn parse_buffer<'a>(
         &self,
         buf: &'a [u8],
-        endianness: MessageEndianness,
+        ctx: &ParseContext,
     ) -> Option<nom::IResult<&'a [u8], DbusTypeContainer>> {
         match self {
             SignatureType::Boolean => Some(map_res(
-                |buf| DbusBoolean::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusBoolean::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Byte => Some(map_res(
-                |buf| DbusByte::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusByte::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Uint16 => Some(map_res(
-                |buf| DbusUint16::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusUint16::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Int16 => Some(map_res(
-                |buf| DbusInt16::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusInt16::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Uint32 => Some(map_res(
-                |buf| DbusUint32::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusUint32::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Int32 => Some(map_res(
-                |buf| DbusInt32::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusInt32::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Uint64 => Some(map_res(
-                |buf| DbusUint64::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusUint64::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Int64 => Some(map_res(
-                |buf| DbusInt64::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusInt64::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Double => Some(map_res(
-                |buf| DbusDouble::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusDouble::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::UnixFd => Some(map_res(
-                |buf| DbusUnixFd::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusUnixFd::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::Signature => Some(map_res(
-                |buf| DbusSignature::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusSignature::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::String => Some(map_res(
-                |buf| DbusString::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusString::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
             SignatureType::ObjectPath => Some(map_res(
-                |buf| DbusObjectPath::unmarshal(buf, endianness, &(*self).into()),
+                |buf| DbusObjectPath::unmarshal(buf, ctx, &(*self).into()),
                 DbusTypeContainer::try_from,
             )(buf)),
-            SignatureType::Array => unimplemented!(),
-            SignatureType::Variant => unimplemented!(),
-            SignatureType::StructStart => unimplemented!(),
-            SignatureType::DictStart => unimplemented!(),
-            SignatureType::GVariant => unimplemented!(),
-            SignatureType::GVariantType => unimplemented!(),
-            SignatureType::GVariantPointer => unimplemented!(),
-            SignatureType::GVariantConversion => unimplemented!(),
             _ => None,
         }
     }
@@ -147,10 +140,109 @@ impl SignatureType {
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Signature(Vec<SignatureType>);
 
+impl SignatureType {
+    /// Alignment of values of this type, for container types the alignment of the container itself
+    pub fn alignment(self) -> usize {
+        match self {
+            SignatureType::Byte | SignatureType::Signature | SignatureType::Variant => 1,
+            SignatureType::Uint16 | SignatureType::Int16 => 2,
+            SignatureType::Uint64
+            | SignatureType::Int64
+            | SignatureType::Double
+            | SignatureType::StructStart
+            | SignatureType::DictStart => 8,
+            _ => 4,
+        }
+    }
+
+    /// Wire size of fixed-size basic types, `None` for every other type
+    pub fn fixed_size(self) -> Option<usize> {
+        match self {
+            SignatureType::Byte => Some(1),
+            SignatureType::Uint16 | SignatureType::Int16 => Some(2),
+            SignatureType::Boolean
+            | SignatureType::Uint32
+            | SignatureType::Int32
+            | SignatureType::UnixFd => Some(4),
+            SignatureType::Uint64 | SignatureType::Int64 | SignatureType::Double => Some(8),
+            _ => None,
+        }
+    }
+
+    /// Whether the type is a basic (non-container) type, usable as a dict key
+    pub fn is_basic(self) -> bool {
+        matches!(
+            self,
+            SignatureType::Boolean
+                | SignatureType::Byte
+                | SignatureType::Uint16
+                | SignatureType::Int16
+                | SignatureType::Uint32
+                | SignatureType::Int32
+                | SignatureType::Uint64
+                | SignatureType::Int64
+                | SignatureType::Double
+                | SignatureType::UnixFd
+                | SignatureType::Signature
+                | SignatureType::String
+                | SignatureType::ObjectPath
+        )
+    }
+}
+
+/// Returns the length of the single complete type at the start of `signature`
+pub(crate) fn complete_type_len(signature: &[SignatureType]) -> Result<usize, DbusParseError> {
+    match signature.first() {
+        Some(SignatureType::Variant) => Ok(1),
+        Some(t) if t.is_basic() => Ok(1),
+        Some(SignatureType::Array) => Ok(1 + complete_type_len(&signature[1..])?),
+        Some(SignatureType::StructStart) => {
+            let mut len = 1;
+            while signature.get(len) != Some(&SignatureType::StructEnd) {
+                len += complete_type_len(&signature[len..])?;
+            }
+
+            // Empty structures are not allowed
+            if len == 1 {
+                return Err(DbusParseError::InvalidSignature);
+            }
+
+            Ok(len + 1)
+        }
+        Some(SignatureType::DictStart) => {
+            // Dict entry keys must be basic types
+            if !signature.get(1).is_some_and(|t| t.is_basic()) {
+                return Err(DbusParseError::InvalidSignature);
+            }
+
+            let len = 2 + complete_type_len(&signature[2..])?;
+            if signature.get(len) != Some(&SignatureType::DictEnd) {
+                return Err(DbusParseError::InvalidSignature);
+            }
+
+            Ok(len + 1)
+        }
+        _ => Err(DbusParseError::InvalidSignature),
+    }
+}
+
 impl Signature {
     pub fn new(signature: Vec<SignatureType>) -> Self {
         Signature(signature)
     }
+
+    /// Splits the signature into its single complete types
+    pub fn split_complete_types(&self) -> Result<Vec<Signature>, DbusParseError> {
+        let mut ret = vec![];
+        let mut rest = &self.0[..];
+        while !rest.is_empty() {
+            let len = complete_type_len(rest)?;
+            ret.push(Signature(rest[..len].to_vec()));
+            rest = &rest[len..];
+        }
+
+        Ok(ret)
+    }
 }
 
 impl From<SignatureType> for Signature {
@@ -159,6 +251,14 @@ impl From<SignatureType> for Signature {
     }
 }
 
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0
+            .iter()
+            .try_for_each(|t| write!(f, "{}", *t as u8 as char))
+    }
+}
+
 impl std::ops::Deref for Signature {
     type Target = Vec<SignatureType>;
 
@@ -173,23 +273,132 @@ impl std::ops::DerefMut for Signature {
     }
 }
 
+impl std::str::FromStr for Signature {
+    type Err = DbusParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Signatures are limited to 255 bytes
+        if s.len() > 255 {
+            return Err(DbusParseError::InvalidSignature);
+        }
+
+        let signature = Signature(
+            s.bytes()
+                .map(SignatureType::try_from)
+                .collect::<Result<_, _>>()?,
+        );
+        signature.split_complete_types()?;
+        Ok(signature)
+    }
+}
+
 impl Signature {
+    /// Decodes one value per single complete type of the signature
     pub fn parse_buffer<'a>(
         &self,
         buf: &'a [u8],
-        endianness: MessageEndianness,
+        ctx: &ParseContext,
     ) -> nom::IResult<&'a [u8], Vec<DbusTypeContainer>> {
-        let init = (buf, Vec::with_capacity(self.0.len()));
-        self.0
+        let types = self
+            .split_complete_types()
+            .map_err(|_| nom::Err::Failure((buf, nom::error::ErrorKind::Verify)))?;
+        let init = (buf, Vec::with_capacity(types.len()));
+        types
             .iter()
-            .try_fold(init, move |(buf, mut ret), signature_type| {
-                if let Some(parse_result) = signature_type.parse_buffer(buf, endianness) {
-                    let (buf, container) = parse_result?;
-                    ret.push(container);
-                    Ok((buf, ret))
-                } else {
-                    Ok((buf, ret))
-                }
+            .try_fold(init, move |(buf, mut ret), signature| {
+                let (buf, container) = signature.parse_complete_type(buf, ctx)?;
+                ret.push(container);
+                Ok((buf, ret))
             })
     }
+
+    /// Decodes one value per single complete type, borrowing strings and byte arrays from `buf`
+    pub fn parse_buffer_borrowed<'a>(
+        &self,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+    ) -> nom::IResult<&'a [u8], Vec<DbusBorrowedTypeContainer<'a>>> {
+        let mut ret = vec![];
+        let mut buf = buf;
+        let mut rest = &self.0[..];
+        while !rest.is_empty() {
+            let len = complete_type_len(rest)
+                .map_err(|_| nom::Err::Failure((buf, nom::error::ErrorKind::Verify)))?;
+            let (next, value) = DbusBorrowedTypeContainer::unmarshal(buf, ctx, &rest[..len])?;
+            ret.push(value);
+            rest = &rest[len..];
+            buf = next;
+        }
+
+        Ok((buf, ret))
+    }
+
+    /// Decodes a value of a signature holding a single complete type
+    pub(crate) fn parse_complete_type<'a>(
+        &self,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+    ) -> nom::IResult<&'a [u8], DbusTypeContainer> {
+        match self.0[0] {
+            SignatureType::Variant => DbusVariant::unmarshal(buf, ctx, self)
+                .map(|(buf, v)| (buf, DbusTypeContainer::Variant(Box::new(v)))),
+            SignatureType::Array if self.0.len() == 2 => match self.0[1] {
+                SignatureType::Byte => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::ByteArray,
+                )(buf),
+                SignatureType::Uint16 => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::Uint16Array,
+                )(buf),
+                SignatureType::Int16 => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::Int16Array,
+                )(buf),
+                SignatureType::Uint32 => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::Uint32Array,
+                )(buf),
+                SignatureType::Int32 => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::Int32Array,
+                )(buf),
+                SignatureType::Uint64 => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::Uint64Array,
+                )(buf),
+                SignatureType::Int64 => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::Int64Array,
+                )(buf),
+                SignatureType::Double => map(
+                    |buf| unmarshal_fixed_array(buf, ctx),
+                    DbusTypeContainer::DoubleArray,
+                )(buf),
+                _ => {
+                    let element = Signature(self.0[1..].to_vec());
+                    DbusArray::unmarshal(buf, ctx, &element)
+                        .map(|(buf, v)| (buf, DbusTypeContainer::Array(v)))
+                }
+            },
+            SignatureType::Array if self.0[1] == SignatureType::DictStart => {
+                let entry = Signature(self.0[2..self.0.len() - 1].to_vec());
+                DbusDict::unmarshal(buf, ctx, &entry)
+                    .map(|(buf, v)| (buf, DbusTypeContainer::Dict(v)))
+            }
+            SignatureType::Array => {
+                let element = Signature(self.0[1..].to_vec());
+                DbusArray::unmarshal(buf, ctx, &element)
+                    .map(|(buf, v)| (buf, DbusTypeContainer::Array(v)))
+            }
+            SignatureType::StructStart => {
+                let fields = Signature(self.0[1..self.0.len() - 1].to_vec());
+                DbusStruct::unmarshal(buf, ctx, &fields)
+                    .map(|(buf, v)| (buf, DbusTypeContainer::Struct(v)))
+            }
+            signature_type => signature_type
+                .parse_buffer(buf, ctx)
+                .unwrap_or(Err(nom::Err::Failure((buf, nom::error::ErrorKind::Verify)))),
+        }
+    }
 }
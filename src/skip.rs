@@ -0,0 +1,246 @@
+use crate::parse_context::{padding, ParseContext};
+use crate::signature_type::{complete_type_len, Signature, SignatureType};
+use crate::types::basic::*;
+use nom::bytes::streaming::take;
+use nom::number::streaming::le_u8;
+use nom::IResult;
+use std::convert::TryFrom;
+
+fn verify(buf: &[u8]) -> nom::Err<(&[u8], nom::error::ErrorKind)> {
+    nom::Err::Error((buf, nom::error::ErrorKind::Verify))
+}
+
+/// Called with the offset and size of every multi-byte number walked over, lengths included
+///
+/// Numbers are only reported once they are known to lie within the input.
+pub(crate) type Visitor<'v> = &'v mut dyn FnMut(usize, usize);
+
+/// Offset of the next value aligned to `alignment`
+fn aligned_offset(buf: &[u8], ctx: &ParseContext, alignment: usize) -> usize {
+    let offset = ctx.offset(buf);
+    offset + padding(offset, alignment)
+}
+
+/// Walks over a value of the single complete type `signature` without decoding it
+///
+/// Lengths, alignment and limits are always honoured. With `check` set, the
+/// contents are verified as well: UTF-8 and nul bytes in strings, object path
+/// and signature grammar, and boolean values.
+fn skip_complete_type<'a>(
+    signature: &[SignatureType],
+    buf: &'a [u8],
+    ctx: &ParseContext,
+    check: bool,
+    visit: Visitor,
+) -> IResult<&'a [u8], ()> {
+    if matches!(
+        signature[0],
+        SignatureType::String | SignatureType::ObjectPath | SignatureType::Array
+    ) {
+        // The length has to be there before it is reported
+        ctx.align_u32(buf)?;
+        visit(aligned_offset(buf, ctx, 4), 4);
+    }
+
+    match signature[0] {
+        SignatureType::String if check => {
+            let (buf, _) = unmarshal_str(buf, ctx)?;
+            Ok((buf, ()))
+        }
+        SignatureType::ObjectPath if check => {
+            let (buf, path) = unmarshal_str(buf, ctx)?;
+            validate_object_path(path).map_err(|_| verify(buf))?;
+            Ok((buf, ()))
+        }
+        SignatureType::String | SignatureType::ObjectPath => {
+            let (buf, len) = ctx.align_u32(buf)?;
+            let (buf, _) = take(len as usize + 1)(buf)?;
+            Ok((buf, ()))
+        }
+        SignatureType::Signature if check => {
+            let (buf, s) = unmarshal_signature_str(buf)?;
+            s.parse::<Signature>().map_err(|_| verify(buf))?;
+            Ok((buf, ()))
+        }
+        SignatureType::Signature => {
+            let (buf, len) = le_u8(buf)?;
+            let (buf, _) = take(len as usize + 1)(buf)?;
+            Ok((buf, ()))
+        }
+        SignatureType::Variant => {
+            let (buf, ctx) = ctx.nest(buf)?;
+            // The contained signature is needed to know what to skip
+            let (buf, s) = unmarshal_signature_str(buf)?;
+            skip_variant_contents(s, buf, &ctx, check, visit)
+        }
+        SignatureType::Array => {
+            let (buf, ctx) = ctx.nest(buf)?;
+            let element = &signature[1..];
+            match element[0].fixed_size() {
+                // Booleans are the only fixed-size type whose contents can be invalid
+                Some(size) if !check || element[0] != SignatureType::Boolean => {
+                    let (buf, len) = ctx.array_len(buf)?;
+                    let (buf, _) = ctx.align(buf, element[0].alignment())?;
+                    if len % size != 0 {
+                        return Err(verify(buf));
+                    }
+
+                    let start = ctx.offset(buf);
+                    let (buf, _) = take(len)(buf)?;
+                    if size > 1 {
+                        (start..start + len)
+                            .step_by(size)
+                            .for_each(|offset| visit(offset, size));
+                    }
+
+                    Ok((buf, ()))
+                }
+                _ if element[0] == SignatureType::DictStart => {
+                    let entry = &element[1..element.len() - 1];
+                    let key_len = complete_type_len(entry).map_err(|_| verify(buf))?;
+                    ctx.for_each_element(buf, 8, |buf| {
+                        let (buf, _) = ctx.align(buf, 8)?;
+                        let (buf, _) =
+                            skip_complete_type(&entry[..key_len], buf, &ctx, check, visit)?;
+                        skip_complete_type(&entry[key_len..], buf, &ctx, check, visit)
+                    })
+                }
+                _ => ctx.for_each_element(buf, element[0].alignment(), |buf| {
+                    skip_complete_type(element, buf, &ctx, check, visit)
+                }),
+            }
+        }
+        SignatureType::StructStart => {
+            let (buf, ctx) = ctx.nest(buf)?;
+            let (buf, _) = ctx.align(buf, 8)?;
+            skip_sequence(&signature[1..signature.len() - 1], buf, &ctx, check, visit)
+        }
+        signature_type => {
+            let size = signature_type.fixed_size().ok_or_else(|| verify(buf))?;
+            let (buf, _) = ctx.align(buf, signature_type.alignment())?;
+            let offset = ctx.offset(buf);
+            let (buf, value) = take(size)(buf)?;
+            if size > 1 {
+                visit(offset, size);
+            }
+            if check && signature_type == SignatureType::Boolean {
+                let (_, v) = ctx.u32(value)?;
+                if v > 1 {
+                    return Err(verify(buf));
+                }
+            }
+
+            Ok((buf, ()))
+        }
+    }
+}
+
+/// Longest signature allowed by the specification
+const MAX_SIGNATURE_LEN: usize = 255;
+
+/// Walks over the value of a variant whose signature is `s`
+///
+/// The signature is read into a buffer on the stack rather than a
+/// [`Signature`], so that skipping never allocates.
+fn skip_variant_contents<'a>(
+    s: &str,
+    buf: &'a [u8],
+    ctx: &ParseContext,
+    check: bool,
+    visit: Visitor,
+) -> IResult<&'a [u8], ()> {
+    let mut storage = [SignatureType::Byte; MAX_SIGNATURE_LEN];
+    if s.len() > storage.len() {
+        return Err(verify(buf));
+    }
+
+    for (slot, b) in storage.iter_mut().zip(s.bytes()) {
+        *slot = SignatureType::try_from(b).map_err(|_| verify(buf))?;
+    }
+
+    // A variant holds exactly one single complete type
+    let signature = &storage[..s.len()];
+    if complete_type_len(signature).ok() != Some(signature.len()) {
+        return Err(verify(buf));
+    }
+
+    skip_complete_type(signature, buf, ctx, check, visit)
+}
+
+/// Moves past the value of a variant whose signature is `s`, without allocating
+pub(crate) fn skip_variant<'a>(
+    s: &str,
+    buf: &'a [u8],
+    ctx: &ParseContext,
+) -> IResult<&'a [u8], ()> {
+    skip_variant_contents(s, buf, ctx, false, &mut |_, _| ())
+}
+
+fn skip_sequence<'a>(
+    mut signature: &[SignatureType],
+    mut buf: &'a [u8],
+    ctx: &ParseContext,
+    check: bool,
+    visit: Visitor,
+) -> IResult<&'a [u8], ()> {
+    while !signature.is_empty() {
+        let len = complete_type_len(signature).map_err(|_| verify(buf))?;
+        let (rest, _) = skip_complete_type(&signature[..len], buf, ctx, check, visit)?;
+        signature = &signature[len..];
+        buf = rest;
+    }
+
+    Ok((buf, ()))
+}
+
+impl Signature {
+    /// Moves past one value per single complete type without decoding them
+    ///
+    /// Only the framing is looked at, string contents are not checked.
+    pub fn skip<'a>(&self, buf: &'a [u8], ctx: &ParseContext) -> IResult<&'a [u8], ()> {
+        skip_sequence(self, buf, ctx, false, &mut |_, _| ())
+    }
+
+    /// Checks that `buf` holds valid values for the signature, without decoding them
+    pub fn validate<'a>(&self, buf: &'a [u8], ctx: &ParseContext) -> IResult<&'a [u8], ()> {
+        skip_sequence(self, buf, ctx, true, &mut |_, _| ())
+    }
+
+    /// Walks over the values like [`skip`](Self::skip), reporting every multi-byte number
+    pub(crate) fn walk<'a>(
+        &self,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+        visit: Visitor,
+    ) -> IResult<&'a [u8], ()> {
+        skip_sequence(self, buf, ctx, false, visit)
+    }
+
+    /// Moves past the first `n` values, and the padding in front of the next one
+    ///
+    /// Fails if the signature holds `n` values or fewer.
+    pub fn skip_to<'a>(
+        &self,
+        buf: &'a [u8],
+        ctx: &ParseContext,
+        n: usize,
+    ) -> IResult<&'a [u8], ()> {
+        let mut signature = &self[..];
+        let mut buf = buf;
+        for _ in 0..n {
+            if signature.is_empty() {
+                break;
+            }
+
+            let len = complete_type_len(signature).map_err(|_| verify(buf))?;
+            let (rest, _) = skip_complete_type(&signature[..len], buf, ctx, false, &mut |_, _| ())?;
+            signature = &signature[len..];
+            buf = rest;
+        }
+
+        match signature.first() {
+            Some(signature_type) => ctx.align(buf, signature_type.alignment()),
+            None => Err(nom::Err::Error((buf, nom::error::ErrorKind::Eof))),
+        }
+    }
+}
@@ -1,9 +1,10 @@
+use crate::signature_type::{Signature, SignatureType};
 use crate::types::{basic::*, containers::*};
 use crate::DbusParseError;
 use std::convert::TryFrom;
 
 macro_rules! impl_from_iresult_type {
-    ($container:ident, $variant:ident, $type:ident) => {
+    ($container:ident, $variant:ident, $type:ty) => {
         impl TryFrom<$type> for $container {
             type Error = DbusParseError;
             fn try_from(v: $type) -> Result<Self, Self::Error> {
@@ -22,7 +23,7 @@ macro_rules! impl_from_iresult_type {
         }
     };
 
-    (box $container:ident, $variant:ident, $type:ident) => {
+    (box $container:ident, $variant:ident, $type:ty) => {
         impl TryFrom<$type> for $container {
             type Error = DbusParseError;
             fn try_from(v: $type) -> Result<Self, Self::Error> {
@@ -59,6 +60,69 @@ pub enum DbusTypeContainer {
     String(DbusString),
     ObjectPath(DbusObjectPath),
     Variant(Box<DbusVariant>),
+    Array(DbusArray),
+    Struct(DbusStruct),
+    Dict(DbusDict),
+    ByteArray(Vec<u8>),
+    Uint16Array(Vec<u16>),
+    Int16Array(Vec<i16>),
+    Uint32Array(Vec<u32>),
+    Int32Array(Vec<i32>),
+    Uint64Array(Vec<u64>),
+    Int64Array(Vec<i64>),
+    DoubleArray(Vec<f64>),
+}
+
+impl DbusTypeContainer {
+    /// Computes the signature of the contained value
+    pub fn signature(&self) -> Signature {
+        match self {
+            DbusTypeContainer::Boolean(_) => SignatureType::Boolean.into(),
+            DbusTypeContainer::Byte(_) => SignatureType::Byte.into(),
+            DbusTypeContainer::Uint16(_) => SignatureType::Uint16.into(),
+            DbusTypeContainer::Int16(_) => SignatureType::Int16.into(),
+            DbusTypeContainer::Uint32(_) => SignatureType::Uint32.into(),
+            DbusTypeContainer::Int32(_) => SignatureType::Int32.into(),
+            DbusTypeContainer::Uint64(_) => SignatureType::Uint64.into(),
+            DbusTypeContainer::Int64(_) => SignatureType::Int64.into(),
+            DbusTypeContainer::Double(_) => SignatureType::Double.into(),
+            DbusTypeContainer::UnixFd(_) => SignatureType::UnixFd.into(),
+            DbusTypeContainer::Signature(_) => SignatureType::Signature.into(),
+            DbusTypeContainer::String(_) => SignatureType::String.into(),
+            DbusTypeContainer::ObjectPath(_) => SignatureType::ObjectPath.into(),
+            DbusTypeContainer::Variant(_) => SignatureType::Variant.into(),
+            DbusTypeContainer::Array(array) => {
+                let mut signature = Signature::from(SignatureType::Array);
+                signature.extend_from_slice(array.element_signature());
+                signature
+            }
+            DbusTypeContainer::Struct(structure) => {
+                let mut signature = Signature::from(SignatureType::StructStart);
+                structure
+                    .iter()
+                    .for_each(|v| signature.extend_from_slice(&v.signature()));
+                signature.push(SignatureType::StructEnd);
+                signature
+            }
+            DbusTypeContainer::Dict(dict) => {
+                let mut signature = Signature::from(SignatureType::Array);
+                signature.extend_from_slice(&dict.entry_signature());
+                signature
+            }
+            DbusTypeContainer::ByteArray(_) => Self::fixed_array_signature::<u8>(),
+            DbusTypeContainer::Uint16Array(_) => Self::fixed_array_signature::<u16>(),
+            DbusTypeContainer::Int16Array(_) => Self::fixed_array_signature::<i16>(),
+            DbusTypeContainer::Uint32Array(_) => Self::fixed_array_signature::<u32>(),
+            DbusTypeContainer::Int32Array(_) => Self::fixed_array_signature::<i32>(),
+            DbusTypeContainer::Uint64Array(_) => Self::fixed_array_signature::<u64>(),
+            DbusTypeContainer::Int64Array(_) => Self::fixed_array_signature::<i64>(),
+            DbusTypeContainer::DoubleArray(_) => Self::fixed_array_signature::<f64>(),
+        }
+    }
+
+    fn fixed_array_signature<T: DbusFixedType>() -> Signature {
+        Signature::new(vec![SignatureType::Array, T::SIGNATURE])
+    }
 }
 
 impl_from_iresult_type!(DbusTypeContainer, Boolean, DbusBoolean);
@@ -75,3 +139,14 @@ impl_from_iresult_type!(DbusTypeContainer, Signature, DbusSignature);
 impl_from_iresult_type!(DbusTypeContainer, String, DbusString);
 impl_from_iresult_type!(DbusTypeContainer, ObjectPath, DbusObjectPath);
 impl_from_iresult_type!(box DbusTypeContainer, Variant, DbusVariant);
+impl_from_iresult_type!(DbusTypeContainer, Array, DbusArray);
+impl_from_iresult_type!(DbusTypeContainer, Struct, DbusStruct);
+impl_from_iresult_type!(DbusTypeContainer, Dict, DbusDict);
+impl_from_iresult_type!(DbusTypeContainer, ByteArray, Vec<u8>);
+impl_from_iresult_type!(DbusTypeContainer, Uint16Array, Vec<u16>);
+impl_from_iresult_type!(DbusTypeContainer, Int16Array, Vec<i16>);
+impl_from_iresult_type!(DbusTypeContainer, Uint32Array, Vec<u32>);
+impl_from_iresult_type!(DbusTypeContainer, Int32Array, Vec<i32>);
+impl_from_iresult_type!(DbusTypeContainer, Uint64Array, Vec<u64>);
+impl_from_iresult_type!(DbusTypeContainer, Int64Array, Vec<i64>);
+impl_from_iresult_type!(DbusTypeContainer, DoubleArray, Vec<f64>);
@@ -1,8 +1,7 @@
-use crate::header::components::MessageEndianness;
+use crate::parse_context::ParseContext;
 use crate::signature_type::Signature;
 use crate::DbusType;
-use nom::combinator::map;
-use nom::number::streaming::{be_u32, le_u32};
+use nom::combinator::map_opt;
 use nom::IResult;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -11,17 +10,27 @@ pub struct DbusBoolean(bool);
 impl DbusType for DbusBoolean {
     const ALIGNMENT: usize = 4;
 
-    fn unmarshal<'a, 'b>(
-        buf: &'b [u8],
-        endianness: MessageEndianness,
-        _: &'a Signature,
-    ) -> IResult<&'b [u8], Self> {
-        map(
-            match endianness {
-                MessageEndianness::BigEndian => be_u32,
-                MessageEndianness::LittleEndian => le_u32,
+    fn unmarshal<'b>(buf: &'b [u8], ctx: &ParseContext, _: &Signature) -> IResult<&'b [u8], Self> {
+        // Only 0 and 1 are valid boolean values
+        map_opt(
+            |buf| ctx.align_u32(buf),
+            |v| match v {
+                0 => Some(DbusBoolean(false)),
+                1 => Some(DbusBoolean(true)),
+                _ => None,
             },
-            |v: u32| DbusBoolean(v != 0u32),
         )(buf)
     }
 }
+
+impl From<bool> for DbusBoolean {
+    fn from(v: bool) -> Self {
+        Self(v)
+    }
+}
+
+impl From<DbusBoolean> for bool {
+    fn from(v: DbusBoolean) -> Self {
+        v.0
+    }
+}
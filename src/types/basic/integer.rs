@@ -1,4 +1,5 @@
 use crate::header::components::MessageEndianness;
+use crate::parse_context::ParseContext;
 use crate::signature_type::Signature;
 use crate::DbusType;
 use nom::combinator::map;
@@ -10,13 +11,14 @@ macro_rules! __inner_impl_parsable {
         impl DbusType for $name {
             const ALIGNMENT: usize = $align;
 
-            fn unmarshal<'a, 'b>(
+            fn unmarshal<'b>(
                 buf: &'b [u8],
-                endianness: MessageEndianness,
-                _: &'a Signature,
+                ctx: &ParseContext,
+                _: &Signature,
             ) -> IResult<&'b [u8], Self> {
+                let (buf, _) = ctx.align(buf, Self::ALIGNMENT)?;
                 map(
-                    match endianness {
+                    match ctx.endianness() {
                         MessageEndianness::BigEndian => $mthd_be,
                         MessageEndianness::LittleEndian => $mthd_le,
                     },
@@ -31,9 +33,9 @@ macro_rules! __inner_impl_parsable {
             }
         }
 
-        impl Into<$inner> for $name {
-            fn into(self) -> $inner {
-                self.0
+        impl From<$name> for $inner {
+            fn from(v: $name) -> Self {
+                v.0
             }
         }
     };
@@ -1,42 +1,58 @@
 use crate::error::DbusParseError;
-use crate::header::components::MessageEndianness;
-use crate::signature_type::{Signature, SignatureType};
+use crate::parse_context::ParseContext;
+use crate::signature_type::Signature;
 use crate::DbusType;
 
 use nom::bytes::streaming::*;
-use nom::combinator::map;
-use nom::combinator::map_res;
-use nom::number::streaming::{be_u32, le_u32};
+use nom::combinator::{map, map_res};
+use nom::number::streaming::le_u8;
 use nom::IResult;
-use std::convert::{TryFrom, TryInto};
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct DbusSignature(String);
+use std::convert::TryInto;
+
+/// Decodes the characters of a signature without copying them out of `buf`
+///
+/// Signatures are a single length byte followed by ASCII type codes and a
+/// terminating nul byte which is not included in the length.
+pub(crate) fn unmarshal_signature_str(buf: &[u8]) -> IResult<&[u8], &str> {
+    let (buf, len) = map(le_u8, |v| v as usize)(buf)?;
+    let (buf, s) = take(len)(buf)?;
+    if !s.is_ascii() {
+        return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+    }
+
+    let (buf, nul) = take(1usize)(buf)?;
+    if nul[0] != b'\0' {
+        return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+    }
+
+    // ASCII has just been checked
+    Ok((buf, std::str::from_utf8(s).unwrap()))
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DbusSignature(pub(crate) String);
 
 impl DbusType for DbusSignature {
     const ALIGNMENT: usize = 1;
 
-    fn unmarshal<'a, 'b>(
-        buf: &'b [u8],
-        endianness: MessageEndianness,
-        _: &'a Signature,
-    ) -> IResult<&'b [u8], Self> {
-        let (buf, len) = match endianness {
-            MessageEndianness::BigEndian => map(be_u32, |v| v as usize)(buf),
-            MessageEndianness::LittleEndian => map(le_u32, |v| v as usize)(buf),
-        }?;
-
-        let (buf, s) = map(map_res(take(len), std::str::from_utf8), |v| Self(v.into()))(buf)?;
+    fn unmarshal<'b>(buf: &'b [u8], _: &ParseContext, _: &Signature) -> IResult<&'b [u8], Self> {
+        map_res(unmarshal_signature_str, |v| {
+            v.parse::<Signature>().map(|_| Self(v.into()))
+        })(buf)
+    }
+}
 
-        if s.0.len() != len {
-            return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
-        }
+impl std::ops::Deref for DbusSignature {
+    type Target = str;
 
-        let (buf, nul) = take(1usize)(buf)?;
-        if nul.len() != 1 || nul[0] != b'\0' {
-            return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
-        }
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
 
-        Ok((buf, s))
+impl From<&Signature> for DbusSignature {
+    fn from(signature: &Signature) -> Self {
+        Self(signature.to_string())
     }
 }
 
@@ -44,12 +60,6 @@ impl TryInto<Signature> for DbusSignature {
     type Error = DbusParseError;
 
     fn try_into(self) -> Result<Signature, Self::Error> {
-        self.0
-            .chars()
-            .into_iter()
-            .try_fold(Signature::default(), |mut sig, character| {
-                (*sig).push(SignatureType::try_from(character as u8)?);
-                Ok(sig)
-            })
+        self.0.parse()
     }
 }
@@ -1,4 +1,4 @@
-use crate::header::components::MessageEndianness;
+use crate::parse_context::ParseContext;
 use crate::signature_type::Signature;
 use crate::DbusParseError;
 use crate::DbusType;
@@ -6,48 +6,64 @@ use core::convert::TryFrom;
 use nom::bytes::streaming::*;
 use nom::combinator::map;
 use nom::combinator::map_res;
-use nom::number::streaming::{be_u32, le_u32};
 use nom::IResult;
 
+/// Decodes the bytes of a string without copying them out of `buf`
+///
+/// Strings are a 4-aligned `u32` length followed by UTF-8 data and a
+/// terminating nul byte which is not included in the length.
+pub(crate) fn unmarshal_str<'b>(buf: &'b [u8], ctx: &ParseContext) -> IResult<&'b [u8], &'b str> {
+    let (buf, len) = map(|buf| ctx.align_u32(buf), |v| v as usize)(buf)?;
+    let (buf, s) = map_res(take(len), std::str::from_utf8)(buf)?;
+
+    // Strings must not contain any nul byte
+    if s.bytes().any(|b| b == b'\0') {
+        return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+    }
+
+    let (buf, nul) = take(1usize)(buf)?;
+    if nul[0] != b'\0' {
+        return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+    }
+
+    Ok((buf, s))
+}
+
 macro_rules! impl_string_parse {
     ($target:ty) => {
         impl DbusType for $target {
             const ALIGNMENT: usize = 4;
 
-            fn unmarshal<'a, 'b>(
+            fn unmarshal<'b>(
                 buf: &'b [u8],
-                endianness: MessageEndianness,
-                _: &'a Signature,
+                ctx: &ParseContext,
+                _: &Signature,
             ) -> IResult<&'b [u8], Self> {
-                let (buf, len) = match endianness {
-                    MessageEndianness::BigEndian => map(be_u32, |v| v as usize)(buf),
-                    MessageEndianness::LittleEndian => map(le_u32, |v| v as usize)(buf),
-                }?;
-
-                let (buf, s) = map_res(
-                    map(map_res(take(len), std::str::from_utf8), String::from),
+                map_res(
+                    map(|buf| unmarshal_str(buf, ctx), String::from),
                     Self::try_from,
-                )(buf)?;
-
-                if s.0.len() != len {
-                    return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
-                }
+                )(buf)
+            }
+        }
 
-                let pad = 1 + ((len + 1) % Self::ALIGNMENT);
+        impl std::ops::Deref for $target {
+            type Target = str;
 
-                let (buf, rest) = take(pad)(buf)?;
-                if rest.len() != 1 || rest[0] != b'\0' {
-                    return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
-                }
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
 
-                Ok((buf, s))
+        impl From<$target> for String {
+            fn from(v: $target) -> Self {
+                v.0
             }
         }
     };
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct DbusString(String);
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DbusString(pub(crate) String);
 impl_string_parse!(DbusString);
 
 impl From<String> for DbusString {
@@ -56,54 +72,71 @@ impl From<String> for DbusString {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct DbusObjectPath(String);
+impl From<&str> for DbusString {
+    fn from(v: &str) -> Self {
+        Self(v.into())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct DbusObjectPath(pub(crate) String);
 
 impl TryFrom<String> for DbusObjectPath {
     type Error = DbusParseError;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        // The path must begin with an ASCII '/' (integer 47) character, and must consist of elements separated by slash characters.
-        if !value.starts_with("/") {
-            return Err(DbusParseError::MalformedObjectPath);
-        }
+        validate_object_path(&value)?;
+        Ok(DbusObjectPath(value))
+    }
+}
 
-        // A trailing '/' character is not allowed unless the path is the root path (a single '/' character).
-        if value.len() == 1 {
-            return Ok(DbusObjectPath(value.into()));
-        }
+impl TryFrom<&str> for DbusObjectPath {
+    type Error = DbusParseError;
 
-        if let Err(_) = value
-            .split("/")
-            .try_fold((), |_, fragment| -> Result<(), DbusParseError> {
-                // No element may be the empty string.
-                if fragment.len() == 0 {
-                    return Err(DbusParseError::UnknownError);
-                }
-
-                // Each element must only contain the ASCII characters "[A-Z][a-z][0-9]_"
-                if fragment
-                    .as_bytes()
-                    .iter()
-                    .position(|b| {
-                        (*b >= b'A' && *b <= b'Z')
-                            || (*b >= b'a' && *b <= b'z')
-                            || (*b >= b'0' && *b <= b'9')
-                            || *b == b'_'
-                    })
-                    .is_none()
-                {
-                    return Err(DbusParseError::UnknownError);
-                }
-
-                Ok(())
-            })
-        {
-            return Err(DbusParseError::MalformedObjectPath);
-        }
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        validate_object_path(value)?;
+        Ok(DbusObjectPath(value.into()))
+    }
+}
 
-        Ok(DbusObjectPath(value))
+/// Checks `value` against the object path grammar
+pub(crate) fn validate_object_path(value: &str) -> Result<(), DbusParseError> {
+    // The path must begin with an ASCII '/' (integer 47) character, and must consist of elements separated by slash characters.
+    if !value.starts_with('/') {
+        return Err(DbusParseError::MalformedObjectPath);
     }
+
+    // A trailing '/' character is not allowed unless the path is the root path (a single '/' character).
+    if value.len() == 1 {
+        return Ok(());
+    }
+
+    if value
+        .split('/')
+        .skip(1)
+        .try_fold((), |_, fragment| -> Result<(), DbusParseError> {
+            // No element may be the empty string.
+            if fragment.is_empty() {
+                return Err(DbusParseError::UnknownError);
+            }
+
+            // Each element must only contain the ASCII characters "[A-Z][a-z][0-9]_"
+            if !fragment
+                .as_bytes()
+                .iter()
+                .all(|b| b.is_ascii_alphanumeric() || *b == b'_')
+            {
+                return Err(DbusParseError::UnknownError);
+            }
+
+            Ok(())
+        })
+        .is_err()
+    {
+        return Err(DbusParseError::MalformedObjectPath);
+    }
+
+    Ok(())
 }
 
 impl_string_parse!(DbusObjectPath);
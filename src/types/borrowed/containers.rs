@@ -0,0 +1,261 @@
+use super::DbusStr;
+use crate::parse_context::ParseContext;
+use crate::signature_type::{complete_type_len, Signature, SignatureType};
+use crate::type_container::DbusTypeContainer;
+use crate::types::basic::*;
+use crate::types::containers::*;
+use crate::DbusType;
+use nom::bytes::streaming::take;
+use nom::combinator::{map, map_res};
+use nom::IResult;
+use std::borrow::Cow;
+
+/// Counterpart of [`DbusTypeContainer`] borrowing strings and byte arrays from the decoded buffer
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbusBorrowedTypeContainer<'a> {
+    Boolean(DbusBoolean),
+    Byte(DbusByte),
+    Uint16(DbusUint16),
+    Int16(DbusInt16),
+    Uint32(DbusUint32),
+    Int32(DbusInt32),
+    Uint64(DbusUint64),
+    Int64(DbusInt64),
+    Double(DbusDouble),
+    UnixFd(DbusUnixFd),
+    Signature(DbusStr<'a>),
+    String(DbusStr<'a>),
+    ObjectPath(DbusStr<'a>),
+    Variant(Box<DbusBorrowedVariant<'a>>),
+    /// An `ay`, kept as the raw bytes of the buffer
+    ByteArray(Cow<'a, [u8]>),
+    Array(DbusBorrowedArray<'a>),
+    Struct(Vec<DbusBorrowedTypeContainer<'a>>),
+    Dict(DbusBorrowedDict<'a>),
+    Uint16Array(Vec<u16>),
+    Int16Array(Vec<i16>),
+    Uint32Array(Vec<u32>),
+    Int32Array(Vec<i32>),
+    Uint64Array(Vec<u64>),
+    Int64Array(Vec<i64>),
+    DoubleArray(Vec<f64>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbusBorrowedVariant<'a> {
+    pub signature: DbusStr<'a>,
+    pub inner: DbusBorrowedTypeContainer<'a>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbusBorrowedArray<'a> {
+    /// The signature of a single element of the array
+    pub signature: Signature,
+    pub inner: Vec<DbusBorrowedTypeContainer<'a>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbusBorrowedDict<'a> {
+    pub key_signature: Signature,
+    pub value_signature: Signature,
+    pub inner: Vec<(DbusBorrowedTypeContainer<'a>, DbusBorrowedTypeContainer<'a>)>,
+}
+
+macro_rules! unmarshal_fixed_array {
+    ($buf:ident, $ctx:ident, $variant:ident) => {
+        map(
+            |buf| unmarshal_fixed_array(buf, $ctx),
+            DbusBorrowedTypeContainer::$variant,
+        )($buf)
+    };
+}
+
+macro_rules! unmarshal_basic {
+    ($buf:ident, $ctx:ident, $variant:ident, $type:ident) => {
+        map(
+            |buf| $type::unmarshal(buf, $ctx, &Signature::default()),
+            DbusBorrowedTypeContainer::$variant,
+        )($buf)
+    };
+}
+
+impl<'a> DbusBorrowedTypeContainer<'a> {
+    /// Decodes a value of `signature`, which must hold a single complete type
+    pub(crate) fn unmarshal(
+        buf: &'a [u8],
+        ctx: &ParseContext,
+        signature: &[SignatureType],
+    ) -> IResult<&'a [u8], Self> {
+        let verify = |buf| nom::Err::Failure((buf, nom::error::ErrorKind::Verify));
+        match signature.first().ok_or_else(|| verify(buf))? {
+            SignatureType::Boolean => unmarshal_basic!(buf, ctx, Boolean, DbusBoolean),
+            SignatureType::Byte => unmarshal_basic!(buf, ctx, Byte, DbusByte),
+            SignatureType::Uint16 => unmarshal_basic!(buf, ctx, Uint16, DbusUint16),
+            SignatureType::Int16 => unmarshal_basic!(buf, ctx, Int16, DbusInt16),
+            SignatureType::Uint32 => unmarshal_basic!(buf, ctx, Uint32, DbusUint32),
+            SignatureType::Int32 => unmarshal_basic!(buf, ctx, Int32, DbusInt32),
+            SignatureType::Uint64 => unmarshal_basic!(buf, ctx, Uint64, DbusUint64),
+            SignatureType::Int64 => unmarshal_basic!(buf, ctx, Int64, DbusInt64),
+            SignatureType::Double => unmarshal_basic!(buf, ctx, Double, DbusDouble),
+            SignatureType::UnixFd => unmarshal_basic!(buf, ctx, UnixFd, DbusUnixFd),
+            SignatureType::String => map(
+                |buf| unmarshal_str(buf, ctx),
+                |s| DbusBorrowedTypeContainer::String(s.into()),
+            )(buf),
+            SignatureType::ObjectPath => map_res(
+                |buf| unmarshal_str(buf, ctx),
+                |s| {
+                    validate_object_path(s).map(|_| DbusBorrowedTypeContainer::ObjectPath(s.into()))
+                },
+            )(buf),
+            SignatureType::Signature => map_res(unmarshal_signature_str, |s| {
+                s.parse::<Signature>()
+                    .map(|_| DbusBorrowedTypeContainer::Signature(s.into()))
+            })(buf),
+            SignatureType::Variant => {
+                let (buf, ctx) = ctx.nest(buf)?;
+                let ctx = &ctx;
+                let (buf, s) = unmarshal_signature_str(buf)?;
+                let signature: Signature = s.parse().map_err(|_| verify(buf))?;
+                // A variant holds exactly one single complete type
+                if complete_type_len(&signature).ok() != Some(signature.len()) {
+                    return Err(verify(buf));
+                }
+
+                let (buf, inner) = Self::unmarshal(buf, ctx, &signature)?;
+                Ok((
+                    buf,
+                    DbusBorrowedTypeContainer::Variant(Box::new(DbusBorrowedVariant {
+                        signature: s.into(),
+                        inner,
+                    })),
+                ))
+            }
+            SignatureType::Array => match signature.get(1).ok_or_else(|| verify(buf))? {
+                SignatureType::Byte => {
+                    let (buf, ctx) = ctx.nest(buf)?;
+                    let (buf, len) = ctx.array_len(buf)?;
+                    map(take(len), |bytes: &'a [u8]| {
+                        DbusBorrowedTypeContainer::ByteArray(Cow::Borrowed(bytes))
+                    })(buf)
+                }
+                SignatureType::Uint16 => unmarshal_fixed_array!(buf, ctx, Uint16Array),
+                SignatureType::Int16 => unmarshal_fixed_array!(buf, ctx, Int16Array),
+                SignatureType::Uint32 => unmarshal_fixed_array!(buf, ctx, Uint32Array),
+                SignatureType::Int32 => unmarshal_fixed_array!(buf, ctx, Int32Array),
+                SignatureType::Uint64 => unmarshal_fixed_array!(buf, ctx, Uint64Array),
+                SignatureType::Int64 => unmarshal_fixed_array!(buf, ctx, Int64Array),
+                SignatureType::Double => unmarshal_fixed_array!(buf, ctx, DoubleArray),
+                SignatureType::DictStart => {
+                    let (buf, ctx) = ctx.nest(buf)?;
+                    let ctx = &ctx;
+                    // Dict keys are always a single basic type
+                    let key = &signature[2..3];
+                    let value = &signature[3..signature.len() - 1];
+                    let (buf, inner) = ctx.array(buf, DbusDictEntry::ALIGNMENT, |buf| {
+                        let (buf, _) = ctx.align(buf, DbusDictEntry::ALIGNMENT)?;
+                        let (buf, k) = Self::unmarshal(buf, ctx, key)?;
+                        let (buf, v) = Self::unmarshal(buf, ctx, value)?;
+                        Ok((buf, (k, v)))
+                    })?;
+
+                    Ok((
+                        buf,
+                        DbusBorrowedTypeContainer::Dict(DbusBorrowedDict {
+                            key_signature: Signature::new(key.to_vec()),
+                            value_signature: Signature::new(value.to_vec()),
+                            inner,
+                        }),
+                    ))
+                }
+                element => {
+                    let (buf, ctx) = ctx.nest(buf)?;
+                    let ctx = &ctx;
+                    let (buf, inner) = ctx.array(buf, element.alignment(), |buf| {
+                        Self::unmarshal(buf, ctx, &signature[1..])
+                    })?;
+
+                    Ok((
+                        buf,
+                        DbusBorrowedTypeContainer::Array(DbusBorrowedArray {
+                            signature: Signature::new(signature[1..].to_vec()),
+                            inner,
+                        }),
+                    ))
+                }
+            },
+            SignatureType::StructStart => {
+                let (buf, ctx) = ctx.nest(buf)?;
+                let ctx = &ctx;
+                let (mut buf, _) = ctx.align(buf, DbusStruct::ALIGNMENT)?;
+                let mut fields = &signature[1..signature.len() - 1];
+                let mut inner = vec![];
+                while !fields.is_empty() {
+                    let len = complete_type_len(fields).map_err(|_| verify(buf))?;
+                    let (rest, value) = Self::unmarshal(buf, ctx, &fields[..len])?;
+                    inner.push(value);
+                    fields = &fields[len..];
+                    buf = rest;
+                }
+
+                Ok((buf, DbusBorrowedTypeContainer::Struct(inner)))
+            }
+            _ => Err(verify(buf)),
+        }
+    }
+
+    /// Copies every borrowed string and byte array into the owned value model
+    pub fn into_owned(self) -> DbusTypeContainer {
+        match self {
+            DbusBorrowedTypeContainer::Boolean(v) => DbusTypeContainer::Boolean(v),
+            DbusBorrowedTypeContainer::Byte(v) => DbusTypeContainer::Byte(v),
+            DbusBorrowedTypeContainer::Uint16(v) => DbusTypeContainer::Uint16(v),
+            DbusBorrowedTypeContainer::Int16(v) => DbusTypeContainer::Int16(v),
+            DbusBorrowedTypeContainer::Uint32(v) => DbusTypeContainer::Uint32(v),
+            DbusBorrowedTypeContainer::Int32(v) => DbusTypeContainer::Int32(v),
+            DbusBorrowedTypeContainer::Uint64(v) => DbusTypeContainer::Uint64(v),
+            DbusBorrowedTypeContainer::Int64(v) => DbusTypeContainer::Int64(v),
+            DbusBorrowedTypeContainer::Double(v) => DbusTypeContainer::Double(v),
+            DbusBorrowedTypeContainer::UnixFd(v) => DbusTypeContainer::UnixFd(v),
+            DbusBorrowedTypeContainer::Signature(v) => {
+                DbusTypeContainer::Signature(DbusSignature(v.into_owned()))
+            }
+            DbusBorrowedTypeContainer::String(v) => {
+                DbusTypeContainer::String(DbusString(v.into_owned()))
+            }
+            DbusBorrowedTypeContainer::ObjectPath(v) => {
+                DbusTypeContainer::ObjectPath(DbusObjectPath(v.into_owned()))
+            }
+            DbusBorrowedTypeContainer::Variant(v) => {
+                DbusTypeContainer::Variant(Box::new(DbusVariant {
+                    signature: DbusSignature(v.signature.into_owned()),
+                    inner: v.inner.into_owned(),
+                }))
+            }
+            DbusBorrowedTypeContainer::ByteArray(v) => DbusTypeContainer::ByteArray(v.into_owned()),
+            DbusBorrowedTypeContainer::Array(v) => DbusTypeContainer::Array(DbusArray {
+                signature: v.signature,
+                inner: v.inner.into_iter().map(Self::into_owned).collect(),
+            }),
+            DbusBorrowedTypeContainer::Struct(v) => {
+                DbusTypeContainer::Struct(DbusStruct(v.into_iter().map(Self::into_owned).collect()))
+            }
+            DbusBorrowedTypeContainer::Dict(v) => DbusTypeContainer::Dict(DbusDict {
+                key_signature: v.key_signature,
+                value_signature: v.value_signature,
+                inner: v
+                    .inner
+                    .into_iter()
+                    .map(|(k, v)| DbusDictEntry(k.into_owned(), v.into_owned()))
+                    .collect(),
+            }),
+            DbusBorrowedTypeContainer::Uint16Array(v) => DbusTypeContainer::Uint16Array(v),
+            DbusBorrowedTypeContainer::Int16Array(v) => DbusTypeContainer::Int16Array(v),
+            DbusBorrowedTypeContainer::Uint32Array(v) => DbusTypeContainer::Uint32Array(v),
+            DbusBorrowedTypeContainer::Int32Array(v) => DbusTypeContainer::Int32Array(v),
+            DbusBorrowedTypeContainer::Uint64Array(v) => DbusTypeContainer::Uint64Array(v),
+            DbusBorrowedTypeContainer::Int64Array(v) => DbusTypeContainer::Int64Array(v),
+            DbusBorrowedTypeContainer::DoubleArray(v) => DbusTypeContainer::DoubleArray(v),
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod strings;
+pub use self::strings::*;
+
+mod containers;
+pub use self::containers::*;
@@ -0,0 +1,42 @@
+use std::borrow::Cow;
+
+/// A string, object path or signature borrowed from the decoded buffer
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DbusStr<'a>(Cow<'a, str>);
+
+impl<'a> DbusStr<'a> {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Copies the string out of the buffer if it was borrowed
+    pub fn into_owned(self) -> String {
+        self.0.into_owned()
+    }
+}
+
+impl<'a> From<&'a str> for DbusStr<'a> {
+    fn from(v: &'a str) -> Self {
+        Self(Cow::Borrowed(v))
+    }
+}
+
+impl From<String> for DbusStr<'_> {
+    fn from(v: String) -> Self {
+        Self(Cow::Owned(v))
+    }
+}
+
+impl std::ops::Deref for DbusStr<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for DbusStr<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
@@ -1,41 +1,85 @@
-use crate::header::components::MessageEndianness;
+use crate::error::DbusParseError;
+use crate::parse_context::ParseContext;
 use crate::signature_type::Signature;
+use crate::type_container::DbusTypeContainer;
 use crate::DbusType;
-use nom::bytes::streaming::take;
-use nom::combinator::iterator;
-use nom::combinator::map;
-use nom::number::streaming::be_u32;
-use nom::number::streaming::le_u32;
 use nom::IResult;
 
+/// An array of values sharing the same element signature
+///
+/// The element signature is kept alongside the values so that empty arrays
+/// still know which type they hold.
 #[derive(Debug, Clone, PartialEq)]
-pub struct DbusArray<T: DbusType>(Vec<T>);
+pub struct DbusArray {
+    pub(crate) signature: Signature,
+    pub(crate) inner: Vec<DbusTypeContainer>,
+}
 
-impl<T: DbusType> DbusType for DbusArray<T> {
-    const ALIGNMENT: usize = 4;
+impl DbusArray {
+    /// Creates an array of `signature` elements, checking every value against it
+    ///
+    /// `signature` must hold a single complete type.
+    pub fn new(
+        signature: Signature,
+        inner: Vec<DbusTypeContainer>,
+    ) -> Result<Self, DbusParseError> {
+        if signature.split_complete_types()?.len() != 1 {
+            return Err(DbusParseError::InvalidSignature);
+        }
 
-    fn unmarshal<'a, 'b>(
-        buf: &'b [u8],
-        endianness: MessageEndianness,
-        signature: &'a Signature,
-    ) -> IResult<&'b [u8], Self> {
-        let (buf, len) = match endianness {
-            MessageEndianness::BigEndian => map(be_u32, |v| v as usize)(buf),
-            MessageEndianness::LittleEndian => map(le_u32, |v| v as usize)(buf),
-        }?;
+        if inner.iter().any(|v| v.signature() != signature) {
+            return Err(DbusParseError::InvalidElementSignature);
+        }
 
-        let inner_alignment = T::ALIGNMENT;
-        // Advance buffer by discarding bytes to pad alignment
-        let (buf, _) = take(std::cmp::max(Self::ALIGNMENT, inner_alignment))(buf)?;
+        Ok(Self { signature, inner })
+    }
 
-        let mut it = iterator(buf, |buf| T::unmarshal(buf, endianness, signature));
-        let inner: Vec<T> = it.collect();
-        let (buf, _) = it.finish()?;
+    /// The signature of a single element of the array
+    pub fn element_signature(&self) -> &Signature {
+        &self.signature
+    }
 
-        if inner.len() % len != 0 {
-            return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
-        }
+    pub fn into_inner(self) -> Vec<DbusTypeContainer> {
+        self.inner
+    }
+}
+
+impl std::ops::Deref for DbusArray {
+    type Target = Vec<DbusTypeContainer>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DbusType for DbusArray {
+    const ALIGNMENT: usize = 4;
+
+    fn unmarshal<'b>(
+        buf: &'b [u8],
+        ctx: &ParseContext,
+        signature: &Signature,
+    ) -> IResult<&'b [u8], Self> {
+        let (buf, ctx) = ctx.nest(buf)?;
+        let ctx = &ctx;
+        let alignment = signature
+            .first()
+            .map(|t| t.alignment())
+            .ok_or(nom::Err::Failure((buf, nom::error::ErrorKind::Verify)))?;
+        let (buf, inner) = ctx.array(buf, alignment, |buf| {
+            let (buf, mut values) = signature.parse_buffer(buf, ctx)?;
+            values
+                .pop()
+                .map(|v| (buf, v))
+                .ok_or(nom::Err::Error((buf, nom::error::ErrorKind::Verify)))
+        })?;
 
-        Ok((buf, Self(inner)))
+        Ok((
+            buf,
+            Self {
+                signature: signature.clone(),
+                inner,
+            },
+        ))
     }
 }
@@ -0,0 +1,108 @@
+use crate::header::components::MessageEndianness;
+use crate::parse_context::ParseContext;
+use crate::signature_type::SignatureType;
+use nom::bytes::streaming::take;
+use nom::IResult;
+
+#[cfg(target_endian = "little")]
+const HOST_ENDIANNESS: MessageEndianness = MessageEndianness::LittleEndian;
+#[cfg(target_endian = "big")]
+const HOST_ENDIANNESS: MessageEndianness = MessageEndianness::BigEndian;
+
+/// Basic types with a fixed wire size, whose arrays are decoded in bulk
+///
+/// Their alignment always equals their size, so the elements of an array are
+/// laid out back to back without padding.
+///
+/// # Safety
+///
+/// Every pattern of `SIZE` bytes must be a valid value of the type, as arrays
+/// are copied straight out of the message buffer.
+pub unsafe trait DbusFixedType: Copy + PartialEq + std::fmt::Debug {
+    const SIGNATURE: SignatureType;
+    const SIZE: usize;
+
+    /// Reverses the byte order of the value
+    fn swap_bytes(self) -> Self;
+    fn extend_le(self, buf: &mut Vec<u8>);
+    fn extend_be(self, buf: &mut Vec<u8>);
+}
+
+macro_rules! impl_fixed_type {
+    ($type:ty, $signature:ident) => {
+        unsafe impl DbusFixedType for $type {
+            const SIGNATURE: SignatureType = SignatureType::$signature;
+            const SIZE: usize = std::mem::size_of::<$type>();
+
+            fn swap_bytes(self) -> Self {
+                <$type>::swap_bytes(self)
+            }
+
+            fn extend_le(self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_le_bytes());
+            }
+
+            fn extend_be(self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_be_bytes());
+            }
+        }
+    };
+}
+
+impl_fixed_type!(u8, Byte);
+impl_fixed_type!(u16, Uint16);
+impl_fixed_type!(i16, Int16);
+impl_fixed_type!(u32, Uint32);
+impl_fixed_type!(i32, Int32);
+impl_fixed_type!(u64, Uint64);
+impl_fixed_type!(i64, Int64);
+
+unsafe impl DbusFixedType for f64 {
+    const SIGNATURE: SignatureType = SignatureType::Double;
+    const SIZE: usize = 8;
+
+    fn swap_bytes(self) -> Self {
+        f64::from_bits(self.to_bits().swap_bytes())
+    }
+
+    fn extend_le(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn extend_be(self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+/// Decodes a whole array of `T` with a single copy of its bytes
+///
+/// The elements are only swapped afterwards when the message byte order
+/// differs from the host's.
+pub(crate) fn unmarshal_fixed_array<'b, T: DbusFixedType>(
+    buf: &'b [u8],
+    ctx: &ParseContext,
+) -> IResult<&'b [u8], Vec<T>> {
+    // Arrays count toward the depth limit even when their elements cannot nest
+    let (buf, ctx) = ctx.nest(buf)?;
+    let (buf, len) = ctx.array_len(buf)?;
+    let (buf, _) = ctx.align(buf, T::SIZE)?;
+    let (buf, bytes) = take(len)(buf)?;
+    if !bytes.len().is_multiple_of(T::SIZE) {
+        return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
+    }
+
+    let count = bytes.len() / T::SIZE;
+    let mut values = Vec::<T>::with_capacity(count);
+    // The vector has room for exactly the `count * T::SIZE` bytes copied, and
+    // any bytes make a valid `T`
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), values.as_mut_ptr() as *mut u8, bytes.len());
+        values.set_len(count);
+    }
+
+    if ctx.endianness() != HOST_ENDIANNESS {
+        values.iter_mut().for_each(|v| *v = v.swap_bytes());
+    }
+
+    Ok((buf, values))
+}
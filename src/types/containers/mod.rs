@@ -4,5 +4,8 @@ pub use self::variant::*;
 mod array;
 pub use self::array::*;
 
+mod fixed_array;
+pub use self::fixed_array::*;
+
 mod structure;
 pub use self::structure::*;
@@ -1,88 +1,193 @@
-use crate::header::components::MessageEndianness;
-use crate::signature_type::Signature;
+use crate::parse_context::ParseContext;
+use crate::signature_type::{Signature, SignatureType};
 use crate::type_container::DbusTypeContainer;
 use crate::{DbusParseError, DbusType};
-use nom::combinator::iterator;
 use nom::IResult;
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct DbusStruct(Vec<DbusTypeContainer>);
+pub struct DbusStruct(pub(crate) Vec<DbusTypeContainer>);
+
+impl DbusStruct {
+    /// Creates a struct of `inner`, which cannot be empty
+    pub fn new(inner: Vec<DbusTypeContainer>) -> Result<Self, DbusParseError> {
+        if inner.is_empty() {
+            return Err(DbusParseError::InvalidSignature);
+        }
+
+        Ok(Self(inner))
+    }
+
+    pub fn into_inner(self) -> Vec<DbusTypeContainer> {
+        self.0
+    }
+}
+
+impl std::ops::Deref for DbusStruct {
+    type Target = Vec<DbusTypeContainer>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
 
 impl DbusType for DbusStruct {
     const ALIGNMENT: usize = 8;
 
-    fn unmarshal<'a, 'b>(
+    fn unmarshal<'b>(
         buf: &'b [u8],
-        endianness: MessageEndianness,
-        signature: &'a Signature,
+        ctx: &ParseContext,
+        signature: &Signature,
     ) -> IResult<&'b [u8], Self> {
-        let (buf, inner) = signature.parse_buffer(buf, endianness)?;
+        let (buf, ctx) = ctx.nest(buf)?;
+        let (buf, _) = ctx.align(buf, Self::ALIGNMENT)?;
+        let (buf, inner) = signature.parse_buffer(buf, &ctx)?;
         Ok((buf, Self(inner)))
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct DbusDictEntry(DbusTypeContainer, DbusTypeContainer);
+pub struct DbusDictEntry(pub(crate) DbusTypeContainer, pub(crate) DbusTypeContainer);
+
+impl DbusDictEntry {
+    pub fn new(key: DbusTypeContainer, value: DbusTypeContainer) -> Self {
+        Self(key, value)
+    }
+
+    pub fn key(&self) -> &DbusTypeContainer {
+        &self.0
+    }
+
+    pub fn value(&self) -> &DbusTypeContainer {
+        &self.1
+    }
+}
 
 impl DbusType for DbusDictEntry {
     const ALIGNMENT: usize = 8;
 
-    fn unmarshal<'a, 'b>(
+    fn unmarshal<'b>(
         buf: &'b [u8],
-        endianness: MessageEndianness,
-        signature: &'a Signature,
+        ctx: &ParseContext,
+        signature: &Signature,
     ) -> IResult<&'b [u8], Self> {
-        let (buf, inner) = signature.parse_buffer(buf, endianness)?;
+        let (buf, _) = ctx.align(buf, Self::ALIGNMENT)?;
+        let (buf, inner) = signature.parse_buffer(buf, ctx)?;
         if inner.len() != 2 {
             return Err(nom::Err::Error((buf, nom::error::ErrorKind::Verify)));
         }
 
         let mut iter = inner.into_iter();
-        let v1 = iter.next().unwrap().into();
-        let v2 = iter.next().unwrap().into();
+        let v1 = iter.next().unwrap();
+        let v2 = iter.next().unwrap();
 
         Ok((buf, Self(v1, v2)))
     }
 }
 
+/// An array of dict entries
+///
+/// Like [`DbusArray`](crate::types::containers::DbusArray), the key and value
+/// signatures are kept so that an empty dict still knows its entry type.
 #[derive(Debug, Clone, PartialEq)]
-pub struct DbusDict(Vec<DbusDictEntry>);
+pub struct DbusDict {
+    pub(crate) key_signature: Signature,
+    pub(crate) value_signature: Signature,
+    pub(crate) inner: Vec<DbusDictEntry>,
+}
 
 impl DbusDict {
-    #[allow(dead_code)]
-    pub fn new(inner: Vec<DbusDictEntry>) -> Self {
-        Self(inner)
+    /// Creates a dict of `key_signature` to `value_signature` entries, checking every entry against them
+    ///
+    /// Keys must be of a basic type, values of a single complete type.
+    pub fn new(
+        key_signature: Signature,
+        value_signature: Signature,
+        inner: Vec<DbusDictEntry>,
+    ) -> Result<Self, DbusParseError> {
+        if key_signature.len() != 1
+            || !key_signature[0].is_basic()
+            || value_signature.split_complete_types()?.len() != 1
+        {
+            return Err(DbusParseError::InvalidSignature);
+        }
+
+        if inner
+            .iter()
+            .any(|e| e.0.signature() != key_signature || e.1.signature() != value_signature)
+        {
+            return Err(DbusParseError::InvalidElementSignature);
+        }
+
+        Ok(Self {
+            key_signature,
+            value_signature,
+            inner,
+        })
+    }
+
+    pub fn key_signature(&self) -> &Signature {
+        &self.key_signature
+    }
+
+    pub fn value_signature(&self) -> &Signature {
+        &self.value_signature
+    }
+
+    /// The `{kv}` signature of a single entry of the dict
+    pub fn entry_signature(&self) -> Signature {
+        let mut signature = Signature::from(SignatureType::DictStart);
+        signature.extend_from_slice(&self.key_signature);
+        signature.extend_from_slice(&self.value_signature);
+        signature.push(SignatureType::DictEnd);
+        signature
+    }
+
+    pub fn into_inner(self) -> Vec<DbusDictEntry> {
+        self.inner
     }
 }
 
-impl From<Vec<DbusTypeContainer>> for DbusDict {
-    fn from(v: Vec<DbusTypeContainer>) -> Self {
-        Self(
-            v.chunks(2)
-                .map(|tmp| DbusDictEntry(tmp[0].clone(), tmp[1].clone()))
-                .collect(),
-        )
+impl std::ops::Deref for DbusDict {
+    type Target = Vec<DbusDictEntry>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
     }
 }
 
 impl DbusType for DbusDict {
-    const ALIGNMENT: usize = 8;
+    const ALIGNMENT: usize = 4;
 
-    fn unmarshal<'a, 'b>(
+    /// `signature` is the `kv` content of the entries, without the braces
+    fn unmarshal<'b>(
         buf: &'b [u8],
-        endianness: MessageEndianness,
-        signature: &'a Signature,
+        ctx: &ParseContext,
+        signature: &Signature,
     ) -> IResult<&'b [u8], Self> {
-        let mut it = iterator(buf, |buf| {
-            DbusDictEntry::unmarshal(buf, endianness, signature)
-        });
-
-        let inner = it.collect();
-        let (buf, _) = it.finish()?;
-
-        Ok((buf, Self(inner)))
+        let mut types = signature
+            .split_complete_types()
+            .map_err(|_| nom::Err::Failure((buf, nom::error::ErrorKind::Verify)))?
+            .into_iter();
+        let (key_signature, value_signature) = match (types.next(), types.next(), types.next()) {
+            (Some(k), Some(v), None) => (k, v),
+            _ => return Err(nom::Err::Failure((buf, nom::error::ErrorKind::Verify))),
+        };
+
+        let (buf, ctx) = ctx.nest(buf)?;
+        let (buf, inner) = ctx.array(buf, DbusDictEntry::ALIGNMENT, |buf| {
+            DbusDictEntry::unmarshal(buf, &ctx, signature)
+        })?;
+
+        Ok((
+            buf,
+            Self {
+                key_signature,
+                value_signature,
+                inner,
+            },
+        ))
     }
 }
 
@@ -94,7 +199,7 @@ impl<
     type Error = DbusParseError;
 
     fn try_into(self) -> Result<HashMap<K, V>, Self::Error> {
-        self.0
+        self.inner
             .into_iter()
             .try_fold(HashMap::default(), |mut hash, current_entry| {
                 let k = current_entry.0.try_into()?;
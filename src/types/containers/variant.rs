@@ -1,4 +1,4 @@
-use crate::header::components::MessageEndianness;
+use crate::parse_context::ParseContext;
 use crate::types::basic::*;
 use crate::Signature;
 use crate::{DbusType, DbusTypeContainer};
@@ -7,11 +7,23 @@ use std::convert::TryInto;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DbusVariant {
-    signature: DbusSignature,
-    inner: DbusTypeContainer,
+    pub(crate) signature: DbusSignature,
+    pub(crate) inner: DbusTypeContainer,
 }
 
 impl DbusVariant {
+    /// Wraps `inner`, deriving the variant signature from the value itself
+    pub fn new(inner: DbusTypeContainer) -> Self {
+        Self {
+            signature: (&inner.signature()).into(),
+            inner,
+        }
+    }
+
+    pub fn signature(&self) -> &DbusSignature {
+        &self.signature
+    }
+
     pub fn into_inner(self) -> DbusTypeContainer {
         self.inner
     }
@@ -20,23 +32,29 @@ impl DbusVariant {
 impl DbusType for DbusVariant {
     const ALIGNMENT: usize = 1;
 
-    fn unmarshal<'a, 'b>(
-        buf: &'b [u8],
-        endianness: MessageEndianness,
-        s: &'a Signature,
-    ) -> IResult<&'b [u8], Self> {
-        let (buf, signature) = DbusSignature::unmarshal(buf, endianness, s)?;
+    fn unmarshal<'b>(buf: &'b [u8], ctx: &ParseContext, s: &Signature) -> IResult<&'b [u8], Self> {
+        let (buf, ctx) = ctx.nest(buf)?;
+        let ctx = &ctx;
+        let (buf, signature) = DbusSignature::unmarshal(buf, ctx, s)?;
 
         let type_signature: Signature = signature
             .clone()
             .try_into()
             .map_err(|_| nom::Err::Failure((buf, nom::error::ErrorKind::Verify)))?;
 
-        let (buf, inner) = type_signature
-            .parse_buffer(buf, endianness)
-            .map(|(buf, inner)| (buf, inner.into_iter().next().unwrap()))?;
+        // A variant holds exactly one single complete type
+        let (buf, mut inner) = type_signature.parse_buffer(buf, ctx)?;
+        if inner.len() != 1 {
+            return Err(nom::Err::Failure((buf, nom::error::ErrorKind::Verify)));
+        }
 
-        Ok((buf, DbusVariant { inner, signature }))
+        Ok((
+            buf,
+            DbusVariant {
+                inner: inner.remove(0),
+                signature,
+            },
+        ))
     }
 }
 
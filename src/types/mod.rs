@@ -1,2 +1,3 @@
 pub mod basic;
+pub mod borrowed;
 pub mod containers;
@@ -0,0 +1,179 @@
+use crate::type_container::DbusTypeContainer;
+use crate::types::basic::DbusUnixFd;
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+
+/// File descriptors carried by a message, indexed by its `h` values
+///
+/// Clones of a message share the descriptors, which are closed once the last
+/// clone is dropped.
+#[derive(Debug, Clone, Default)]
+pub struct UnixFds(Vec<Arc<OwnedFd>>);
+
+impl UnixFds {
+    pub fn new(fds: Vec<OwnedFd>) -> Self {
+        Self(fds.into_iter().map(Arc::new).collect())
+    }
+
+    pub fn get(&self, index: DbusUnixFd) -> Option<&OwnedFd> {
+        self.0.get(u32::from(index) as usize).map(|fd| &**fd)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &OwnedFd> {
+        self.0.iter().map(|fd| &**fd)
+    }
+}
+
+/// Messages are equal when they carry the very same descriptors
+impl PartialEq for UnixFds {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|(a, b)| a.as_raw_fd() == b.as_raw_fd())
+    }
+}
+
+/// Calls `visit` with every `h` value held by `value`, however deeply nested
+pub(crate) fn visit_unix_fds(value: &DbusTypeContainer, visit: &mut dyn FnMut(DbusUnixFd)) {
+    match value {
+        DbusTypeContainer::UnixFd(fd) => visit(*fd),
+        DbusTypeContainer::Variant(v) => visit_unix_fds(&v.inner, visit),
+        DbusTypeContainer::Array(array) => array.iter().for_each(|v| visit_unix_fds(v, visit)),
+        DbusTypeContainer::Struct(structure) => {
+            structure.iter().for_each(|v| visit_unix_fds(v, visit))
+        }
+        DbusTypeContainer::Dict(dict) => dict.iter().for_each(|entry| {
+            visit_unix_fds(entry.key(), visit);
+            visit_unix_fds(entry.value(), visit);
+        }),
+        _ => {}
+    }
+}
+
+/// Most descriptors the kernel passes in one message
+const MAX_FDS_PER_CALL: usize = 253;
+const FD_SIZE: usize = std::mem::size_of::<libc::c_int>();
+
+/// Size of a control buffer with room for `count` descriptors
+fn control_len(count: usize) -> usize {
+    // SAFETY: `CMSG_SPACE` only computes a size
+    unsafe { libc::CMSG_SPACE((count * FD_SIZE) as u32) as usize }
+}
+
+/// Writes `data` with `fds` attached as `SCM_RIGHTS`, returning the bytes written
+///
+/// The descriptors travel with the first byte written, so a partial write
+/// must be finished without them.
+pub(crate) fn send_with_fds(
+    socket: &impl AsRawFd,
+    data: &[u8],
+    fds: &[&OwnedFd],
+) -> io::Result<usize> {
+    if fds.len() > MAX_FDS_PER_CALL {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "too many file descriptors for one message",
+        ));
+    }
+
+    let mut control = vec![0u8; control_len(fds.len())];
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    // SAFETY: an all-zero `msghdr` is a valid empty header
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    // SAFETY: the control buffer was sized for one header holding `fds`, and
+    // `data` and `control` outlive the call
+    let sent = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN((fds.len() * FD_SIZE) as u32) as _;
+        let payload = libc::CMSG_DATA(cmsg) as *mut libc::c_int;
+        for (i, fd) in fds.iter().enumerate() {
+            payload.add(i).write_unaligned(fd.as_raw_fd());
+        }
+
+        libc::sendmsg(socket.as_raw_fd(), &msg, 0)
+    };
+
+    if sent < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(sent as usize)
+    }
+}
+
+/// Reads into `buf`, appending the descriptors that came along to `fds`
+pub(crate) fn recv_with_fds(
+    socket: &impl AsRawFd,
+    buf: &mut [u8],
+    fds: &mut VecDeque<OwnedFd>,
+) -> io::Result<usize> {
+    let mut control = vec![0u8; control_len(MAX_FDS_PER_CALL)];
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // SAFETY: an all-zero `msghdr` is a valid empty header
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    #[cfg(target_os = "linux")]
+    let flags = libc::MSG_CMSG_CLOEXEC;
+    #[cfg(not(target_os = "linux"))]
+    let flags = 0;
+
+    // SAFETY: `buf` and `control` outlive the call and their lengths are given
+    let received = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, flags) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: the kernel filled in well-formed control headers, and every
+    // descriptor it passed is ours to own from now on
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / FD_SIZE;
+                let payload = libc::CMSG_DATA(cmsg) as *const libc::c_int;
+                for i in 0..count {
+                    fds.push_back(OwnedFd::from_raw_fd(payload.add(i).read_unaligned()));
+                }
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "file descriptors were dropped by the kernel",
+        ));
+    }
+
+    Ok(received as usize)
+}
@@ -0,0 +1,171 @@
+use conducto_nom::*;
+use std::path::PathBuf;
+
+#[test]
+fn parses_several_entries() {
+    let address: BusAddress = "unix:path=/run/bus,guid=0123;tcp:host=localhost,port=4000;;"
+        .parse()
+        .unwrap();
+    assert_eq!(address.len(), 2);
+
+    assert_eq!(address[0].transport_name(), "unix");
+    assert_eq!(address[0].guid(), Some("0123"));
+    assert_eq!(
+        address[0].transport().unwrap(),
+        Some(Transport::Unix(UnixSocket::Path("/run/bus".into())))
+    );
+
+    assert_eq!(
+        address[1].transport().unwrap(),
+        Some(Transport::Tcp(TcpAddress {
+            host: Some("localhost".to_string()),
+            bind: None,
+            port: Some(4000),
+            family: None,
+        }))
+    );
+
+    assert_eq!(
+        address.to_string(),
+        "unix:path=/run/bus,guid=0123;tcp:host=localhost,port=4000"
+    );
+    assert!("".parse::<BusAddress>().unwrap().is_empty());
+}
+
+#[test]
+fn unescapes_values() {
+    let entry: AddressEntry = "unix:abstract=%2ftmp%2Fdbus%20bus%3b1".parse().unwrap();
+    assert_eq!(entry.get("abstract"), Some("/tmp/dbus bus;1"));
+    assert_eq!(
+        entry.transport().unwrap(),
+        Some(Transport::Unix(UnixSocket::Abstract(
+            "/tmp/dbus bus;1".to_string()
+        )))
+    );
+
+    // Only bytes outside the optionally escaped set are escaped again
+    assert_eq!(entry.to_string(), "unix:abstract=/tmp/dbus%20bus%3b1");
+    assert_eq!(entry.to_string().parse::<AddressEntry>().unwrap(), entry);
+
+    let entry = AddressEntry::new("unix").with("path", "/tmp/a,b=c;é");
+    assert_eq!(entry.to_string(), "unix:path=/tmp/a%2cb%3dc%3b%c3%a9");
+    assert_eq!(entry.to_string().parse::<AddressEntry>().unwrap(), entry);
+
+    for invalid in ["unix:path=%2", "unix:path=%zz", "unix:path=%ff"].iter() {
+        assert_eq!(
+            invalid.parse::<AddressEntry>(),
+            Err(AddressError::InvalidEscape),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn keeps_unknown_transports() {
+    let entry: AddressEntry = "vsock:cid=3,port=5000".parse().unwrap();
+    assert_eq!(entry.transport_name(), "vsock");
+    assert_eq!(entry.get("cid"), Some("3"));
+    assert_eq!(entry.transport().unwrap(), None);
+
+    let address: BusAddress = "vsock:cid=3;unix:path=/run/bus".parse().unwrap();
+    assert_eq!(address.len(), 2);
+}
+
+#[test]
+fn unix_needs_exactly_one_socket() {
+    assert_eq!(
+        "unix:guid=0123".parse::<AddressEntry>(),
+        Err(AddressError::MissingKey(
+            "unix".to_string(),
+            "path".to_string()
+        ))
+    );
+    assert_eq!(
+        "unix:path=/a,abstract=b".parse::<AddressEntry>(),
+        Err(AddressError::UnexpectedKey(
+            "unix".to_string(),
+            "abstract".to_string()
+        ))
+    );
+    assert_eq!(
+        "unix:runtime=no".parse::<AddressEntry>(),
+        Err(AddressError::InvalidValue("runtime".to_string()))
+    );
+
+    let entry: AddressEntry = "unix:runtime=yes".parse().unwrap();
+    assert_eq!(
+        entry.transport().unwrap(),
+        Some(Transport::Unix(UnixSocket::Runtime))
+    );
+}
+
+#[test]
+fn other_transports() {
+    let entry: AddressEntry = "nonce-tcp:host=::1,family=ipv6,noncefile=/tmp/nonce"
+        .parse()
+        .unwrap();
+    match entry.transport().unwrap() {
+        Some(Transport::NonceTcp(tcp, noncefile)) => {
+            assert_eq!(tcp.family, Some(TcpFamily::Ipv6));
+            assert_eq!(noncefile, Some(PathBuf::from("/tmp/nonce")));
+        }
+        transport => panic!("unexpected transport {:?}", transport),
+    }
+
+    let entry: AddressEntry = "unixexec:path=/bin/sh,argv1=-c,argv0=sh,argv2=true"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        entry.transport().unwrap(),
+        Some(Transport::UnixExec {
+            path: "/bin/sh".into(),
+            argv: vec!["sh".to_string(), "-c".to_string(), "true".to_string()],
+        })
+    );
+
+    let errors = [
+        (
+            "tcp:noncefile=/tmp/nonce",
+            AddressError::UnexpectedKey("tcp".to_string(), "noncefile".to_string()),
+        ),
+        (
+            "tcp:port=70000",
+            AddressError::InvalidValue("port".to_string()),
+        ),
+        (
+            "tcp:family=ipx",
+            AddressError::InvalidValue("family".to_string()),
+        ),
+        (
+            "unixexec:argv0=sh",
+            AddressError::MissingKey("unixexec".to_string(), "path".to_string()),
+        ),
+    ];
+    for (address, error) in errors.iter() {
+        assert_eq!(address.parse::<AddressEntry>().as_ref(), Err(error));
+    }
+}
+
+#[test]
+fn malformed_entries() {
+    let errors = [
+        ("path=/run/bus", AddressError::MissingTransport),
+        (":path=/run/bus", AddressError::MissingTransport),
+        ("unix:path", AddressError::MalformedPair),
+        ("unix:=/run/bus", AddressError::MalformedPair),
+        (
+            "unix:path=/a,path=/b",
+            AddressError::DuplicateKey("path".to_string()),
+        ),
+    ];
+    for (address, error) in errors.iter() {
+        assert_eq!(address.parse::<AddressEntry>().as_ref(), Err(error));
+    }
+
+    // One bad entry spoils the whole address
+    assert_eq!(
+        "unix:path=/run/bus;unix:path".parse::<BusAddress>(),
+        Err(AddressError::MalformedPair)
+    );
+}
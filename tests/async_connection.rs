@@ -0,0 +1,203 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use common::{string, Peer, GUID};
+use conducto_nom::components::MessageFlags;
+use conducto_nom::*;
+use std::future::Future;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+fn run<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// A connection to a fresh peer, which answers from its own thread
+async fn connect() -> (AsyncConnection, Peer) {
+    let (client, server) = UnixStream::pair().unwrap();
+    client.set_nonblocking(true).unwrap();
+    let peer = thread::spawn(move || Peer::accept(server, false));
+    let client = tokio::net::UnixStream::from_std(client).unwrap();
+    let connection = AsyncConnection::from_stream(client).await.unwrap();
+    (connection, peer.join().unwrap())
+}
+
+fn call(member: &str) -> Message {
+    Message::method_call(Some("com.example"), "/com/example", None, member).unwrap()
+}
+
+fn signal(member: &str) -> Message {
+    Message::signal("/com/example", "com.example.Async", member).unwrap()
+}
+
+#[test]
+fn hello_keeps_the_unique_name() {
+    run(async {
+        let (connection, mut peer) = connect().await;
+        assert_eq!(connection.guid(), GUID);
+        assert_eq!(connection.unique_name(), None);
+
+        let peer = thread::spawn(move || {
+            let hello = peer.read_message();
+            assert_eq!(hello.header.member(), Some("Hello"));
+            peer.reply(hello.header.serial(), vec![string(":1.3")]);
+            peer
+        });
+        assert_eq!(connection.hello().await.unwrap(), ":1.3");
+        assert_eq!(connection.unique_name(), Some(":1.3"));
+        peer.join().unwrap();
+    });
+}
+
+#[test]
+fn replies_wake_up_their_own_call() {
+    run(async {
+        let (connection, mut peer) = connect().await;
+        let mut subscription = connection.subscribe();
+
+        let peer = thread::spawn(move || {
+            let first = peer.read_message();
+            let second = peer.read_message();
+            peer.send(signal("Between"));
+            // Answered in reverse order
+            for call in [second, first].iter() {
+                let member = call.header.member().unwrap().to_string();
+                peer.reply(call.header.serial(), vec![string(&member)]);
+            }
+            peer
+        });
+
+        let a = tokio::spawn({
+            let connection = connection.clone();
+            async move { connection.call(call("A")).await }
+        });
+        let b = tokio::spawn({
+            let connection = connection.clone();
+            async move { connection.call(call("B")).await }
+        });
+        let a = a.await.unwrap().unwrap().unwrap();
+        let b = b.await.unwrap().unwrap().unwrap();
+        assert_eq!(a.message, vec![string("A")]);
+        assert_eq!(b.message, vec![string("B")]);
+
+        // Only the message answering no call reaches subscribers
+        let received = subscription.next().await.unwrap();
+        assert_eq!(received.header.member(), Some("Between"));
+        peer.join().unwrap();
+    });
+}
+
+#[test]
+fn error_replies() {
+    run(async {
+        let (connection, mut peer) = connect().await;
+        let peer = thread::spawn(move || {
+            let call = peer.read_message();
+            peer.send(Message::error(
+                &call,
+                "com.example.Error.Failed",
+                "it broke",
+            ));
+            peer
+        });
+
+        match connection.call(call("Fail")).await {
+            Err(ConnectionError::MethodError { name, message }) => {
+                assert_eq!(name, "com.example.Error.Failed");
+                assert_eq!(message, "it broke");
+            }
+            other => panic!("unexpected outcome {:?}", other),
+        }
+        peer.join().unwrap();
+    });
+}
+
+#[test]
+fn late_replies_go_to_subscribers() {
+    run(async {
+        let (connection, mut peer) = connect().await;
+        let mut subscription = connection.subscribe();
+        assert!(matches!(
+            connection
+                .call_with_timeout(call("Slow"), Duration::from_millis(10))
+                .await,
+            Err(ConnectionError::Timeout)
+        ));
+
+        let serial = peer.read_message().header.serial();
+        peer.reply(serial, vec![]);
+        let late = subscription.next().await.unwrap();
+        assert_eq!(late.header.reply_serial(), Some(serial));
+    });
+}
+
+#[test]
+fn calls_expecting_no_reply() {
+    run(async {
+        let (connection, mut peer) = connect().await;
+        let mut message = call("Forget");
+        message.header.fixed.flags |= MessageFlags::NO_REPLY_EXPECTED;
+        assert!(connection.call(message).await.unwrap().is_none());
+        assert_eq!(connection.send(signal("Next")).await.unwrap(), 2);
+
+        let received = peer.read_message();
+        assert_eq!(received.header.member(), Some("Forget"));
+        assert_eq!(received.header.serial(), 1);
+        assert_eq!(peer.read_message().header.serial(), 2);
+    });
+}
+
+#[test]
+fn match_rules_filter_subscriptions() {
+    run(async {
+        let (connection, mut peer) = connect().await;
+        let peer = thread::spawn(move || {
+            let add_match = peer.read_message();
+            assert_eq!(add_match.header.member(), Some("AddMatch"));
+            assert_eq!(
+                add_match.message,
+                vec![string("type='signal',member='Wanted'")]
+            );
+            peer.reply(add_match.header.serial(), vec![]);
+            peer.send(signal("Unwanted"));
+            peer.send(signal("Wanted"));
+            peer
+        });
+
+        let rule: MatchRule = "type='signal',member='Wanted'".parse().unwrap();
+        let mut subscription = connection.add_match(rule.clone()).await.unwrap();
+        assert_eq!(subscription.rule(), Some(&rule));
+        let received = subscription.next().await.unwrap();
+        assert_eq!(received.header.member(), Some("Wanted"));
+        peer.join().unwrap();
+    });
+}
+
+#[test]
+fn closes_when_the_peer_hangs_up() {
+    run(async {
+        let (connection, mut peer) = connect().await;
+        let mut subscription = connection.subscribe();
+        peer.send(signal("Last"));
+        drop(peer);
+
+        let last = subscription.next().await.unwrap();
+        assert_eq!(last.header.member(), Some("Last"));
+        assert!(subscription.next().await.is_none());
+        assert!(connection.is_closed());
+        assert!(matches!(
+            connection.send(signal("Late")).await,
+            Err(ConnectionError::Disconnected)
+        ));
+        assert!(matches!(
+            connection.call(call("Late")).await,
+            Err(ConnectionError::Disconnected)
+        ));
+    });
+}
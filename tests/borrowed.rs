@@ -0,0 +1,52 @@
+mod common;
+
+use common::signature;
+use conducto_nom::components::MessageEndianness;
+use conducto_nom::types::borrowed::DbusBorrowedTypeContainer;
+use conducto_nom::*;
+
+/// A body of one `g` value holding `s`
+fn signature_body(s: &str) -> Vec<u8> {
+    let mut buf = vec![s.len() as u8];
+    buf.extend_from_slice(s.as_bytes());
+    buf.push(0);
+    buf
+}
+
+#[test]
+fn decodes_strings_in_place() {
+    let buf = [5, 0, 0, 0, b'h', b'e', b'l', b'l', b'o', 0];
+    let ctx = ParseContext::new(MessageEndianness::LittleEndian, &buf);
+    let (rest, values) = signature("s").parse_buffer_borrowed(&buf, &ctx).unwrap();
+    assert!(rest.is_empty());
+    match &values[..] {
+        [DbusBorrowedTypeContainer::String(s)] => {
+            assert_eq!(&**s, "hello");
+            assert_eq!(s.as_ptr(), buf[4..].as_ptr());
+        }
+        other => panic!("unexpected values {:?}", other),
+    }
+}
+
+#[test]
+fn both_paths_validate_signatures() {
+    let valid = signature_body("a{sv}");
+    let ctx = ParseContext::new(MessageEndianness::LittleEndian, &valid);
+    assert!(signature("g").parse_buffer(&valid, &ctx).is_ok());
+    assert!(signature("g").parse_buffer_borrowed(&valid, &ctx).is_ok());
+
+    for invalid in ["a", "()", "a{vs}", "(i", "z"].iter() {
+        let buf = signature_body(invalid);
+        let ctx = ParseContext::new(MessageEndianness::LittleEndian, &buf);
+        assert!(
+            signature("g").parse_buffer(&buf, &ctx).is_err(),
+            "{}",
+            invalid
+        );
+        assert!(
+            signature("g").parse_buffer_borrowed(&buf, &ctx).is_err(),
+            "{}",
+            invalid
+        );
+    }
+}
@@ -0,0 +1,259 @@
+mod common;
+
+use common::string;
+use conducto_nom::components::MessageType;
+use conducto_nom::*;
+
+const DBUS: &str = "org.freedesktop.DBus";
+
+fn call(connection: &mut Connection, member: &str, body: Vec<DbusTypeContainer>) -> Message {
+    try_call(connection, member, body).unwrap()
+}
+
+fn try_call(
+    connection: &mut Connection,
+    member: &str,
+    body: Vec<DbusTypeContainer>,
+) -> Result<Message, ConnectionError> {
+    let mut message = Message::method_call(Some(DBUS), "/org/freedesktop/DBus", Some(DBUS), member)
+        .unwrap()
+        .with_body(body);
+    connection.call(&mut message)
+}
+
+fn request_name(connection: &mut Connection, name: &str, flags: u32) -> DbusTypeContainer {
+    let reply = call(
+        connection,
+        "RequestName",
+        vec![string(name), DbusTypeContainer::Uint32(flags.into())],
+    );
+    reply.message[0].clone()
+}
+
+fn error_name(result: Result<Message, ConnectionError>) -> String {
+    match result {
+        Err(ConnectionError::MethodError { name, .. }) => name,
+        other => panic!("unexpected outcome {:?}", other),
+    }
+}
+
+/// Skips the messages the bus sends on its own, such as `NameAcquired`
+fn receive_from(connection: &mut Connection, sender: &str) -> Message {
+    loop {
+        let message = connection.receive().unwrap();
+        if message.header.sender() == Some(sender) {
+            return message;
+        }
+    }
+}
+
+#[test]
+fn hello_assigns_unique_names() {
+    let bus = Bus::new().unwrap();
+    let mut a = bus.connect().unwrap();
+    let mut b = bus.connect().unwrap();
+    let a_name = a.unique_name().unwrap().to_string();
+    let b_name = b.unique_name().unwrap().to_string();
+    assert!(a_name.starts_with(':'));
+    assert_ne!(a_name, b_name);
+    assert_eq!(a.guid(), bus.guid());
+
+    let reply = call(&mut a, "ListNames", vec![]);
+    let names = match &reply.message[0] {
+        DbusTypeContainer::Array(names) => names.clone().into_inner(),
+        other => panic!("unexpected reply {:?}", other),
+    };
+    for name in [DBUS, &a_name, &b_name].iter() {
+        assert!(names.contains(&string(name)));
+    }
+
+    assert_eq!(
+        error_name(try_call(&mut b, "Hello", vec![])),
+        error_name::FAILED
+    );
+}
+
+#[test]
+fn name_ownership() {
+    let bus = Bus::new().unwrap();
+    let mut a = bus.connect().unwrap();
+    let mut b = bus.connect().unwrap();
+    let a_name = a.unique_name().unwrap().to_string();
+
+    assert_eq!(
+        request_name(&mut a, "com.example.Test", 0),
+        DbusTypeContainer::Uint32(1.into())
+    );
+    assert_eq!(
+        request_name(&mut a, "com.example.Test", 0),
+        DbusTypeContainer::Uint32(4.into())
+    );
+    assert_eq!(
+        request_name(&mut b, "com.example.Test", 4),
+        DbusTypeContainer::Uint32(3.into())
+    );
+
+    let reply = call(&mut b, "GetNameOwner", vec![string("com.example.Test")]);
+    assert_eq!(reply.message, vec![string(&a_name)]);
+    let reply = call(&mut b, "NameHasOwner", vec![string("com.example.Test")]);
+    assert_eq!(reply.message, vec![DbusTypeContainer::Boolean(true.into())]);
+
+    let release = |connection: &mut Connection| {
+        call(connection, "ReleaseName", vec![string("com.example.Test")]).message[0].clone()
+    };
+    assert_eq!(release(&mut b), DbusTypeContainer::Uint32(3.into()));
+    assert_eq!(release(&mut a), DbusTypeContainer::Uint32(1.into()));
+    assert_eq!(release(&mut a), DbusTypeContainer::Uint32(2.into()));
+
+    let result = try_call(&mut b, "GetNameOwner", vec![string("com.example.Test")]);
+    assert_eq!(error_name(result), error_name::NAME_HAS_NO_OWNER);
+    let result = try_call(&mut b, "RequestName", vec![string("invalid")]);
+    assert_eq!(error_name(result), error_name::INVALID_ARGS);
+    assert_eq!(
+        error_name(try_call(&mut b, "Frobnicate", vec![])),
+        error_name::UNKNOWN_METHOD
+    );
+}
+
+#[test]
+fn routes_calls_by_destination() {
+    let bus = Bus::new().unwrap();
+    let mut service = bus.connect().unwrap();
+    let mut client = bus.connect().unwrap();
+    request_name(&mut service, "com.example.Echo", 0);
+    let client_name = client.unique_name().unwrap().to_string();
+
+    let handle = std::thread::spawn(move || {
+        let call = receive_from(&mut service, &client_name);
+        assert_eq!(call.header.member(), Some("Echo"));
+        let mut reply = Message::method_return(&call).with_body(call.message.clone());
+        service.send(&mut reply).unwrap();
+    });
+
+    let mut message = Message::method_call(
+        Some("com.example.Echo"),
+        "/com/example/Echo",
+        Some("com.example.Echo"),
+        "Echo",
+    )
+    .unwrap()
+    .with_body(vec![string("ping")]);
+    let reply = client.call(&mut message).unwrap();
+    assert_eq!(reply.message, vec![string("ping")]);
+    handle.join().unwrap();
+
+    let mut message = Message::method_call(Some("com.example.Missing"), "/", None, "Echo").unwrap();
+    assert_eq!(
+        error_name(client.call(&mut message)),
+        error_name::SERVICE_UNKNOWN
+    );
+}
+
+#[test]
+fn routes_signals_by_match_rules() {
+    let bus = Bus::new().unwrap();
+    let mut emitter = bus.connect().unwrap();
+    let mut listener = bus.connect().unwrap();
+    let emitter_name = emitter.unique_name().unwrap().to_string();
+    request_name(&mut emitter, "com.example.Emitter", 0);
+
+    let rule: MatchRule = "type='signal',sender='com.example.Emitter',member='Tick'"
+        .parse()
+        .unwrap();
+    listener.add_match(&rule).unwrap();
+
+    for member in ["Tock", "Tick"].iter() {
+        let mut signal = Message::signal("/com/example", "com.example.Clock", member).unwrap();
+        emitter.send(&mut signal).unwrap();
+    }
+
+    let signal = receive_from(&mut listener, &emitter_name);
+    assert_eq!(signal.header.message_type(), MessageType::Signal);
+    assert_eq!(signal.header.member(), Some("Tick"));
+
+    listener.remove_match(&rule).unwrap();
+    let result = listener.remove_match(&rule);
+    match result {
+        Err(ConnectionError::MethodError { name, .. }) => {
+            assert_eq!(name, error_name::MATCH_RULE_NOT_FOUND)
+        }
+        other => panic!("unexpected outcome {:?}", other),
+    }
+}
+
+#[test]
+fn disconnect_releases_names() {
+    let bus = Bus::new().unwrap();
+    let mut watcher = bus.connect().unwrap();
+    let mut owner = bus.connect().unwrap();
+    let owner_name = owner.unique_name().unwrap().to_string();
+    request_name(&mut owner, "com.example.Gone", 0);
+
+    let rule: MatchRule = "type='signal',member='NameOwnerChanged',arg0='com.example.Gone'"
+        .parse()
+        .unwrap();
+    watcher.add_match(&rule).unwrap();
+    drop(owner);
+
+    let signal = loop {
+        let signal = receive_from(&mut watcher, DBUS);
+        if signal.header.member() == Some("NameOwnerChanged") {
+            break signal;
+        }
+    };
+    assert_eq!(
+        signal.message,
+        vec![string("com.example.Gone"), string(&owner_name), string("")]
+    );
+    let reply = call(
+        &mut watcher,
+        "NameHasOwner",
+        vec![string("com.example.Gone")],
+    );
+    assert_eq!(
+        reply.message,
+        vec![DbusTypeContainer::Boolean(false.into())]
+    );
+}
+
+#[test]
+fn queued_client_gets_the_name() {
+    let bus = Bus::new().unwrap();
+    let mut first = bus.connect().unwrap();
+    let mut second = bus.connect().unwrap();
+    let first_name = first.unique_name().unwrap().to_string();
+    let second_name = second.unique_name().unwrap().to_string();
+
+    request_name(&mut first, "com.example.Queue", 0);
+    assert_eq!(
+        request_name(&mut second, "com.example.Queue", 0),
+        DbusTypeContainer::Uint32(2.into())
+    );
+    let reply = call(
+        &mut first,
+        "ListQueuedOwners",
+        vec![string("com.example.Queue")],
+    );
+    let owners = match &reply.message[0] {
+        DbusTypeContainer::Array(owners) => owners.clone().into_inner(),
+        other => panic!("unexpected reply {:?}", other),
+    };
+    assert_eq!(owners, vec![string(&first_name), string(&second_name)]);
+
+    drop(first);
+    let acquired = loop {
+        let signal = receive_from(&mut second, DBUS);
+        if signal.header.member() == Some("NameAcquired")
+            && signal.message == vec![string("com.example.Queue")]
+        {
+            break signal;
+        }
+    };
+    assert_eq!(acquired.header.destination(), Some(second_name.as_str()));
+    let reply = call(
+        &mut second,
+        "GetNameOwner",
+        vec![string("com.example.Queue")],
+    );
+    assert_eq!(reply.message, vec![string(&second_name)]);
+}
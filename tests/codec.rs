@@ -0,0 +1,155 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use bytes::BytesMut;
+use common::{string, GUID};
+use conducto_nom::sasl::{ExternalClient, SaslClient};
+use conducto_nom::*;
+use std::os::unix::net::UnixStream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+fn signal(member: &str) -> Message {
+    let mut message = Message::signal("/com/example", "com.example.Codec", member)
+        .unwrap()
+        .with_body(vec![string("value")]);
+    message.header.fixed.msg_serial = 1;
+    message
+}
+
+#[test]
+fn decodes_partial_frames() {
+    let mut codec = MessageCodec::new();
+    let mut data = signal("First").marshal();
+    let first_len = data.len();
+    data.extend_from_slice(&signal("Second").marshal());
+
+    let mut src = BytesMut::new();
+    let mut decoded = vec![];
+    for (i, b) in data.iter().enumerate() {
+        src.extend_from_slice(&[*b]);
+        match codec.decode(&mut src).unwrap() {
+            Some(message) => decoded.push((i + 1, message)),
+            None => assert!(!src.is_empty()),
+        }
+    }
+
+    assert!(src.is_empty());
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(decoded[0].0, first_len);
+    assert_eq!(decoded[0].1.marshal(), signal("First").marshal());
+    assert_eq!(decoded[1].0, data.len());
+    assert_eq!(decoded[1].1.marshal(), signal("Second").marshal());
+}
+
+#[test]
+fn encodes_messages() {
+    let mut codec = MessageCodec::new();
+    let mut dst = BytesMut::new();
+    codec.encode(&signal("First"), &mut dst).unwrap();
+    codec.encode(signal("Second"), &mut dst).unwrap();
+
+    let mut expected = signal("First").marshal();
+    expected.extend_from_slice(&signal("Second").marshal());
+    assert_eq!(&dst[..], &expected[..]);
+}
+
+#[test]
+fn message_length_limit() {
+    let data = signal("Long").marshal();
+    let limits = ParseLimits {
+        max_message_len: data.len() - 1,
+        ..ParseLimits::default()
+    };
+    let mut codec = MessageCodec::new().with_limits(limits);
+    assert_eq!(codec.limits(), &limits);
+
+    // The fixed header is enough to tell the message is too long
+    let mut src = BytesMut::from(&data[..16]);
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(ConnectionError::TooLarge)
+    ));
+
+    let mut dst = BytesMut::new();
+    assert!(matches!(
+        codec.encode(&signal("Long"), &mut dst),
+        Err(ConnectionError::TooLarge)
+    ));
+    assert!(dst.is_empty());
+
+    let mut codec = MessageCodec::new().with_limits(ParseLimits {
+        max_message_len: data.len(),
+        ..ParseLimits::default()
+    });
+    let mut src = BytesMut::from(&data[..]);
+    assert!(codec.decode(&mut src).unwrap().is_some());
+    assert!(src.is_empty());
+}
+
+#[test]
+fn refuses_descriptors() {
+    let (fd, _) = UnixStream::pair().unwrap();
+    let message = signal("Fd").with_fds(vec![fd.into()]);
+    let mut dst = BytesMut::new();
+    assert!(matches!(
+        MessageCodec::new().encode(&message, &mut dst),
+        Err(ConnectionError::UnixFdNotSupported)
+    ));
+    assert!(dst.is_empty());
+}
+
+#[test]
+fn rejects_invalid_frames() {
+    let mut data = signal("Broken").marshal();
+    data[3] = 2;
+    let mut src = BytesMut::from(&data[..]);
+    assert!(matches!(
+        MessageCodec::new().decode(&mut src),
+        Err(ConnectionError::InvalidMessage)
+    ));
+
+    // A bad endianness byte is caught as soon as the fixed header is in
+    let mut data = signal("Broken").marshal();
+    data[0] = b'x';
+    let mut src = BytesMut::from(&data[..15]);
+    assert!(matches!(MessageCodec::new().decode(&mut src), Ok(None)));
+    src.extend_from_slice(&data[15..16]);
+    assert!(matches!(
+        MessageCodec::new().decode(&mut src),
+        Err(ConnectionError::InvalidMessage)
+    ));
+}
+
+#[test]
+fn authenticate_keeps_what_follows_the_handshake() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let (client, mut server) = tokio::io::duplex(4096);
+        // The server answers before the client even asks, then sends a message right away
+        let mut data = format!("OK {}\r\n", GUID).into_bytes();
+        data.extend_from_slice(&signal("Early").marshal());
+        server.write_all(&data).await.unwrap();
+
+        let sasl = SaslClient::new(vec![Box::new(ExternalClient::new())]);
+        let (framed, guid) = authenticate(client, sasl, MessageCodec::new())
+            .await
+            .unwrap();
+        assert_eq!(guid, GUID);
+
+        // Whatever was read past the handshake starts the first message
+        let mut parts = framed.into_parts();
+        assert!(!parts.read_buf.is_empty());
+        let message = loop {
+            if let Some(message) = parts.codec.decode(&mut parts.read_buf).unwrap() {
+                break message;
+            }
+            assert_ne!(parts.io.read_buf(&mut parts.read_buf).await.unwrap(), 0);
+        };
+        assert_eq!(message.marshal(), signal("Early").marshal());
+    });
+}
@@ -0,0 +1,259 @@
+//! Helpers shared by the integration tests
+//!
+//! Each test binary compiles its own copy and uses only some of them.
+#![allow(dead_code)]
+
+use conducto_nom::components::{HeaderField, MessageEndianness, MessageType};
+use conducto_nom::sasl::{current_uid, Command, ExternalServer, SaslServer};
+use conducto_nom::types::basic::DbusObjectPath;
+use conducto_nom::{Connection, DbusTypeContainer, HeaderPeek, Message, Signature};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+pub fn signature(s: &str) -> Signature {
+    s.parse().unwrap()
+}
+
+pub fn string(value: &str) -> DbusTypeContainer {
+    DbusTypeContainer::String(value.into())
+}
+
+pub fn path(path: &str) -> DbusObjectPath {
+    DbusObjectPath::try_from(path).unwrap()
+}
+
+/// Writes values by hand in either byte order, aligned relative to the start of the buffer
+pub struct Encoder {
+    pub buf: Vec<u8>,
+    endianness: MessageEndianness,
+}
+
+impl Encoder {
+    pub fn new(endianness: MessageEndianness) -> Self {
+        Self {
+            buf: vec![],
+            endianness,
+        }
+    }
+
+    pub fn align(&mut self, alignment: usize) -> &mut Self {
+        while !self.buf.len().is_multiple_of(alignment) {
+            self.buf.push(0);
+        }
+        self
+    }
+
+    /// Writes `le_bytes` aligned to their size, reversed for big endian
+    fn put(&mut self, le_bytes: &[u8]) -> &mut Self {
+        self.align(le_bytes.len());
+        match self.endianness {
+            MessageEndianness::LittleEndian => self.buf.extend(le_bytes.iter()),
+            MessageEndianness::BigEndian => self.buf.extend(le_bytes.iter().rev()),
+        }
+        self
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.put(&[value])
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.put(&value.to_le_bytes())
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.put(&value.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.put(&value.to_le_bytes())
+    }
+
+    pub fn str(&mut self, value: &str) -> &mut Self {
+        self.u32(value.len() as u32);
+        self.buf.extend(value.bytes());
+        self.buf.push(0);
+        self
+    }
+
+    pub fn signature(&mut self, value: &str) -> &mut Self {
+        self.u8(value.len() as u8);
+        self.buf.extend(value.bytes());
+        self.buf.push(0);
+        self
+    }
+
+    /// Writes an array whose elements, aligned to `alignment`, are written by `elements`
+    pub fn array(&mut self, alignment: usize, elements: impl FnOnce(&mut Self)) -> &mut Self {
+        self.u32(0);
+        let len_at = self.buf.len() - 4;
+        self.align(alignment);
+        let start = self.buf.len();
+        elements(self);
+
+        let len = (self.buf.len() - start) as u32;
+        let len = match self.endianness {
+            MessageEndianness::LittleEndian => len.to_le_bytes(),
+            MessageEndianness::BigEndian => len.to_be_bytes(),
+        };
+        self.buf[len_at..len_at + 4].copy_from_slice(&len);
+        self
+    }
+}
+
+/// Encodes a message with the string-valued header `fields` and the encoded `body`
+pub fn message(
+    endianness: MessageEndianness,
+    message_type: MessageType,
+    serial: u32,
+    reply_serial: Option<u32>,
+    fields: &[(HeaderField, &str)],
+    body_signature: &str,
+    body: &[u8],
+) -> Vec<u8> {
+    let mut encoder = Encoder::new(endianness);
+    encoder
+        .u8(endianness as u8)
+        .u8(message_type as u8)
+        .u8(0)
+        .u8(1);
+    encoder.u32(body.len() as u32).u32(serial);
+    encoder.array(8, |e| {
+        for (field, value) in fields {
+            let signature = match field {
+                HeaderField::Path => "o",
+                _ => "s",
+            };
+            e.align(8).u8(*field as u8).signature(signature).str(value);
+        }
+        if let Some(reply_serial) = reply_serial {
+            e.align(8)
+                .u8(HeaderField::ReplySerial as u8)
+                .signature("u")
+                .u32(reply_serial);
+        }
+        if !body_signature.is_empty() {
+            e.align(8)
+                .u8(HeaderField::Signature as u8)
+                .signature("g")
+                .signature(body_signature);
+        }
+    });
+    encoder.align(8);
+    encoder.buf.extend_from_slice(body);
+    encoder.buf
+}
+
+/// Encodes a signal from `/com/example` with serial 1 carrying the encoded `body`
+pub fn signal(endianness: MessageEndianness, body_signature: &str, body: &[u8]) -> Vec<u8> {
+    message(
+        endianness,
+        MessageType::Signal,
+        1,
+        None,
+        &[
+            (HeaderField::Path, "/com/example"),
+            (HeaderField::Interface, "com.example.Signals"),
+            (HeaderField::Member, "Changed"),
+        ],
+        body_signature,
+        body,
+    )
+}
+
+/// The server end of a socket pair, speaking just enough of the protocol for a test
+pub struct Peer {
+    pub stream: UnixStream,
+    buf: Vec<u8>,
+    next_serial: u32,
+}
+
+impl Peer {
+    /// Runs the server side of the handshake, agreeing to pass descriptors if `unix_fd`
+    pub fn accept(stream: UnixStream, unix_fd: bool) -> Self {
+        let mut sasl = SaslServer::new(
+            GUID.to_string(),
+            vec![Box::new(ExternalServer::new(Some(current_uid())))],
+        );
+        if unix_fd {
+            sasl = sasl.with_unix_fd_support();
+        }
+
+        let mut peer = Self {
+            stream,
+            buf: vec![],
+            next_serial: 1000,
+        };
+        while peer.buf.is_empty() {
+            peer.read_more();
+        }
+        assert_eq!(peer.buf.remove(0), 0);
+
+        while !sasl.is_authenticated() {
+            let command = loop {
+                if let Ok((rest, command)) = Command::parse(&peer.buf) {
+                    let consumed = peer.buf.len() - rest.len();
+                    peer.buf.drain(..consumed);
+                    break command;
+                }
+                peer.read_more();
+            };
+
+            if let Some(answer) = sasl.handle(command).unwrap() {
+                peer.stream.write_all(&answer.to_bytes()).unwrap();
+            }
+        }
+
+        peer
+    }
+
+    pub fn read_message(&mut self) -> Message {
+        loop {
+            if let Ok((_, peek)) = HeaderPeek::from_bytes(&self.buf) {
+                let len = peek.message_len();
+                if self.buf.len() >= len {
+                    let (_, message) = Message::parse(&self.buf[..len]).unwrap();
+                    self.buf.drain(..len);
+                    return message;
+                }
+            }
+
+            self.read_more();
+        }
+    }
+
+    /// Writes `message` under the next serial of the peer, returning that serial
+    pub fn send(&mut self, mut message: Message) -> u32 {
+        let serial = self.next_serial;
+        self.next_serial += 1;
+        message.header.fixed.msg_serial = serial;
+        self.stream.write_all(&message.marshal()).unwrap();
+        serial
+    }
+
+    /// Replies to the call the client numbered `serial`
+    pub fn reply(&mut self, serial: u32, body: Vec<DbusTypeContainer>) -> u32 {
+        let mut call = Message::method_call(None, "/", None, "Call").unwrap();
+        call.header.fixed.msg_serial = serial;
+        self.send(Message::method_return(&call).with_body(body))
+    }
+
+    fn read_more(&mut self) {
+        let mut chunk = [0; 4096];
+        let n = self.stream.read(&mut chunk).unwrap();
+        assert_ne!(n, 0, "the client hung up");
+        self.buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+pub const GUID: &str = "0123456789abcdef0123456789abcdef";
+
+/// A connection to a fresh peer, over a socket pair
+pub fn socketpair(unix_fd: bool) -> (Connection, Peer) {
+    let (client, server) = UnixStream::pair().unwrap();
+    let peer = thread::spawn(move || Peer::accept(server, unix_fd));
+    let connection = Connection::from_stream(client).unwrap();
+    (connection, peer.join().unwrap())
+}
@@ -0,0 +1,128 @@
+mod common;
+
+use common::{path, signature, string, Encoder};
+use conducto_nom::components::MessageEndianness;
+use conducto_nom::types::containers::{
+    DbusArray, DbusDict, DbusDictEntry, DbusStruct, DbusVariant,
+};
+use conducto_nom::*;
+use std::sync::Arc;
+
+fn variant(value: DbusTypeContainer) -> DbusTypeContainer {
+    DbusTypeContainer::Variant(Box::new(DbusVariant::new(value)))
+}
+
+fn body() -> Vec<DbusTypeContainer> {
+    let entries = vec![
+        DbusDictEntry::new(
+            string("volume"),
+            variant(DbusTypeContainer::Double(0.5.into())),
+        ),
+        DbusDictEntry::new(
+            string("muted"),
+            variant(DbusTypeContainer::Boolean(false.into())),
+        ),
+    ];
+    vec![
+        DbusTypeContainer::Byte(1.into()),
+        DbusTypeContainer::Int64((-2).into()),
+        DbusTypeContainer::Uint16(3.into()),
+        string("text"),
+        DbusTypeContainer::Struct(
+            DbusStruct::new(vec![
+                DbusTypeContainer::Int32(4.into()),
+                DbusTypeContainer::ObjectPath(path("/com/example")),
+            ])
+            .unwrap(),
+        ),
+        DbusTypeContainer::Array(
+            DbusArray::new(
+                signature("(yd)"),
+                vec![DbusTypeContainer::Struct(
+                    DbusStruct::new(vec![
+                        DbusTypeContainer::Byte(5.into()),
+                        DbusTypeContainer::Double(6.5.into()),
+                    ])
+                    .unwrap(),
+                )],
+            )
+            .unwrap(),
+        ),
+        DbusTypeContainer::Dict(DbusDict::new(signature("s"), signature("v"), entries).unwrap()),
+        DbusTypeContainer::Uint32Array(vec![7, 8]),
+    ]
+}
+
+/// Encodes `body()` by hand
+fn encode(endianness: MessageEndianness) -> Vec<u8> {
+    let mut encoder = Encoder::new(endianness);
+    encoder.u8(1).u64(-2i64 as u64).u16(3).str("text");
+    encoder.align(8).u32(4).str("/com/example");
+    encoder.array(8, |e| {
+        e.u8(5).u64(6.5f64.to_bits());
+    });
+    encoder.array(8, |e| {
+        e.str("volume").signature("d").u64(0.5f64.to_bits());
+        e.align(8).str("muted").signature("b").u32(0);
+    });
+    encoder.array(4, |e| {
+        e.u32(7).u32(8);
+    });
+    encoder.buf
+}
+
+#[test]
+fn decodes_like_the_signature() {
+    let compiled = CompiledSignature::new(signature("yxqs(io)a(yd)a{sv}au")).unwrap();
+    assert_eq!(compiled.signature(), &signature("yxqs(io)a(yd)a{sv}au"));
+
+    for endianness in [
+        MessageEndianness::LittleEndian,
+        MessageEndianness::BigEndian,
+    ]
+    .iter()
+    {
+        let buf = encode(*endianness);
+        let ctx = ParseContext::new(*endianness, &buf);
+        let (rest, values) = compiled.parse_buffer(&buf, &ctx).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(values, body());
+
+        let (_, expected) = compiled.signature().parse_buffer(&buf, &ctx).unwrap();
+        assert_eq!(values, expected);
+    }
+}
+
+#[test]
+fn fails_on_truncated_input() {
+    let buf = encode(MessageEndianness::LittleEndian);
+    let compiled = CompiledSignature::new(signature("yxqs(io)a(yd)a{sv}au")).unwrap();
+    for end in 0..buf.len() {
+        let truncated = &buf[..end];
+        let ctx = ParseContext::new(MessageEndianness::LittleEndian, truncated);
+        assert!(compiled.parse_buffer(truncated, &ctx).is_err(), "{}", end);
+    }
+}
+
+#[test]
+fn rejects_invalid_signatures() {
+    assert!(CompiledSignature::new(Signature::new(vec![SignatureType::Array])).is_err());
+    assert!(SignatureCache::new(4).get("a{vs}").is_err());
+}
+
+#[test]
+fn cache_evicts_the_least_recently_used() {
+    let mut cache = SignatureCache::new(2);
+    assert!(cache.is_empty());
+
+    let first = cache.get("a{sv}").unwrap();
+    let second = cache.get("(iu)").unwrap();
+    assert!(Arc::ptr_eq(&first, &cache.get("a{sv}").unwrap()));
+    assert_eq!(cache.len(), 2);
+
+    // "(iu)" is now the least recently used
+    cache.get("as").unwrap();
+    assert_eq!(cache.len(), 2);
+    assert!(Arc::ptr_eq(&first, &cache.get("a{sv}").unwrap()));
+    assert!(!Arc::ptr_eq(&second, &cache.get("(iu)").unwrap()));
+}
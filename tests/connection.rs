@@ -0,0 +1,151 @@
+mod common;
+
+use common::{socketpair, string, GUID};
+use conducto_nom::components::MessageType;
+use conducto_nom::*;
+use std::io::Write;
+
+fn signal(member: &str) -> Message {
+    Message::signal("/com/example", "com.example.Connection", member).unwrap()
+}
+
+#[test]
+fn handshake() {
+    let (connection, _peer) = socketpair(false);
+    assert_eq!(connection.guid(), GUID);
+    assert!(!connection.unix_fd_agreed());
+    assert_eq!(connection.unique_name(), None);
+
+    let (connection, _peer) = socketpair(true);
+    assert!(connection.unix_fd_agreed());
+}
+
+#[test]
+fn hello_keeps_the_unique_name() {
+    let (mut connection, mut peer) = socketpair(false);
+    // Serials start at 1, so the reply can be written ahead of the call
+    peer.reply(1, vec![string(":1.7")]);
+    assert_eq!(connection.hello().unwrap(), ":1.7");
+    assert_eq!(connection.unique_name(), Some(":1.7"));
+
+    let hello = peer.read_message();
+    assert_eq!(hello.header.serial(), 1);
+    assert_eq!(hello.header.destination(), Some("org.freedesktop.DBus"));
+    assert_eq!(hello.header.member(), Some("Hello"));
+}
+
+#[test]
+fn send_numbers_messages() {
+    let (mut connection, mut peer) = socketpair(false);
+    for serial in 1..4 {
+        assert_eq!(connection.send(&mut signal("Changed")).unwrap(), serial);
+        let received = peer.read_message();
+        assert_eq!(received.header.serial(), serial);
+        assert_eq!(received.header.member(), Some("Changed"));
+    }
+}
+
+#[test]
+fn call_queues_other_messages() {
+    let (mut connection, mut peer) = socketpair(false);
+    peer.send(signal("First"));
+    peer.reply(7, vec![]);
+    peer.send(signal("Second"));
+    peer.reply(1, vec![string("done")]);
+
+    let mut call = Message::method_call(Some("com.example"), "/", None, "Run").unwrap();
+    let reply = connection.call(&mut call).unwrap();
+    assert_eq!(reply.header.reply_serial(), Some(1));
+    assert_eq!(reply.message, vec![string("done")]);
+    assert_eq!(peer.read_message().header.member(), Some("Run"));
+
+    // What arrived in the meantime comes out in order
+    assert_eq!(connection.receive().unwrap().header.member(), Some("First"));
+    assert_eq!(connection.receive().unwrap().header.reply_serial(), Some(7));
+    assert_eq!(
+        connection.receive().unwrap().header.member(),
+        Some("Second")
+    );
+}
+
+#[test]
+fn error_replies() {
+    let (mut connection, mut peer) = socketpair(false);
+    let mut call = Message::method_call(Some("com.example"), "/", None, "Fail").unwrap();
+    call.header.fixed.msg_serial = 1;
+    peer.send(Message::error(
+        &call,
+        "com.example.Error.Failed",
+        "it broke",
+    ));
+
+    match connection.call(&mut call) {
+        Err(ConnectionError::MethodError { name, message }) => {
+            assert_eq!(name, "com.example.Error.Failed");
+            assert_eq!(message, "it broke");
+        }
+        other => panic!("unexpected outcome {:?}", other),
+    }
+
+    let received = peer.read_message();
+    assert_eq!(received.header.message_type(), MessageType::MethodCall);
+}
+
+#[test]
+fn refuses_descriptors_unless_agreed() {
+    let (mut connection, _peer) = socketpair(false);
+    let (fd, _) = std::os::unix::net::UnixStream::pair().unwrap();
+    let mut message = signal("Fd").with_fds(vec![fd.into()]);
+    assert!(matches!(
+        connection.send(&mut message),
+        Err(ConnectionError::UnixFdNotSupported)
+    ));
+}
+
+#[test]
+fn disconnects() {
+    let (mut connection, mut peer) = socketpair(false);
+    peer.send(signal("Last"));
+    drop(peer);
+
+    assert_eq!(connection.receive().unwrap().header.member(), Some("Last"));
+    assert!(matches!(
+        connection.receive(),
+        Err(ConnectionError::Disconnected)
+    ));
+}
+
+#[test]
+fn rejects_oversized_and_invalid_messages() {
+    let (mut connection, mut peer) = socketpair(false);
+    // A fixed header announcing a body past the default limit
+    let mut header = vec![b'l', 4, 0, 1];
+    header.extend_from_slice(&(1u32 << 28).to_le_bytes());
+    header.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+    peer.stream.write_all(&header).unwrap();
+    assert!(matches!(
+        connection.receive(),
+        Err(ConnectionError::TooLarge)
+    ));
+
+    let (mut connection, mut peer) = socketpair(false);
+    let mut buf = signal("Broken").marshal();
+    buf[8] = 1;
+    // Version 2 of the protocol does not exist
+    buf[3] = 2;
+    peer.stream.write_all(&buf).unwrap();
+    assert!(matches!(
+        connection.receive(),
+        Err(ConnectionError::InvalidMessage)
+    ));
+
+    // Without a valid endianness byte the message cannot even be framed
+    let (mut connection, mut peer) = socketpair(false);
+    let mut buf = signal("Broken").marshal();
+    buf[0] = b'x';
+    peer.stream.write_all(&buf).unwrap();
+    assert!(matches!(
+        connection.receive(),
+        Err(ConnectionError::InvalidMessage)
+    ));
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use common::{path, signal, signature, string};
+use conducto_nom::components::{HeaderField, MessageEndianness, MessageType};
+use conducto_nom::types::containers::{
+    DbusArray, DbusDict, DbusDictEntry, DbusStruct, DbusVariant,
+};
+use conducto_nom::*;
+
+fn variant(value: DbusTypeContainer) -> DbusTypeContainer {
+    DbusTypeContainer::Variant(Box::new(DbusVariant::new(value)))
+}
+
+/// A signal carrying every kind of value
+fn message() -> Message {
+    let entries = vec![DbusDictEntry::new(
+        DbusTypeContainer::Uint16(1.into()),
+        variant(DbusTypeContainer::Int64((-5).into())),
+    )];
+    let buf = common::message(
+        MessageEndianness::LittleEndian,
+        MessageType::Signal,
+        0x0102_0304,
+        None,
+        &[
+            (HeaderField::Path, "/com/example"),
+            (HeaderField::Interface, "com.example.Values"),
+            (HeaderField::Member, "Changed"),
+            (HeaderField::Sender, ":1.5"),
+        ],
+        "",
+        &[],
+    );
+    let mut message = parse(&buf);
+    message.message = vec![
+        DbusTypeContainer::Boolean(true.into()),
+        DbusTypeContainer::Byte(2.into()),
+        DbusTypeContainer::Int16((-3).into()),
+        DbusTypeContainer::Uint32(4.into()),
+        DbusTypeContainer::Uint64(5.into()),
+        DbusTypeContainer::Double(6.5.into()),
+        string("text"),
+        DbusTypeContainer::ObjectPath(path("/com/example/Child")),
+        DbusTypeContainer::Signature((&signature("a{sv}")).into()),
+        variant(DbusTypeContainer::Struct(
+            DbusStruct::new(vec![DbusTypeContainer::Byte(7.into()), string("nested")]).unwrap(),
+        )),
+        DbusTypeContainer::Array(
+            DbusArray::new(signature("s"), vec![string("a"), string("bc")]).unwrap(),
+        ),
+        DbusTypeContainer::Dict(DbusDict::new(signature("q"), signature("v"), entries).unwrap()),
+        DbusTypeContainer::Int32Array(vec![-1, 1]),
+        DbusTypeContainer::ByteArray(vec![]),
+    ];
+    message
+}
+
+fn parse(buf: &[u8]) -> Message {
+    let (rest, message) = Message::parse(buf).unwrap();
+    assert!(rest.is_empty());
+    message
+}
+
+#[test]
+fn encodes_in_either_byte_order() {
+    let message = message();
+    for endianness in [
+        MessageEndianness::LittleEndian,
+        MessageEndianness::BigEndian,
+    ]
+    .iter()
+    {
+        let buf = message.to_endianness(*endianness);
+        assert_eq!(buf[0], *endianness as u8);
+
+        let (_, peek) = HeaderPeek::from_bytes(&buf).unwrap();
+        assert_eq!(peek.fixed.endianness, *endianness);
+        assert_eq!(peek.serial(), 0x0102_0304);
+        assert_eq!(peek.sender, Some(":1.5"));
+
+        let parsed = parse(&buf);
+        assert_eq!(parsed.message, message.message);
+
+        // Decoding then encoding again gives back the same bytes
+        assert_eq!(parsed.marshal(), buf);
+    }
+}
+
+#[test]
+fn swaps_encoded_messages() {
+    let message = message();
+    let le = message.to_endianness(MessageEndianness::LittleEndian);
+    let be = message.to_endianness(MessageEndianness::BigEndian);
+
+    assert_eq!(swap_endianness(&le).unwrap(), be);
+    assert_eq!(swap_endianness(&be).unwrap(), le);
+    assert_eq!(
+        parse(&swap_endianness(&le).unwrap()).message,
+        message.message
+    );
+}
+
+#[test]
+fn swaps_only_the_first_message() {
+    let mut buf = message().to_endianness(MessageEndianness::LittleEndian);
+    let len = buf.len();
+    buf.extend_from_slice(b"next");
+    assert_eq!(swap_endianness(&buf).unwrap().len(), len);
+}
+
+/// Encodes a signal with `body`, then cuts the body down to `body_len` bytes
+fn truncated(body: Vec<DbusTypeContainer>, body_len: usize) -> Vec<u8> {
+    let mut message = parse(&signal(MessageEndianness::LittleEndian, "", &[]));
+    message.message = body;
+    let mut buf = message.marshal();
+    let (_, peek) = HeaderPeek::from_bytes(&buf).unwrap();
+    buf.truncate(peek.header_len + body_len);
+    buf[4..8].copy_from_slice(&(body_len as u32).to_le_bytes());
+    buf
+}
+
+#[test]
+fn rejects_truncated_bodies() {
+    let cases = [
+        (vec![string("text")], 2),
+        (vec![DbusTypeContainer::Uint64(1.into())], 4),
+        (vec![DbusTypeContainer::Uint32Array(vec![1, 2])], 3),
+        (vec![DbusTypeContainer::Uint32Array(vec![1, 2])], 8),
+        (
+            vec![DbusTypeContainer::Array(
+                DbusArray::new(signature("s"), vec![string("a")]).unwrap(),
+            )],
+            6,
+        ),
+        (vec![variant(DbusTypeContainer::Int16(1.into()))], 4),
+    ];
+
+    for (body, body_len) in cases.iter() {
+        let buf = truncated(body.clone(), *body_len);
+        assert!(
+            matches!(swap_endianness(&buf), Err(DbusParseError::InvalidBody)),
+            "{:?}",
+            body
+        );
+    }
+
+    assert!(matches!(
+        swap_endianness(&[b'l', 4, 0, 1]),
+        Err(DbusParseError::MalformedHeader)
+    ));
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use common::{signal, signature};
+use conducto_nom::components::MessageEndianness;
+use conducto_nom::*;
+
+fn parse(signature_str: &str, endianness: MessageEndianness, buf: &[u8]) -> Vec<DbusTypeContainer> {
+    let ctx = ParseContext::new(endianness, buf);
+    let (rest, values) = signature(signature_str).parse_buffer(buf, &ctx).unwrap();
+    assert!(rest.is_empty());
+    values
+}
+
+#[test]
+fn decodes_in_either_byte_order() {
+    let le = [8, 0, 0, 0, 1, 0, 0, 0, 0xef, 0xbe, 0xad, 0xde];
+    let be = [0, 0, 0, 8, 0, 0, 0, 1, 0xde, 0xad, 0xbe, 0xef];
+    let expected = vec![DbusTypeContainer::Uint32Array(vec![1, 0xdead_beef])];
+    assert_eq!(parse("au", MessageEndianness::LittleEndian, &le), expected);
+    assert_eq!(parse("au", MessageEndianness::BigEndian, &be), expected);
+
+    assert_eq!(
+        parse(
+            "ay",
+            MessageEndianness::LittleEndian,
+            &[3, 0, 0, 0, 1, 2, 3]
+        ),
+        vec![DbusTypeContainer::ByteArray(vec![1, 2, 3])]
+    );
+    assert_eq!(
+        parse(
+            "an",
+            MessageEndianness::LittleEndian,
+            &[4, 0, 0, 0, 0xff, 0xff, 2, 0]
+        ),
+        vec![DbusTypeContainer::Int16Array(vec![-1, 2])]
+    );
+}
+
+#[test]
+fn skips_padding_before_the_first_element() {
+    // 8-byte elements start on the next 8-byte boundary, even for empty arrays
+    let mut buf = vec![8, 0, 0, 0, 0, 0, 0, 0];
+    buf.extend_from_slice(&0.5f64.to_le_bytes());
+    assert_eq!(
+        parse("ad", MessageEndianness::LittleEndian, &buf),
+        vec![DbusTypeContainer::DoubleArray(vec![0.5])]
+    );
+
+    assert_eq!(
+        parse("ax", MessageEndianness::LittleEndian, &[0; 8]),
+        vec![DbusTypeContainer::Int64Array(vec![])]
+    );
+}
+
+#[test]
+fn rejects_partial_elements() {
+    let buf = [6, 0, 0, 0, 1, 0, 0, 0, 2, 0];
+    let ctx = ParseContext::new(MessageEndianness::LittleEndian, &buf);
+    assert!(signature("au").parse_buffer(&buf, &ctx).is_err());
+}
+
+/// Encodes an array of `size`-byte elements, given as their little endian bytes
+fn encode(endianness: MessageEndianness, size: usize, elements: &[Vec<u8>]) -> Vec<u8> {
+    let len = (size * elements.len()) as u32;
+    let mut buf = match endianness {
+        MessageEndianness::LittleEndian => len.to_le_bytes().to_vec(),
+        MessageEndianness::BigEndian => len.to_be_bytes().to_vec(),
+    };
+    buf.resize(std::cmp::max(4, size), 0);
+    for element in elements {
+        match endianness {
+            MessageEndianness::LittleEndian => buf.extend(element.iter()),
+            MessageEndianness::BigEndian => buf.extend(element.iter().rev()),
+        }
+    }
+    buf
+}
+
+#[test]
+fn borrowed_decoding_matches_owned() {
+    let cases = [
+        ("ay", 1),
+        ("aq", 2),
+        ("an", 2),
+        ("au", 4),
+        ("ai", 4),
+        ("at", 8),
+        ("ax", 8),
+        ("ad", 8),
+    ];
+
+    for (signature_str, size) in cases.iter() {
+        // Bytes differ within each element, so a missing or extra swap shows
+        let elements: Vec<Vec<u8>> = (0..3u8)
+            .map(|i| (0..*size as u8).map(|b| 0x11 * (b + 1) + i).collect())
+            .collect();
+
+        let mut decoded = vec![];
+        for endianness in [
+            MessageEndianness::LittleEndian,
+            MessageEndianness::BigEndian,
+        ]
+        .iter()
+        {
+            let buf = encode(*endianness, *size, &elements);
+            let owned = parse(signature_str, *endianness, &buf);
+
+            let ctx = ParseContext::new(*endianness, &buf);
+            let (rest, borrowed) = signature(signature_str)
+                .parse_buffer_borrowed(&buf, &ctx)
+                .unwrap();
+            assert!(rest.is_empty());
+            let borrowed: Vec<_> = borrowed.into_iter().map(|v| v.into_owned()).collect();
+            assert_eq!(borrowed, owned, "{}", signature_str);
+
+            decoded.push(owned);
+        }
+        assert_eq!(decoded[0], decoded[1], "{}", signature_str);
+    }
+}
+
+#[test]
+fn message_round_trip() {
+    let body = vec![
+        DbusTypeContainer::ByteArray(vec![1, 2, 3]),
+        DbusTypeContainer::Uint16Array(vec![1, 2]),
+        DbusTypeContainer::Int32Array(vec![-1]),
+        DbusTypeContainer::Uint64Array(vec![u64::MAX]),
+        DbusTypeContainer::DoubleArray(vec![0.5, -2.0]),
+    ];
+    let (_, mut message) =
+        Message::parse(&signal(MessageEndianness::LittleEndian, "", &[])).unwrap();
+    message.message = body.clone();
+    assert_eq!(message.body_signature(), signature("ayaqaiatad"));
+
+    for endianness in [
+        MessageEndianness::LittleEndian,
+        MessageEndianness::BigEndian,
+    ]
+    .iter()
+    {
+        let buf = message.to_endianness(*endianness);
+        let (_, parsed) = Message::parse(&buf).unwrap();
+        assert_eq!(parsed.message, body);
+
+        let (_, borrowed) = BorrowedMessage::parse(&buf).unwrap();
+        assert_eq!(borrowed.into_owned().message, body);
+    }
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::{message, string, Encoder};
+use conducto_nom::components::{HeaderField, MessageEndianness, MessageType};
+use conducto_nom::*;
+
+const ENDIANNESSES: [MessageEndianness; 2] = [
+    MessageEndianness::LittleEndian,
+    MessageEndianness::BigEndian,
+];
+
+fn body() -> Vec<DbusTypeContainer> {
+    vec![string("body"), DbusTypeContainer::Uint32Array(vec![1, 2])]
+}
+
+fn call(endianness: MessageEndianness) -> Vec<u8> {
+    let mut body = Encoder::new(endianness);
+    body.str("body").array(4, |e| {
+        e.u32(1).u32(2);
+    });
+    message(
+        endianness,
+        MessageType::MethodCall,
+        3,
+        None,
+        &[
+            (HeaderField::Path, "/com/example"),
+            (HeaderField::Interface, "com.example.Patch"),
+            (HeaderField::Member, "Echo"),
+            (HeaderField::Destination, "com.example"),
+        ],
+        "sau",
+        &body.buf,
+    )
+}
+
+fn reply(endianness: MessageEndianness) -> Vec<u8> {
+    let mut body = Encoder::new(endianness);
+    body.str("reply");
+    message(
+        endianness,
+        MessageType::MethodReturn,
+        4,
+        Some(3),
+        &[(HeaderField::Destination, ":1.3")],
+        "s",
+        &body.buf,
+    )
+}
+
+fn peek(buf: &[u8]) -> HeaderPeek<'_> {
+    let (_, peek) = HeaderPeek::from_bytes(buf).unwrap();
+    peek
+}
+
+fn parse(buf: &[u8]) -> Message {
+    let (rest, message) = Message::parse(buf).unwrap();
+    assert!(rest.is_empty());
+    message
+}
+
+#[test]
+fn rewrites_the_serial_in_place() {
+    let mut buf = call(MessageEndianness::LittleEndian);
+    let len = buf.len();
+    HeaderPatch::new().with_serial(9).apply(&mut buf).unwrap();
+    assert_eq!(buf.len(), len);
+
+    assert_eq!(peek(&buf).serial(), 9);
+    assert_eq!(peek(&buf).member, Some("Echo"));
+    assert_eq!(parse(&buf).message, body());
+}
+
+#[test]
+fn rewrites_an_existing_reply_serial_in_place() {
+    for endianness in ENDIANNESSES.iter() {
+        let mut buf = reply(*endianness);
+        let len = buf.len();
+        HeaderPatch::new()
+            .with_reply_serial(70_000)
+            .apply(&mut buf)
+            .unwrap();
+        assert_eq!(buf.len(), len);
+        assert_eq!(peek(&buf).reply_serial, Some(70_000));
+        assert_eq!(parse(&buf).message, vec![string("reply")]);
+    }
+}
+
+#[test]
+fn adds_and_replaces_routing_fields() {
+    for endianness in ENDIANNESSES.iter() {
+        let mut buf = call(*endianness);
+        HeaderPatch::new()
+            .with_sender(":1.42")
+            .with_reply_serial(7)
+            .apply(&mut buf)
+            .unwrap();
+        assert_eq!(peek(&buf).sender, Some(":1.42"));
+        assert_eq!(peek(&buf).reply_serial, Some(7));
+        assert_eq!(peek(&buf).destination, Some("com.example"));
+        assert_eq!(parse(&buf).message, body());
+
+        HeaderPatch::new()
+            .with_sender(":1.7")
+            .with_destination("org.example.Other")
+            .with_serial(11)
+            .apply(&mut buf)
+            .unwrap();
+        let patched = peek(&buf);
+        assert_eq!(patched.sender, Some(":1.7"));
+        assert_eq!(patched.destination, Some("org.example.Other"));
+        assert_eq!(patched.serial(), 11);
+        assert_eq!(patched.path, Some("/com/example"));
+        assert_eq!(patched.interface, Some("com.example.Patch"));
+        assert_eq!(parse(&buf).message, body());
+    }
+}
+
+#[test]
+fn keeps_what_follows_the_message() {
+    let mut buf = call(MessageEndianness::LittleEndian);
+    let len = buf.len();
+    buf.extend_from_slice(b"next");
+    HeaderPatch::new()
+        .with_sender(":1.1")
+        .apply(&mut buf)
+        .unwrap();
+    assert!(buf.ends_with(b"next"));
+
+    let (rest, patched) = Message::parse(&buf).unwrap();
+    assert_eq!(rest, b"next");
+    assert_eq!(patched.message, body());
+    assert_eq!(peek(&buf).sender, Some(":1.1"));
+    assert!(buf.len() > len + 4);
+}
+
+#[test]
+fn errors() {
+    let mut buf = call(MessageEndianness::LittleEndian);
+    assert!(matches!(
+        HeaderPatch::new().with_sender("a\0b").apply(&mut buf),
+        Err(DbusParseError::InvalidHeaderField)
+    ));
+
+    let mut truncated = buf[..buf.len() - 1].to_vec();
+    assert!(matches!(
+        HeaderPatch::new().with_serial(1).apply(&mut truncated),
+        Err(DbusParseError::MalformedHeader)
+    ));
+    assert!(matches!(
+        HeaderPatch::new().with_serial(1).apply(&mut vec![b'l', 1]),
+        Err(DbusParseError::MalformedHeader)
+    ));
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use common::{signal, string, Encoder};
+use conducto_nom::components::{MessageEndianness, MessageType};
+use conducto_nom::*;
+
+/// A little-endian signal whose header carries a field with the unknown `code`
+fn with_unknown_field_code(code: u8) -> Vec<u8> {
+    let mut buf = vec![b'l', 4, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0];
+    let pad = |buf: &mut Vec<u8>, alignment: usize| {
+        while !buf.len().is_multiple_of(alignment) {
+            buf.push(0);
+        }
+    };
+    let string = |buf: &mut Vec<u8>, value: &str| {
+        pad(buf, 4);
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(0);
+    };
+
+    for (code, signature, value) in [(1, "o", "/a"), (2, "s", "a.b"), (3, "s", "M")].iter() {
+        pad(&mut buf, 8);
+        buf.extend_from_slice(&[*code, 1, signature.as_bytes()[0], 0]);
+        string(&mut buf, value);
+    }
+
+    // The unknown field holding {"k": <7u32>}
+    pad(&mut buf, 8);
+    buf.extend_from_slice(&[code, 5]);
+    buf.extend_from_slice(b"a{sv}\0");
+    pad(&mut buf, 4);
+    let len_offset = buf.len();
+    buf.extend_from_slice(&[0; 4]);
+    pad(&mut buf, 8);
+    let start = buf.len();
+    string(&mut buf, "k");
+    buf.extend_from_slice(&[1, b'u', 0]);
+    pad(&mut buf, 4);
+    buf.extend_from_slice(&7u32.to_le_bytes());
+    let len = (buf.len() - start) as u32;
+    buf[len_offset..len_offset + 4].copy_from_slice(&len.to_le_bytes());
+
+    let fields_len = (buf.len() - 16) as u32;
+    buf[12..16].copy_from_slice(&fields_len.to_le_bytes());
+    pad(&mut buf, 8);
+    buf
+}
+
+fn with_unknown_field() -> Vec<u8> {
+    with_unknown_field_code(42)
+}
+
+#[test]
+fn skips_unknown_fields() {
+    let buf = with_unknown_field();
+    let (rest, peek) = HeaderPeek::from_bytes(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(peek.message_type(), MessageType::Signal);
+    assert_eq!(peek.serial(), 1);
+    assert_eq!(peek.path, Some("/a"));
+    assert_eq!(peek.interface, Some("a.b"));
+    assert_eq!(peek.member, Some("M"));
+    assert_eq!(peek.header_len, buf.len());
+    assert_eq!(peek.message_len(), buf.len());
+}
+
+#[test]
+fn decoding_skips_unknown_fields() {
+    for code in [42, 200].iter() {
+        let buf = with_unknown_field_code(*code);
+        let (_, peek) = HeaderPeek::from_bytes(&buf).unwrap();
+        assert_eq!(peek.path, Some("/a"));
+        let (rest, message) = Message::parse(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert!(message.is_empty());
+    }
+
+    // The same frame with its interface field code patched to 200
+    let mut buf = signal(MessageEndianness::LittleEndian, "", &[]);
+    let at = buf
+        .windows(5)
+        .position(|w| w == b"\x02\x01s\0\x13")
+        .unwrap();
+    buf[at] = 200;
+    let (_, peek) = HeaderPeek::from_bytes(&buf).unwrap();
+    assert_eq!(peek.path, Some("/com/example"));
+    assert_eq!(peek.interface, None);
+    assert_eq!(peek.member, Some("Changed"));
+    let (rest, message) = Message::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert!(message.is_empty());
+}
+
+#[test]
+fn matches_the_decoded_header() {
+    for endianness in [
+        MessageEndianness::LittleEndian,
+        MessageEndianness::BigEndian,
+    ]
+    .iter()
+    {
+        let mut body = Encoder::new(*endianness);
+        body.str("value");
+        let buf = signal(*endianness, "s", &body.buf);
+        let (rest, peek) = HeaderPeek::from_bytes(&buf).unwrap();
+        assert_eq!(peek.fixed.endianness, *endianness);
+        assert_eq!(peek.message_type(), MessageType::Signal);
+        assert_eq!(peek.serial(), 1);
+        assert_eq!(peek.path, Some("/com/example"));
+        assert_eq!(peek.interface, Some("com.example.Signals"));
+        assert_eq!(peek.member, Some("Changed"));
+        assert_eq!(peek.signature, Some("s"));
+        assert_eq!(peek.reply_serial, None);
+        assert_eq!(peek.sender, None);
+        assert_eq!(peek.body_len(), rest.len());
+        assert_eq!(peek.message_len(), buf.len());
+
+        let (header_rest, header) = Header::parse(&buf).unwrap();
+        assert_eq!(header_rest, rest);
+        let (_, message) = header.parse_message(header_rest).unwrap();
+        assert_eq!(message.message, vec![string("value")]);
+    }
+}
+
+#[test]
+fn frame_len_needs_the_fixed_header() {
+    let mut message = Message::signal("/com/example", "com.example.Frame", "Changed")
+        .unwrap()
+        .with_body(vec![DbusTypeContainer::Uint32Array(vec![1, 2, 3])]);
+    message.header.fixed.msg_serial = 1;
+
+    for endianness in [
+        MessageEndianness::LittleEndian,
+        MessageEndianness::BigEndian,
+    ]
+    .iter()
+    {
+        let buf = message.to_endianness(*endianness);
+        assert_eq!(HeaderPeek::frame_len(&buf[..15]), None);
+        assert_eq!(HeaderPeek::frame_len(&buf[..16]), Some(buf.len()));
+        assert_eq!(HeaderPeek::frame_len(&buf), Some(buf.len()));
+    }
+
+    assert_eq!(
+        HeaderPeek::frame_len(&with_unknown_field()),
+        Some(with_unknown_field().len())
+    );
+
+    // Only `l` and `B` announce a byte order
+    for endianness in [0, b'b', b'L', b'x'].iter() {
+        let mut buf = with_unknown_field();
+        buf[0] = *endianness;
+        assert_eq!(HeaderPeek::frame_len(&buf), None);
+    }
+}
+
+#[test]
+fn rejects_malformed_headers() {
+    let buf = with_unknown_field();
+    assert!(matches!(
+        HeaderPeek::from_bytes(&buf[..buf.len() - 8]),
+        Err(nom::Err::Incomplete(_))
+    ));
+
+    let mut version = buf.clone();
+    version[3] = 2;
+    assert!(HeaderPeek::from_bytes(&version).is_err());
+
+    // A variant must hold exactly one single complete type
+    let mut signature = buf;
+    let at = signature.windows(5).position(|w| w == b"a{sv}").unwrap();
+    signature[at + 4] = b'u';
+    assert!(HeaderPeek::from_bytes(&signature).is_err());
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use common::{path, signature};
+use conducto_nom::components::MessageType;
+use conducto_nom::*;
+
+fn server() -> ObjectServer {
+    let player = Interface::new("com.example.Player")
+        .with_method("Play", signature(""), signature(""), |_| Ok(vec![]))
+        .with_method("Seek", signature("xs"), signature("a{sv}"), |_| Ok(vec![]))
+        .with_signal("Seeked", signature("x"))
+        .with_signal("Stopped", signature(""))
+        .with_property(
+            "Volume",
+            Property::read_write(
+                signature("d"),
+                || Ok(DbusTypeContainer::Double(0.5.into())),
+                |_| Ok(()),
+            ),
+        )
+        .with_property(
+            "Identity",
+            Property::read_only(signature("s"), || {
+                Ok(DbusTypeContainer::String("Player".into()))
+            })
+            .with_emits_changed(EmitsChanged::Const),
+        );
+
+    let mut server = ObjectServer::new();
+    server.add_object_manager(&path("/com/example"));
+    server.add_interface(&path("/com/example/player/main"), player);
+    server.add_interface(
+        &path("/com/example/tracks/1"),
+        Interface::new("com.example.Track"),
+    );
+    server
+}
+
+const PLAYER_XML: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+    <method name="GetMachineId">
+      <arg type="s" name="machine_uuid" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg type="s" name="xml_data" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Properties">
+    <method name="Get">
+      <arg type="s" name="interface_name" direction="in"/>
+      <arg type="s" name="property_name" direction="in"/>
+      <arg type="v" name="value" direction="out"/>
+    </method>
+    <method name="GetAll">
+      <arg type="s" name="interface_name" direction="in"/>
+      <arg type="a{sv}" name="props" direction="out"/>
+    </method>
+    <method name="Set">
+      <arg type="s" name="interface_name" direction="in"/>
+      <arg type="s" name="property_name" direction="in"/>
+      <arg type="v" name="value" direction="in"/>
+    </method>
+    <signal name="PropertiesChanged">
+      <arg type="s" name="interface_name"/>
+      <arg type="a{sv}" name="changed_properties"/>
+      <arg type="as" name="invalidated_properties"/>
+    </signal>
+  </interface>
+  <interface name="com.example.Player">
+    <method name="Play"/>
+    <method name="Seek">
+      <arg type="x" direction="in"/>
+      <arg type="s" direction="in"/>
+      <arg type="a{sv}" direction="out"/>
+    </method>
+    <signal name="Seeked">
+      <arg type="x"/>
+    </signal>
+    <signal name="Stopped"/>
+    <property name="Identity" type="s" access="read">
+      <annotation name="org.freedesktop.DBus.Property.EmitsChangedSignal" value="const"/>
+    </property>
+    <property name="Volume" type="d" access="readwrite"/>
+  </interface>
+</node>
+"#;
+
+fn introspect(server: &mut ObjectServer, path: &str) -> Message {
+    let mut call = Message::method_call(
+        Some("com.example"),
+        path,
+        Some(INTROSPECTABLE_INTERFACE),
+        "Introspect",
+    )
+    .unwrap();
+    call.header.fixed.msg_serial = 2;
+    server.dispatch(&call).remove(0)
+}
+
+#[test]
+fn describes_registered_interfaces() {
+    let mut server = server();
+    let reply = introspect(&mut server, "/com/example/player/main");
+    assert_eq!(reply.header.message_type(), MessageType::MethodReturn);
+    assert_eq!(
+        reply.message,
+        vec![DbusTypeContainer::String(PLAYER_XML.into())]
+    );
+}
+
+#[test]
+fn lists_child_nodes() {
+    let server = server();
+    let root = server.introspect("/").unwrap();
+    assert!(root.ends_with("  <node name=\"com\"/>\n</node>\n"));
+    assert!(!root.contains("org.freedesktop.DBus.Properties"));
+
+    let manager = server.introspect("/com/example").unwrap();
+    assert!(manager.contains("<interface name=\"org.freedesktop.DBus.ObjectManager\">"));
+    assert!(manager.contains("  <node name=\"player\"/>\n  <node name=\"tracks\"/>\n</node>"));
+
+    let track = server.introspect("/com/example/tracks/1").unwrap();
+    assert!(track.contains("  <interface name=\"com.example.Track\">\n  </interface>\n</node>"));
+    assert!(!track.contains("<node name="));
+}
+
+#[test]
+fn unknown_paths() {
+    let mut server = server();
+    assert!(server.introspect("/com/other").is_err());
+    assert!(server.introspect("/com/example/play").is_err());
+    let reply = introspect(&mut server, "/com/other");
+    assert_eq!(reply.header.error_name(), Some(error_name::UNKNOWN_OBJECT));
+
+    // An empty server still answers for the root
+    assert!(ObjectServer::new().introspect("/").is_ok());
+}
+
+#[test]
+fn invalid_signatures_fail_the_introspection() {
+    let mut server = ObjectServer::new();
+    server.add_interface(
+        &path("/com/example"),
+        Interface::new("com.example.Broken").with_method(
+            "Take",
+            Signature::new(vec![SignatureType::Array]),
+            Signature::default(),
+            |_| Ok(vec![]),
+        ),
+    );
+
+    assert!(server.introspect("/com/example").is_err());
+    let reply = introspect(&mut server, "/com/example");
+    assert_eq!(reply.header.error_name(), Some(error_name::FAILED));
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use common::{path, signature};
+use conducto_nom::introspection::*;
+use conducto_nom::{ObjectServer, PropertyAccess};
+
+const SAMPLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<!-- A sample & a comment with <tags> -->
+<node name="/com/example/sample_object0" xmlns:doc="http://www.freedesktop.org/dbus/1.0/doc.dtd">
+  <interface name='com.example.SampleInterface0'>
+    <doc:doc><doc:summary>Not part of the <b>model</b></doc:summary></doc:doc>
+    <method name="Frobate">
+      <arg name="foo" type="i" direction="in"/>
+      <arg name="bar" type="s" direction="out"/>
+      <arg name="baz" type="a{us}" direction="out">
+        <annotation name="org.qtproject.QtDBus.QtTypeName" value="QMap&lt;uint, QString&gt;"/>
+      </arg>
+      <annotation name="org.freedesktop.DBus.Deprecated" value="true"/>
+    </method>
+    <method name="Bazify">
+      <arg name="bar" type="(iiu)"/>
+      <arg name="result" type="v" direction="out"/>
+    </method>
+    <signal name="Changed">
+      <arg name="new_value" type="b"/>
+    </signal>
+    <property name="Bar" type="y" access="readwrite"/>
+    <property name="Secret" type="s" access="write">
+      <annotation name="org.freedesktop.DBus.Property.EmitsChangedSignal" value="false"/>
+    </property>
+  </interface>
+  <node name="child_of_sample_object"/>
+  <node name="another_child_of_sample_object"></node>
+</node>
+"#;
+
+#[test]
+fn parses_the_model() {
+    let node: Node = SAMPLE.parse().unwrap();
+    assert_eq!(node.name.as_deref(), Some("/com/example/sample_object0"));
+    assert_eq!(node.interfaces.len(), 1);
+    let names: Vec<_> = node
+        .nodes
+        .iter()
+        .map(|child| child.name.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vec!["child_of_sample_object", "another_child_of_sample_object"]
+    );
+
+    let interface = node.interface("com.example.SampleInterface0").unwrap();
+    let frobate = interface.method("Frobate").unwrap();
+    assert_eq!(frobate.in_signature(), signature("i"));
+    assert_eq!(frobate.out_signature(), signature("sa{us}"));
+    assert_eq!(
+        frobate.annotations,
+        vec![Annotation::new("org.freedesktop.DBus.Deprecated", "true")]
+    );
+    assert_eq!(frobate.args[2].annotations[0].value, "QMap<uint, QString>");
+
+    // Method arguments are in unless stated otherwise
+    let bazify = interface.method("Bazify").unwrap();
+    assert_eq!(bazify.args[0].direction, Direction::In);
+    assert_eq!(bazify.in_signature(), signature("(iiu)"));
+
+    let changed = interface.signal("Changed").unwrap();
+    assert_eq!(changed.args[0].direction, Direction::Out);
+    assert_eq!(changed.signature(), signature("b"));
+
+    assert_eq!(
+        interface.property("Bar").unwrap(),
+        &Property::new("Bar", signature("y"), PropertyAccess::ReadWrite)
+    );
+    assert_eq!(
+        interface.property("Secret").unwrap().access,
+        PropertyAccess::Write
+    );
+}
+
+#[test]
+fn round_trips() {
+    let node: Node = SAMPLE.parse().unwrap();
+    let xml = node.to_xml();
+    assert!(xml.starts_with(DOCTYPE));
+    assert!(xml.contains("value=\"QMap&lt;uint, QString&gt;\""));
+    assert!(xml.contains("<arg type=\"(iiu)\" name=\"bar\" direction=\"in\"/>"));
+    assert!(xml.contains("<arg type=\"b\" name=\"new_value\"/>"));
+    assert!(!xml.contains("doc:"));
+    assert_eq!(xml.parse::<Node>().unwrap(), node);
+
+    assert_eq!(Node::new().to_string(), "<node/>\n");
+}
+
+#[test]
+fn describes_object_server_objects() {
+    let mut server = ObjectServer::new();
+    let path = path("/com/example/Echo");
+    server.add_interface(
+        &path,
+        conducto_nom::Interface::new("com.example.Echo")
+            .with_method("Echo", signature("sv"), signature("as"), |_| Ok(vec![]))
+            .with_signal("Echoed", signature("s")),
+    );
+
+    let root: Node = server.introspect("/").unwrap().parse().unwrap();
+    assert_eq!(root.nodes[0].name.as_deref(), Some("com"));
+
+    let node: Node = server
+        .introspect("/com/example/Echo")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let names: Vec<_> = node
+        .interfaces
+        .iter()
+        .map(|interface| interface.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "org.freedesktop.DBus.Peer",
+            "org.freedesktop.DBus.Introspectable",
+            "org.freedesktop.DBus.Properties",
+            "com.example.Echo",
+        ]
+    );
+
+    let echo = node.interface("com.example.Echo").unwrap();
+    let method = echo.method("Echo").unwrap();
+    assert_eq!(method.in_signature(), signature("sv"));
+    assert_eq!(method.out_signature(), signature("as"));
+    assert_eq!(echo.signal("Echoed").unwrap().signature(), signature("s"));
+}
+
+/// A document with a single interface made of `members`
+fn interface(members: &str) -> String {
+    format!(
+        "<node><interface name=\"a.b\">{}</interface></node>",
+        members
+    )
+}
+
+#[test]
+fn errors() {
+    let cases = [
+        (
+            "<node><interface name=\"a.b\"></node>".to_string(),
+            IntrospectionError::MismatchedTag("node".into(), "interface".into()),
+        ),
+        ("<node>".to_string(), IntrospectionError::MalformedXml(6)),
+        (
+            "<node name=\"x></node>".to_string(),
+            IntrospectionError::MalformedXml(11),
+        ),
+        (
+            "<node/><node/>".to_string(),
+            IntrospectionError::MalformedXml(14),
+        ),
+        (
+            "<interface name=\"a.b\"/>".to_string(),
+            IntrospectionError::UnexpectedRoot("interface".into()),
+        ),
+        (
+            "<node><interface/></node>".to_string(),
+            IntrospectionError::MissingAttribute("interface".into(), "name".into()),
+        ),
+        (
+            interface("<signal name=\"S\"><arg type=\"s\" direction=\"in\"/></signal>"),
+            IntrospectionError::InvalidValue("direction".into(), "in".into()),
+        ),
+        (
+            interface("<property name=\"P\" type=\"s\" access=\"none\"/>"),
+            IntrospectionError::InvalidValue("access".into(), "none".into()),
+        ),
+        (
+            interface("<method name=\"M\"><arg type=\"ii\"/></method>"),
+            IntrospectionError::InvalidSignature("ii".into()),
+        ),
+        (
+            interface("<property name=\"P\" type=\"a\" access=\"read\"/>"),
+            IntrospectionError::InvalidSignature("a".into()),
+        ),
+    ];
+
+    for (xml, expected) in cases.iter() {
+        assert_eq!(&xml.parse::<Node>().unwrap_err(), expected, "{}", xml);
+    }
+}
+
+#[test]
+fn limits_nesting() {
+    let nested = |depth: usize| "<node>".repeat(depth) + &"</node>".repeat(depth);
+    assert!(nested(64).parse::<Node>().is_ok());
+    assert_eq!(
+        nested(65).parse::<Node>().unwrap_err(),
+        IntrospectionError::TooDeep(65 * 6)
+    );
+    assert_eq!(
+        nested(200_000).parse::<Node>().unwrap_err(),
+        IntrospectionError::TooDeep(65 * 6)
+    );
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{signal, signature, string, Encoder};
+use conducto_nom::components::MessageEndianness;
+use conducto_nom::*;
+
+fn encoded() -> (Vec<DbusTypeContainer>, Vec<u8>) {
+    let body = vec![
+        DbusTypeContainer::Uint32(7.into()),
+        string("text"),
+        DbusTypeContainer::DoubleArray(vec![0.5]),
+    ];
+    let mut encoder = Encoder::new(MessageEndianness::LittleEndian);
+    encoder.u32(7).str("text").array(8, |e| {
+        e.u64(0.5f64.to_bits());
+    });
+    let buf = signal(MessageEndianness::LittleEndian, "usad", &encoder.buf);
+    (body, buf)
+}
+
+#[test]
+fn decodes_arguments_on_demand() {
+    let (body, buf) = encoded();
+    let (rest, lazy) = LazyMessage::parse(&buf).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(lazy.as_bytes(), &buf[..]);
+    assert_eq!(lazy.len(), 3);
+    assert_eq!(lazy.arg_signature(2), Some(&signature("ad")));
+    assert_eq!(lazy.arg_signature(3), None);
+
+    // Later arguments can be asked for first
+    assert_eq!(lazy.arg(2).unwrap(), &body[2]);
+    assert_eq!(lazy.arg(0).unwrap(), &body[0]);
+    assert!(std::ptr::eq(lazy.arg(1).unwrap(), lazy.arg(1).unwrap()));
+    assert!(matches!(
+        lazy.arg(3),
+        Err(DbusParseError::ArgumentOutOfRange)
+    ));
+
+    let (_, parsed) = Message::parse(&buf).unwrap();
+    assert_eq!(parsed.message, body);
+    assert_eq!(lazy.into_message().unwrap(), parsed);
+}
+
+#[test]
+fn keeps_trailing_input() {
+    let (_, mut buf) = encoded();
+    let len = buf.len();
+    buf.extend_from_slice(&[1, 2, 3]);
+    let (rest, lazy) = LazyMessage::parse(&buf).unwrap();
+    assert_eq!(rest, &[1, 2, 3]);
+    assert_eq!(lazy.as_bytes().len(), len);
+}
+
+#[test]
+fn invalid_arguments() {
+    let (_, mut buf) = encoded();
+    let (_, offset) = Message::arg_offset(&buf, 1).unwrap();
+    // Not UTF-8
+    buf[offset + 4] = 0xff;
+
+    let (_, lazy) = LazyMessage::parse(&buf).unwrap();
+    assert!(lazy.arg(0).is_ok());
+    assert!(lazy.arg(2).is_ok());
+    assert!(matches!(lazy.arg(1), Err(DbusParseError::InvalidBody)));
+    assert!(lazy.into_message().is_err());
+}
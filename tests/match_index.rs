@@ -0,0 +1,135 @@
+mod common;
+
+use common::string;
+use conducto_nom::*;
+
+fn rule(s: &str) -> MatchRule {
+    s.parse().unwrap()
+}
+
+fn signal(path: &str, interface: &str, member: &str, sender: &str) -> Message {
+    let mut message = Message::signal(path, interface, member).unwrap();
+    message.header.fields.sender = Some(sender.into());
+    message
+}
+
+/// The values of the rules matching `message`, sorted
+fn matched(index: &MatchIndex<&'static str>, message: &Message) -> Vec<&'static str> {
+    let mut values: Vec<_> = index.matches(message).map(|(_, value)| *value).collect();
+    values.sort_unstable();
+    values
+}
+
+const RULES: [(&str, &str); 9] = [
+    ("member", "member=Changed"),
+    ("member and path", "member=Changed,path=/a"),
+    ("path", "path=/b"),
+    ("interface", "interface=com.example.B"),
+    ("sender", "sender=:1.2"),
+    ("type", "type=signal"),
+    ("method calls", "type=method_call"),
+    ("any", ""),
+    ("argument", "arg0=x"),
+];
+
+fn index() -> MatchIndex<&'static str> {
+    let mut index = MatchIndex::new();
+    for (value, rule_text) in RULES.iter() {
+        index.insert(rule(rule_text), *value);
+    }
+    index
+}
+
+#[test]
+fn finds_rules_by_any_field() {
+    let index = index();
+    assert_eq!(index.len(), RULES.len());
+
+    assert_eq!(
+        matched(&index, &signal("/a", "com.example.A", "Changed", ":1.1")),
+        vec!["any", "member", "member and path", "type"]
+    );
+    assert_eq!(
+        matched(&index, &signal("/b", "com.example.B", "Other", ":1.2")),
+        vec!["any", "interface", "path", "sender", "type"]
+    );
+    let with_arg = signal("/c", "com.example.C", "Changed", ":1.3").with_body(vec![string("x")]);
+    assert_eq!(
+        matched(&index, &with_arg),
+        vec!["any", "argument", "member", "type"]
+    );
+
+    let call = Message::method_call(None, "/c", None, "Run").unwrap();
+    assert_eq!(matched(&index, &call), vec!["any", "method calls"]);
+}
+
+#[test]
+fn agrees_with_evaluating_every_rule() {
+    let index = index();
+    let messages = [
+        signal("/a", "com.example.A", "Changed", ":1.1"),
+        signal("/a", "com.example.B", "Other", ":1.2"),
+        signal("/b", "com.example.A", "Changed", ":1.2").with_body(vec![string("x")]),
+        Message::method_call(Some(":1.2"), "/b", Some("com.example.B"), "Changed").unwrap(),
+    ];
+
+    for message in messages.iter() {
+        let mut expected: Vec<_> = index
+            .iter()
+            .filter(|(_, rule, _)| rule.matches(message))
+            .map(|(_, _, value)| *value)
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(matched(&index, message), expected);
+    }
+}
+
+#[test]
+fn removes_rules_without_reviving_stale_ids() {
+    let mut index = MatchIndex::new();
+    let member = index.insert(rule("member=Changed"), "member");
+    let any = index.insert(rule(""), "any");
+    let message = signal("/a", "com.example.A", "Changed", ":1.1");
+    assert_eq!(matched(&index, &message), vec!["any", "member"]);
+
+    assert_eq!(
+        index.get(member),
+        Some((&rule("member=Changed"), &"member"))
+    );
+    assert_eq!(
+        index.remove(member),
+        Some((rule("member=Changed"), "member"))
+    );
+    assert_eq!(index.remove(member), None);
+    assert_eq!(index.get(member), None);
+    assert_eq!(index.len(), 1);
+    assert_eq!(matched(&index, &message), vec!["any"]);
+
+    // A rule taking the freed slot is out of reach of the old id
+    let path = index.insert(rule("path=/a"), "path");
+    assert_ne!(path, member);
+    assert_eq!(index.get(member), None);
+    assert_eq!(index.remove(member), None);
+    assert_eq!(index.get(path), Some((&rule("path=/a"), &"path")));
+    assert_eq!(matched(&index, &message), vec!["any", "path"]);
+    let ids: Vec<_> = index.iter().map(|(id, _, _)| id).collect();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&any) && ids.contains(&path));
+
+    index.remove(any);
+    index.remove(path);
+    assert!(index.is_empty());
+    assert_eq!(index.matches(&message).count(), 0);
+}
+
+#[test]
+fn reports_each_rule_once() {
+    let mut index = MatchIndex::new();
+    let id = index.insert(
+        rule("type=signal,sender=:1.1,interface=com.example.A,path=/a,member=Changed"),
+        "all",
+    );
+    let message = signal("/a", "com.example.A", "Changed", ":1.1");
+    let matches: Vec<_> = index.matches(&message).collect();
+    assert_eq!(matches, vec![(id, &"all")]);
+}
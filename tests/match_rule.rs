@@ -0,0 +1,246 @@
+mod common;
+
+use common::{path, string};
+use conducto_nom::components::MessageType;
+use conducto_nom::*;
+
+fn rule(s: &str) -> MatchRule {
+    s.parse().unwrap()
+}
+
+fn signal(path: &str, member: &str, body: Vec<DbusTypeContainer>) -> Message {
+    let mut message = Message::signal(path, "com.example.Match", member)
+        .unwrap()
+        .with_body(body);
+    message.header.fields.sender = Some(":1.4".into());
+    message
+}
+
+#[test]
+fn parses_keys() {
+    let parsed = rule(
+        "type='signal', sender=':1.4',interface='com.example.Match',member=Changed,\
+         path_namespace='/com/example',arg0='a',arg2path='/x/',arg0namespace=com.example,\
+         eavesdrop='true'",
+    );
+    assert_eq!(parsed.message_type, Some(MessageType::Signal));
+    assert_eq!(parsed.sender.as_deref(), Some(":1.4"));
+    assert_eq!(parsed.interface.as_deref(), Some("com.example.Match"));
+    assert_eq!(parsed.member.as_deref(), Some("Changed"));
+    assert_eq!(parsed.path_namespace.as_deref(), Some("/com/example"));
+    assert_eq!(parsed.args.get(&0).map(String::as_str), Some("a"));
+    assert_eq!(parsed.arg_paths.get(&2).map(String::as_str), Some("/x/"));
+    assert_eq!(parsed.arg0_namespace.as_deref(), Some("com.example"));
+    assert_eq!(parsed.eavesdrop, Some(true));
+
+    assert_eq!(rule(""), MatchRule::new());
+    assert_eq!(rule("arg63='x'").args.len(), 1);
+}
+
+#[test]
+fn quoting_and_escaping() {
+    // Commas and backslashes are literal inside quotes
+    assert_eq!(rule("member='a,b'").member.as_deref(), Some("a,b"));
+    assert_eq!(rule("arg0='a\\b'").args[&0], "a\\b");
+    // An apostrophe is escaped outside quotes
+    assert_eq!(rule("arg0='it'\\''s'").args[&0], "it's");
+    assert_eq!(rule("arg0=\\'").args[&0], "'");
+    assert_eq!(rule("arg0=a\\b").args[&0], "a\\b");
+    assert_eq!(rule("arg0=''").args[&0], "");
+
+    assert_eq!(
+        "member='open".parse::<MatchRule>(),
+        Err(MatchRuleError::UnterminatedQuote)
+    );
+}
+
+#[test]
+fn display_round_trips() {
+    let mut built = MatchRule::new();
+    built.message_type = Some(MessageType::MethodCall);
+    built.member = Some("it's, quoted\\".to_string());
+    built.path = Some("/com/example".to_string());
+    built.args.insert(3, "'".to_string());
+    built.arg_paths.insert(0, "/a/".to_string());
+    built.arg0_namespace = Some("com.example".to_string());
+    built.eavesdrop = Some(false);
+
+    let text = built.to_string();
+    assert_eq!(
+        text,
+        "type='method_call',member='it'\\''s, quoted\\',path='/com/example',\
+         arg3=''\\''',arg0path='/a/',arg0namespace='com.example',eavesdrop='false'"
+    );
+    assert_eq!(rule(&text), built);
+
+    let parsed = rule("sender=org.example,type=signal");
+    assert_eq!(parsed.to_string(), "type='signal',sender='org.example'");
+    assert_eq!(rule(&parsed.to_string()), parsed);
+
+    // The invalid type has no name, so it is left out like an unset type
+    let mut invalid = MatchRule::new();
+    invalid.message_type = Some(MessageType::Invalid);
+    invalid.member = Some("Changed".to_string());
+    assert_eq!(invalid.to_string(), "member='Changed'");
+    invalid.message_type = None;
+    assert_eq!(rule(&invalid.to_string()), invalid);
+}
+
+#[test]
+fn rejects_invalid_rules() {
+    let errors = [
+        ("member", MatchRuleError::MalformedPair),
+        ("=x", MatchRuleError::MalformedPair),
+        ("a,b=c", MatchRuleError::MalformedPair),
+        (
+            "colour='red'",
+            MatchRuleError::UnknownKey("colour".to_string()),
+        ),
+        ("arg64='x'", MatchRuleError::UnknownKey("arg64".to_string())),
+        ("arg01='x'", MatchRuleError::UnknownKey("arg01".to_string())),
+        ("arg+1='x'", MatchRuleError::UnknownKey("arg+1".to_string())),
+        (
+            "member=a,member=b",
+            MatchRuleError::DuplicateKey("member".to_string()),
+        ),
+        (
+            "arg1path=/a,arg1path=/b",
+            MatchRuleError::DuplicateKey("arg1path".to_string()),
+        ),
+        (
+            "type=signal,type=error",
+            MatchRuleError::DuplicateKey("type".to_string()),
+        ),
+        (
+            "type=bogus",
+            MatchRuleError::InvalidValue("type".to_string()),
+        ),
+        (
+            "eavesdrop=yes",
+            MatchRuleError::InvalidValue("eavesdrop".to_string()),
+        ),
+        (
+            "path=relative",
+            MatchRuleError::InvalidValue("path".to_string()),
+        ),
+        (
+            "path_namespace=/a/",
+            MatchRuleError::InvalidValue("path_namespace".to_string()),
+        ),
+        (
+            "path=/a,path_namespace=/a",
+            MatchRuleError::PathAndNamespace,
+        ),
+    ];
+
+    for (rule, error) in errors.iter() {
+        assert_eq!(rule.parse::<MatchRule>().as_ref(), Err(error), "{}", rule);
+    }
+}
+
+#[test]
+fn matches_header_fields() {
+    let message = signal("/com/example", "Changed", vec![]);
+    assert!(MatchRule::new().matches(&message));
+    assert!(rule("type=signal,sender=:1.4,member=Changed,path=/com/example").matches(&message));
+    assert!(rule("interface=com.example.Match").matches(&message));
+    assert!(!rule("type=method_call").matches(&message));
+    let mut invalid = MatchRule::new();
+    invalid.message_type = Some(MessageType::Invalid);
+    assert!(invalid.matches(&message));
+    assert!(!rule("sender=:1.5").matches(&message));
+    assert!(!rule("member=Other").matches(&message));
+    // Unset header fields never match a value
+    assert!(!rule("destination=:1.9").matches(&message));
+}
+
+#[test]
+fn matches_path_namespaces() {
+    let namespace = rule("path_namespace=/com/example");
+    for (path, expected) in [
+        ("/com/example", true),
+        ("/com/example/Child", true),
+        ("/com/examples", false),
+        ("/com", false),
+    ]
+    .iter()
+    {
+        let message = signal(path, "Changed", vec![]);
+        assert_eq!(namespace.matches(&message), *expected, "{}", path);
+        assert!(rule("path_namespace=/").matches(&message));
+    }
+}
+
+#[test]
+fn matches_string_arguments() {
+    let message = signal(
+        "/com/example",
+        "Changed",
+        vec![
+            string("com.example.Name"),
+            DbusTypeContainer::Uint32(1.into()),
+        ],
+    );
+    assert!(rule("arg0=com.example.Name").matches(&message));
+    assert!(!rule("arg0=com.example").matches(&message));
+    // Only strings are compared
+    assert!(!rule("arg1='1'").matches(&message));
+    assert!(!rule("arg2=x").matches(&message));
+
+    for (namespace, expected) in [
+        ("com.example.Name", true),
+        ("com.example", true),
+        ("com", true),
+        ("com.exam", false),
+        ("com.example.Name.Child", false),
+    ]
+    .iter()
+    {
+        let rule = rule(&format!("arg0namespace={}", namespace));
+        assert_eq!(rule.matches(&message), *expected, "{}", namespace);
+    }
+}
+
+#[test]
+fn matches_path_arguments() {
+    let cases = [
+        ("/aa/bb/", "/aa/bb/", true),
+        ("/aa/bb/", "/aa/bb/cc", true),
+        ("/aa/bb/", "/aa/", true),
+        ("/aa/bb/", "/", true),
+        ("/aa/bb/", "/aa/b", false),
+        ("/aa/bb", "/aa/bb/cc", false),
+        ("/aa/bb", "/aa/bb", true),
+        ("/aa/bb/", "/aa", false),
+    ];
+
+    for (rule_value, arg, expected) in cases.iter() {
+        let rule = rule(&format!("arg0path='{}'", rule_value));
+        let as_string = signal("/com/example", "Changed", vec![string(arg)]);
+        assert_eq!(
+            rule.matches(&as_string),
+            *expected,
+            "{} {}",
+            rule_value,
+            arg
+        );
+
+        // Object paths cannot end in a slash, the root aside
+        if *arg == "/" || !arg.ends_with('/') {
+            let as_path = signal(
+                "/com/example",
+                "Changed",
+                vec![DbusTypeContainer::ObjectPath(path(arg))],
+            );
+            assert_eq!(rule.matches(&as_path), *expected, "{} {}", rule_value, arg);
+        }
+    }
+
+    // Other argument types never match
+    let message = signal(
+        "/com/example",
+        "Changed",
+        vec![DbusTypeContainer::Byte(1.into())],
+    );
+    assert!(!rule("arg0path=/").matches(&message));
+}
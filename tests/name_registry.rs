@@ -0,0 +1,229 @@
+use conducto_nom::*;
+
+const NAME: &str = "com.example.Service";
+
+/// Signals as `(member, destination, string arguments)`, for concise comparisons
+fn summary(signals: &[Message]) -> Vec<(String, Option<String>, Vec<String>)> {
+    signals
+        .iter()
+        .map(|signal| {
+            let args = signal
+                .message
+                .iter()
+                .map(|arg| match arg {
+                    DbusTypeContainer::String(s) => s.to_string(),
+                    other => panic!("unexpected argument {:?}", other),
+                })
+                .collect();
+            (
+                signal.header.member().unwrap().to_string(),
+                signal.header.destination().map(String::from),
+                args,
+            )
+        })
+        .collect()
+}
+
+fn signal(
+    member: &str,
+    destination: Option<&str>,
+    args: &[&str],
+) -> (String, Option<String>, Vec<String>) {
+    (
+        member.to_string(),
+        destination.map(String::from),
+        args.iter().map(|arg| arg.to_string()).collect(),
+    )
+}
+
+fn request(
+    registry: &mut NameRegistry,
+    client: &str,
+    flags: RequestNameFlags,
+) -> (RequestNameReply, Vec<Message>) {
+    registry.request_name(NAME, client, flags).unwrap()
+}
+
+#[test]
+fn first_request_acquires() {
+    let mut registry = NameRegistry::new();
+    let (reply, signals) = request(&mut registry, ":1.1", RequestNameFlags::empty());
+    assert_eq!(reply, RequestNameReply::PrimaryOwner);
+    assert_eq!(
+        summary(&signals),
+        vec![
+            signal("NameOwnerChanged", None, &[NAME, "", ":1.1"]),
+            signal("NameAcquired", Some(":1.1"), &[NAME]),
+        ]
+    );
+    assert_eq!(registry.owner(NAME), Some(":1.1"));
+
+    let (reply, signals) = request(&mut registry, ":1.1", RequestNameFlags::empty());
+    assert_eq!(reply, RequestNameReply::AlreadyOwner);
+    assert!(signals.is_empty());
+}
+
+#[test]
+fn requests_queue_by_default() {
+    let mut registry = NameRegistry::new();
+    request(&mut registry, ":1.1", RequestNameFlags::empty());
+    let (reply, signals) = request(&mut registry, ":1.2", RequestNameFlags::empty());
+    assert_eq!(reply, RequestNameReply::InQueue);
+    assert!(signals.is_empty());
+    let (reply, _) = request(&mut registry, ":1.3", RequestNameFlags::DO_NOT_QUEUE);
+    assert_eq!(reply, RequestNameReply::Exists);
+    assert_eq!(registry.queued_owners(NAME), vec![":1.1", ":1.2"]);
+
+    // The owner leaving hands the name to the next in line
+    let (reply, signals) = registry.release_name(NAME, ":1.1").unwrap();
+    assert_eq!(reply, ReleaseNameReply::Released);
+    assert_eq!(
+        summary(&signals),
+        vec![
+            signal("NameLost", Some(":1.1"), &[NAME]),
+            signal("NameOwnerChanged", None, &[NAME, ":1.1", ":1.2"]),
+            signal("NameAcquired", Some(":1.2"), &[NAME]),
+        ]
+    );
+    assert_eq!(registry.owner(NAME), Some(":1.2"));
+}
+
+#[test]
+fn do_not_queue_leaves_the_queue() {
+    let mut registry = NameRegistry::new();
+    request(&mut registry, ":1.1", RequestNameFlags::empty());
+    request(&mut registry, ":1.2", RequestNameFlags::empty());
+    let (reply, _) = request(&mut registry, ":1.2", RequestNameFlags::DO_NOT_QUEUE);
+    assert_eq!(reply, RequestNameReply::Exists);
+    assert_eq!(registry.queued_owners(NAME), vec![":1.1"]);
+}
+
+#[test]
+fn replacement() {
+    let mut registry = NameRegistry::new();
+    request(&mut registry, ":1.1", RequestNameFlags::empty());
+
+    // Not allowed by the owner, so the request waits in the queue
+    let (reply, _) = request(&mut registry, ":1.2", RequestNameFlags::REPLACE_EXISTING);
+    assert_eq!(reply, RequestNameReply::InQueue);
+
+    // The owner changes its mind with a new request
+    request(&mut registry, ":1.1", RequestNameFlags::ALLOW_REPLACEMENT);
+    let (reply, signals) = request(&mut registry, ":1.2", RequestNameFlags::REPLACE_EXISTING);
+    assert_eq!(reply, RequestNameReply::PrimaryOwner);
+    assert_eq!(
+        summary(&signals),
+        vec![
+            signal("NameLost", Some(":1.1"), &[NAME]),
+            signal("NameOwnerChanged", None, &[NAME, ":1.1", ":1.2"]),
+            signal("NameAcquired", Some(":1.2"), &[NAME]),
+        ]
+    );
+
+    // The previous owner waits first in line
+    assert_eq!(registry.queued_owners(NAME), vec![":1.2", ":1.1"]);
+}
+
+#[test]
+fn replaced_owner_not_queued_with_do_not_queue() {
+    let mut registry = NameRegistry::new();
+    request(
+        &mut registry,
+        ":1.1",
+        RequestNameFlags::ALLOW_REPLACEMENT | RequestNameFlags::DO_NOT_QUEUE,
+    );
+    request(&mut registry, ":1.2", RequestNameFlags::empty());
+    let (reply, _) = request(
+        &mut registry,
+        ":1.3",
+        RequestNameFlags::REPLACE_EXISTING | RequestNameFlags::DO_NOT_QUEUE,
+    );
+    assert_eq!(reply, RequestNameReply::PrimaryOwner);
+    assert_eq!(registry.queued_owners(NAME), vec![":1.3", ":1.2"]);
+}
+
+#[test]
+fn release() {
+    let mut registry = NameRegistry::new();
+    let (reply, _) = registry.release_name(NAME, ":1.1").unwrap();
+    assert_eq!(reply, ReleaseNameReply::NonExistent);
+
+    request(&mut registry, ":1.1", RequestNameFlags::empty());
+    request(&mut registry, ":1.2", RequestNameFlags::empty());
+    let (reply, _) = registry.release_name(NAME, ":1.3").unwrap();
+    assert_eq!(reply, ReleaseNameReply::NotOwner);
+
+    // Leaving the queue goes unnoticed
+    let (reply, signals) = registry.release_name(NAME, ":1.2").unwrap();
+    assert_eq!(reply, ReleaseNameReply::Released);
+    assert!(signals.is_empty());
+
+    let (_, signals) = registry.release_name(NAME, ":1.1").unwrap();
+    assert_eq!(
+        summary(&signals),
+        vec![
+            signal("NameLost", Some(":1.1"), &[NAME]),
+            signal("NameOwnerChanged", None, &[NAME, ":1.1", ""]),
+        ]
+    );
+    assert_eq!(registry.owner(NAME), None);
+    assert_eq!(registry.names().count(), 0);
+}
+
+#[test]
+fn release_all() {
+    let mut registry = NameRegistry::new();
+    registry
+        .request_name("com.example.A", ":1.1", RequestNameFlags::empty())
+        .unwrap();
+    registry
+        .request_name("com.example.B", ":1.2", RequestNameFlags::empty())
+        .unwrap();
+    registry
+        .request_name("com.example.B", ":1.1", RequestNameFlags::empty())
+        .unwrap();
+
+    let signals = registry.release_all(":1.1");
+    assert_eq!(
+        summary(&signals),
+        vec![
+            signal("NameLost", Some(":1.1"), &["com.example.A"]),
+            signal("NameOwnerChanged", None, &["com.example.A", ":1.1", ""]),
+        ]
+    );
+    assert_eq!(registry.queued_owners("com.example.B"), vec![":1.2"]);
+    assert_eq!(
+        registry.names_owned_by(":1.2").collect::<Vec<_>>(),
+        vec!["com.example.B"]
+    );
+}
+
+#[test]
+fn invalid_names() {
+    let mut registry = NameRegistry::new();
+    for name in [
+        "",
+        "single",
+        ":1.1",
+        "com..example",
+        "com.1example",
+        "com.ex ample",
+    ]
+    .iter()
+    {
+        assert_eq!(
+            registry
+                .request_name(name, ":1.1", RequestNameFlags::empty())
+                .unwrap_err(),
+            NameError::InvalidName(name.to_string())
+        );
+    }
+
+    assert_eq!(
+        registry
+            .request_name("org.freedesktop.DBus", ":1.1", RequestNameFlags::empty())
+            .unwrap_err(),
+        NameError::Reserved("org.freedesktop.DBus".to_string())
+    );
+    assert!(is_valid_well_known_name("com.example-app._1"));
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use common::{path, signature, string};
+use conducto_nom::components::MessageType;
+use conducto_nom::types::containers::{DbusArray, DbusDict, DbusDictEntry, DbusVariant};
+use conducto_nom::*;
+
+const MANAGER: &str = "/com/example";
+const BATTERY: &str = "com.example.Battery";
+
+fn byte(value: u8) -> DbusTypeContainer {
+    DbusTypeContainer::Byte(value.into())
+}
+
+fn battery(level: u8) -> Interface {
+    Interface::new(BATTERY)
+        .with_property(
+            "Level",
+            Property::read_only(signature("y"), move || Ok(byte(level))),
+        )
+        .with_property("Secret", Property::write_only(signature("s"), |_| Ok(())))
+}
+
+fn server() -> ObjectServer {
+    let mut server = ObjectServer::new();
+    server.add_object_manager(&path(MANAGER));
+    server.add_interface(&path("/com/example/battery0"), battery(80));
+    server.add_interface(
+        &path("/com/example/battery0"),
+        Interface::new("com.example.Empty"),
+    );
+    server.add_interface(&path("/com/example/sub/battery1"), battery(30));
+    // Neither the manager itself nor objects outside it are managed
+    server.add_interface(&path(MANAGER), battery(1));
+    server.add_interface(&path("/com/examples"), battery(2));
+    server
+}
+
+fn get_managed_objects(server: &mut ObjectServer) -> ManagedObjects {
+    let mut call = ManagedObjects::call("com.example", MANAGER).unwrap();
+    call.header.fixed.msg_serial = 4;
+    let mut reply = server.dispatch(&call).remove(0);
+    assert_eq!(reply.header.message_type(), MessageType::MethodReturn);
+    assert_eq!(reply.body_signature(), signature("a{oa{sa{sv}}}"));
+
+    // Decode what a client would receive, empty dicts included
+    reply.header.fixed.msg_serial = 1;
+    let (_, reply) = Message::parse(&reply.marshal()).unwrap();
+    ManagedObjects::from_reply(&reply).unwrap()
+}
+
+#[test]
+fn get_managed_objects_lists_objects_below_the_manager() {
+    let mut server = server();
+    let objects = get_managed_objects(&mut server);
+    assert_eq!(
+        objects.paths().collect::<Vec<_>>(),
+        vec!["/com/example/battery0", "/com/example/sub/battery1"]
+    );
+
+    let battery0 = objects.object("/com/example/battery0").unwrap();
+    assert_eq!(
+        battery0.keys().collect::<Vec<_>>(),
+        vec!["com.example.Battery", "com.example.Empty"]
+    );
+    assert_eq!(battery0[BATTERY].keys().collect::<Vec<_>>(), vec!["Level"]);
+    assert_eq!(
+        objects.property("/com/example/sub/battery1", BATTERY, "Level"),
+        Some(&byte(30))
+    );
+}
+
+#[test]
+fn object_manager_errors() {
+    let mut server = server();
+    let mut call = Message::method_call(
+        Some("com.example"),
+        MANAGER,
+        Some(OBJECT_MANAGER_INTERFACE),
+        "GetManagedObjects",
+    )
+    .unwrap()
+    .with_body(vec![string("extra")]);
+    call.header.fixed.msg_serial = 4;
+    let reply = server.dispatch(&call).remove(0);
+    assert_eq!(reply.header.error_name(), Some(error_name::INVALID_ARGS));
+
+    // Objects other than managers do not implement the interface
+    let mut call = ManagedObjects::call("com.example", "/com/example/battery0").unwrap();
+    call.header.fixed.msg_serial = 5;
+    let reply = server.dispatch(&call).remove(0);
+    assert_eq!(
+        reply.header.error_name(),
+        Some(error_name::UNKNOWN_INTERFACE)
+    );
+}
+
+#[test]
+fn closest_manager_is_responsible() {
+    let mut server = server();
+    server.add_object_manager(&path("/com/example/sub"));
+    assert_eq!(
+        server.object_managers().collect::<Vec<_>>(),
+        vec!["/com/example", "/com/example/sub"]
+    );
+    assert_eq!(
+        server.object_manager_of("/com/example/sub/battery1"),
+        Some("/com/example/sub")
+    );
+    assert_eq!(server.object_manager_of("/com/example/sub"), Some(MANAGER));
+    assert_eq!(server.object_manager_of("/com/examples"), None);
+
+    assert!(server.remove_object_manager("/com/example/sub"));
+    assert_eq!(
+        server.object_manager_of("/com/example/sub/battery1"),
+        Some(MANAGER)
+    );
+}
+
+#[test]
+fn mirror_follows_signals() {
+    let mut server = server();
+    let mut objects = get_managed_objects(&mut server);
+
+    server.add_interface(&path("/com/example/battery2"), battery(55));
+    let added = server
+        .interfaces_added("/com/example/battery2", &[BATTERY])
+        .unwrap();
+    assert_eq!(added.header.path(), Some(MANAGER));
+    assert_eq!(added.header.member(), Some("InterfacesAdded"));
+    assert!(objects.update(&added).unwrap());
+    assert_eq!(
+        objects.property("/com/example/battery2", BATTERY, "Level"),
+        Some(&byte(55))
+    );
+
+    let removed = server.remove_object("/com/example/battery0");
+    let names: Vec<_> = removed.iter().map(Interface::name).collect();
+    let removed = server
+        .interfaces_removed("/com/example/battery0", &names)
+        .unwrap();
+    assert_eq!(removed.header.member(), Some("InterfacesRemoved"));
+    assert!(objects.update(&removed).unwrap());
+    assert!(objects.object("/com/example/battery0").is_none());
+
+    // The mirror now matches what the server reports
+    assert_eq!(objects, get_managed_objects(&mut server));
+
+    // Objects outside any manager are not announced
+    server.add_interface(&path("/org/example"), battery(3));
+    assert!(server
+        .interfaces_added("/org/example", &[BATTERY])
+        .is_none());
+}
+
+#[test]
+fn mirror_follows_property_changes() {
+    let mut server = server();
+    let mut objects = get_managed_objects(&mut server);
+    let level = DbusDictEntry::new(
+        string("Level"),
+        DbusTypeContainer::Variant(Box::new(DbusVariant::new(byte(79)))),
+    );
+    let changed = DbusDict::new(signature("s"), signature("v"), vec![level]).unwrap();
+    let invalidated = DbusArray::new(signature("s"), vec![]).unwrap();
+    let mut changed = Message::signal(
+        "/com/example/battery0",
+        PROPERTIES_INTERFACE,
+        "PropertiesChanged",
+    )
+    .unwrap()
+    .with_body(vec![
+        string(BATTERY),
+        DbusTypeContainer::Dict(changed),
+        DbusTypeContainer::Array(invalidated),
+    ]);
+    changed.header.fields.sender = Some(":1.7".into());
+    assert!(objects.update(&changed).unwrap());
+    assert_eq!(
+        objects.property("/com/example/battery0", BATTERY, "Level"),
+        Some(&byte(79))
+    );
+
+    // The rule for the mirror selects the signal
+    assert!(ManagedObjects::match_rule(":1.7", MANAGER).matches(&changed));
+
+    // Signals the mirror does not follow change nothing
+    let other = Message::signal("/com/example/battery0", BATTERY, "Low").unwrap();
+    assert!(!objects.update(&other).unwrap());
+}
+
+#[test]
+fn malformed_signals() {
+    let mut objects = ManagedObjects::new();
+    let signal = Message::signal(MANAGER, OBJECT_MANAGER_INTERFACE, "InterfacesAdded")
+        .unwrap()
+        .with_body(vec![string("/com/example/battery0")]);
+    assert!(objects.update(&signal).is_err());
+    assert!(objects.is_empty());
+}
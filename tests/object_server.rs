@@ -0,0 +1,197 @@
+mod common;
+
+use common::{path, signature, string};
+use conducto_nom::components::MessageType;
+use conducto_nom::*;
+
+fn calculator() -> ObjectServer {
+    let mut total = 0;
+    let interface = Interface::new("com.example.Calculator")
+        .with_method("Add", signature("i"), signature("i"), move |call| {
+            match call.message[0] {
+                DbusTypeContainer::Int32(n) => total += i32::from(n),
+                _ => unreachable!(),
+            }
+            Ok(vec![DbusTypeContainer::Int32(total.into())])
+        })
+        .with_method("Fail", signature(""), signature(""), |_| {
+            Err(MethodError::new("com.example.Error.Nope", "Nope"))
+        })
+        .with_method("Lie", signature(""), signature("s"), |_| Ok(vec![]));
+    let echo = Interface::new("com.example.Echo").with_method(
+        "Echo",
+        signature("s"),
+        signature("s"),
+        |call| Ok(call.message.clone()),
+    );
+
+    let mut server = ObjectServer::new();
+    let path = path("/com/example/Calculator");
+    server.add_interface(&path, interface);
+    server.add_interface(&path, echo);
+    server
+}
+
+fn call(
+    path: &str,
+    interface: Option<&str>,
+    member: &str,
+    body: Vec<DbusTypeContainer>,
+) -> Message {
+    let mut call = Message::method_call(Some("com.example"), path, interface, member)
+        .unwrap()
+        .with_body(body);
+    call.header.fixed.msg_serial = 7;
+    call.header.fields.sender = Some(":1.42".into());
+    call
+}
+
+fn error_name(reply: &Message) -> &str {
+    assert_eq!(reply.header.message_type(), MessageType::Error);
+    reply.header.error_name().unwrap()
+}
+
+#[test]
+fn dispatches_to_the_handler() {
+    let mut server = calculator();
+    for expected in [2, 4].iter() {
+        let call = call(
+            "/com/example/Calculator",
+            Some("com.example.Calculator"),
+            "Add",
+            vec![DbusTypeContainer::Int32(2.into())],
+        );
+        let reply = server.dispatch(&call).remove(0);
+        assert_eq!(reply.header.message_type(), MessageType::MethodReturn);
+        assert_eq!(reply.header.reply_serial(), Some(7));
+        assert_eq!(reply.header.destination(), Some(":1.42"));
+        assert_eq!(
+            reply.message,
+            vec![DbusTypeContainer::Int32((*expected).into())]
+        );
+    }
+
+    // Without an interface, any interface with the method answers
+    let call = call("/com/example/Calculator", None, "Echo", vec![string("hi")]);
+    assert_eq!(server.dispatch(&call).remove(0).message, vec![string("hi")]);
+}
+
+#[test]
+fn standard_errors() {
+    let mut server = calculator();
+    let cases = [
+        (
+            call("/com/example/Missing", None, "Add", vec![]),
+            error_name::UNKNOWN_OBJECT,
+        ),
+        (
+            call(
+                "/com/example/Calculator",
+                Some("com.example.Missing"),
+                "Add",
+                vec![],
+            ),
+            error_name::UNKNOWN_INTERFACE,
+        ),
+        (
+            call(
+                "/com/example/Calculator",
+                Some("com.example.Calculator"),
+                "Subtract",
+                vec![],
+            ),
+            error_name::UNKNOWN_METHOD,
+        ),
+        (
+            call(
+                "/com/example/Calculator",
+                Some("com.example.Calculator"),
+                "Add",
+                vec![string("2")],
+            ),
+            error_name::INVALID_ARGS,
+        ),
+        (
+            call("/com/example/Calculator", None, "Lie", vec![]),
+            error_name::FAILED,
+        ),
+        (
+            call("/com/example/Calculator", None, "Fail", vec![]),
+            "com.example.Error.Nope",
+        ),
+    ];
+
+    for (call, expected) in cases.iter() {
+        let reply = server.dispatch(call).remove(0);
+        assert_eq!(error_name(&reply), *expected);
+    }
+}
+
+#[test]
+fn no_reply_expected() {
+    let mut server = calculator();
+    let mut call = call("/com/example/Calculator", None, "Fail", vec![]);
+    call.header.fixed.flags |= components::MessageFlags::NO_REPLY_EXPECTED;
+    assert!(server.dispatch(&call).is_empty());
+
+    let signal = Message::signal("/com/example/Calculator", "com.example.Echo", "Echo").unwrap();
+    assert!(server.dispatch(&signal).is_empty());
+}
+
+#[test]
+fn registration() {
+    let mut server = calculator();
+    assert_eq!(
+        server.paths().collect::<Vec<_>>(),
+        vec!["/com/example/Calculator"]
+    );
+    let names: Vec<_> = server
+        .interfaces("/com/example/Calculator")
+        .map(Interface::name)
+        .collect();
+    assert_eq!(names, vec!["com.example.Calculator", "com.example.Echo"]);
+
+    let removed = server.remove_interface("/com/example/Calculator", "com.example.Echo");
+    assert_eq!(
+        removed.unwrap().method("Echo").unwrap().in_signature(),
+        &signature("s")
+    );
+    assert!(server.has_object("/com/example/Calculator"));
+    server.remove_interface("/com/example/Calculator", "com.example.Calculator");
+    assert!(!server.has_object("/com/example/Calculator"));
+}
+
+#[test]
+fn serves_over_a_bus() {
+    let bus = Bus::new().unwrap();
+    let mut service = bus.connect().unwrap();
+    let mut client = bus.connect().unwrap();
+    let service_name = service.unique_name().unwrap().to_string();
+
+    let handle = std::thread::spawn(move || {
+        let mut server = calculator();
+        loop {
+            let message = service.receive().unwrap();
+            let mut replies = server.dispatch(&message);
+            for reply in replies.iter_mut() {
+                service.send(reply).unwrap();
+            }
+
+            if !replies.is_empty() {
+                return;
+            }
+        }
+    });
+
+    let mut call = Message::method_call(
+        Some(&service_name),
+        "/com/example/Calculator",
+        Some("com.example.Calculator"),
+        "Add",
+    )
+    .unwrap()
+    .with_body(vec![DbusTypeContainer::Int32(5.into())]);
+    let reply = client.call(&mut call).unwrap();
+    assert_eq!(reply.message, vec![DbusTypeContainer::Int32(5.into())]);
+    handle.join().unwrap();
+}
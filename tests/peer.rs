@@ -0,0 +1,84 @@
+mod common;
+
+use common::path;
+use conducto_nom::components::MessageType;
+use conducto_nom::*;
+use std::fs;
+
+const MACHINE_ID: &str = "0123456789abcdef0123456789abcdef";
+
+fn call(path: &str, interface: Option<&str>, member: &str) -> Message {
+    let mut call = Message::method_call(Some("com.example"), path, interface, member).unwrap();
+    call.header.fixed.msg_serial = 9;
+    call
+}
+
+#[test]
+fn ping_on_any_path() {
+    let mut server = ObjectServer::new();
+    for call in [
+        call("/", Some(PEER_INTERFACE), "Ping"),
+        call("/com/example/Missing", None, "Ping"),
+    ]
+    .iter()
+    {
+        let reply = server.dispatch(call).remove(0);
+        assert_eq!(reply.header.message_type(), MessageType::MethodReturn);
+        assert!(reply.message.is_empty());
+    }
+
+    let mut ping = call("/", Some(PEER_INTERFACE), "Ping")
+        .with_body(vec![DbusTypeContainer::String("pong".into())]);
+    ping.header.fixed.msg_serial = 10;
+    let reply = server.dispatch(&ping).remove(0);
+    assert_eq!(reply.header.error_name(), Some(error_name::INVALID_ARGS));
+}
+
+#[test]
+fn machine_id_from_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("machine-id");
+    fs::write(&path, format!("{}\n", MACHINE_ID.to_uppercase())).unwrap();
+
+    let mut server = ObjectServer::new().with_machine_id_path(&path);
+    let reply = server
+        .dispatch(&call("/", Some(PEER_INTERFACE), "GetMachineId"))
+        .remove(0);
+    assert_eq!(
+        reply.message,
+        vec![DbusTypeContainer::String(MACHINE_ID.into())]
+    );
+
+    fs::write(&path, "not a machine id\n").unwrap();
+    let reply = server.dispatch(&call("/", None, "GetMachineId")).remove(0);
+    assert_eq!(reply.header.error_name(), Some(error_name::FAILED));
+
+    let mut server = ObjectServer::new().with_machine_id_path(dir.path().join("missing"));
+    let reply = server
+        .dispatch(&call("/", Some(PEER_INTERFACE), "GetMachineId"))
+        .remove(0);
+    assert_eq!(reply.header.error_name(), Some(error_name::FAILED));
+}
+
+#[test]
+fn objects_may_implement_peer_themselves() {
+    let mut server = ObjectServer::new();
+    let path = path("/com/example");
+    server.add_interface(
+        &path,
+        Interface::new(PEER_INTERFACE).with_method(
+            "Ping",
+            Signature::default(),
+            "s".parse().unwrap(),
+            |_| Ok(vec![DbusTypeContainer::String("custom".into())]),
+        ),
+    );
+
+    let reply = server
+        .dispatch(&call("/com/example", Some(PEER_INTERFACE), "Ping"))
+        .remove(0);
+    assert_eq!(
+        reply.message,
+        vec![DbusTypeContainer::String("custom".into())]
+    );
+}
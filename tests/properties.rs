@@ -0,0 +1,215 @@
+mod common;
+
+use common::{path, signature, string};
+use conducto_nom::components::MessageType;
+use conducto_nom::types::containers::DbusVariant;
+use conducto_nom::*;
+use std::sync::{Arc, Mutex};
+
+const PATH: &str = "/com/example/Thermostat";
+const INTERFACE: &str = "com.example.Thermostat";
+
+fn variant(value: DbusTypeContainer) -> DbusTypeContainer {
+    DbusTypeContainer::Variant(Box::new(DbusVariant::new(value)))
+}
+
+fn int(value: i32) -> DbusTypeContainer {
+    DbusTypeContainer::Int32(value.into())
+}
+
+/// A server with a thermostat, along with the target temperature it shares with the service
+fn thermostat() -> (ObjectServer, Arc<Mutex<i32>>) {
+    let target = Arc::new(Mutex::new(20));
+    let (get, set) = (target.clone(), target.clone());
+    let interface = Interface::new(INTERFACE)
+        .with_property(
+            "Target",
+            Property::read_write(
+                signature("i"),
+                move || Ok(int(*get.lock().unwrap())),
+                move |value| match value {
+                    DbusTypeContainer::Int32(value) if i32::from(value) < 40 => {
+                        *set.lock().unwrap() = value.into();
+                        Ok(())
+                    }
+                    _ => Err(MethodError::invalid_args("Too hot")),
+                },
+            ),
+        )
+        .with_property(
+            "Model",
+            Property::read_only(signature("s"), || Ok(string("T-1000")))
+                .with_emits_changed(EmitsChanged::Const),
+        )
+        .with_property(
+            "Reading",
+            Property::read_only(signature("i"), || Ok(int(19)))
+                .with_emits_changed(EmitsChanged::Invalidates),
+        )
+        .with_property("Pin", Property::write_only(signature("s"), |_| Ok(())));
+
+    let mut server = ObjectServer::new();
+    server.add_interface(&path(PATH), interface);
+    (server, target)
+}
+
+fn call(member: &str, body: Vec<DbusTypeContainer>) -> Message {
+    let mut call = Message::method_call(
+        Some("com.example"),
+        PATH,
+        Some(PROPERTIES_INTERFACE),
+        member,
+    )
+    .unwrap()
+    .with_body(body);
+    call.header.fixed.msg_serial = 3;
+    call
+}
+
+fn error_name(messages: &[Message]) -> &str {
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].header.message_type(), MessageType::Error);
+    messages[0].header.error_name().unwrap()
+}
+
+/// `a{sv}` entries as pairs, for concise comparisons
+fn entries(dict: &DbusTypeContainer) -> Vec<(DbusTypeContainer, DbusTypeContainer)> {
+    match dict {
+        DbusTypeContainer::Dict(dict) => dict
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect(),
+        other => panic!("unexpected value {:?}", other),
+    }
+}
+
+#[test]
+fn get() {
+    let (mut server, target) = thermostat();
+    *target.lock().unwrap() = 22;
+    let messages = server.dispatch(&call("Get", vec![string(INTERFACE), string("Target")]));
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].message, vec![variant(int(22))]);
+
+    let cases = [
+        (
+            vec![string("com.example.Missing"), string("Target")],
+            error_name::UNKNOWN_INTERFACE,
+        ),
+        (
+            vec![string(INTERFACE), string("Missing")],
+            error_name::UNKNOWN_PROPERTY,
+        ),
+        (
+            vec![string(INTERFACE), string("Pin")],
+            error_name::ACCESS_DENIED,
+        ),
+        (vec![string(INTERFACE)], error_name::INVALID_ARGS),
+    ];
+    for (body, expected) in cases.iter() {
+        let messages = server.dispatch(&call("Get", body.clone()));
+        assert_eq!(error_name(&messages), *expected);
+    }
+}
+
+#[test]
+fn get_all() {
+    let (mut server, _) = thermostat();
+    let messages = server.dispatch(&call("GetAll", vec![string(INTERFACE)]));
+    assert_eq!(
+        entries(&messages[0].message[0]),
+        vec![
+            (string("Model"), variant(string("T-1000"))),
+            (string("Reading"), variant(int(19))),
+            (string("Target"), variant(int(20))),
+        ]
+    );
+}
+
+#[test]
+fn set_emits_properties_changed() {
+    let (mut server, target) = thermostat();
+    let messages = server.dispatch(&call(
+        "Set",
+        vec![string(INTERFACE), string("Target"), variant(int(25))],
+    ));
+    assert_eq!(*target.lock().unwrap(), 25);
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].header.message_type(), MessageType::MethodReturn);
+
+    let signal = &messages[1];
+    assert_eq!(signal.header.member(), Some("PropertiesChanged"));
+    assert_eq!(signal.header.path(), Some(PATH));
+    assert_eq!(signal.message[0], string(INTERFACE));
+    assert_eq!(
+        entries(&signal.message[1]),
+        vec![(string("Target"), variant(int(25)))]
+    );
+    assert_eq!(signal.body_signature(), signature("sa{sv}as"));
+}
+
+#[test]
+fn set_errors() {
+    let (mut server, target) = thermostat();
+    let cases = [
+        (variant(int(45)), "Target", error_name::INVALID_ARGS),
+        (variant(string("25")), "Target", error_name::INVALID_ARGS),
+        (
+            variant(string("T-800")),
+            "Model",
+            error_name::PROPERTY_READ_ONLY,
+        ),
+        (variant(int(1)), "Missing", error_name::UNKNOWN_PROPERTY),
+    ];
+    for (value, name, expected) in cases.iter() {
+        let messages = server.dispatch(&call(
+            "Set",
+            vec![string(INTERFACE), string(name), value.clone()],
+        ));
+        assert_eq!(error_name(&messages), *expected);
+    }
+    assert_eq!(*target.lock().unwrap(), 20);
+}
+
+#[test]
+fn changes_made_by_the_service() {
+    let (mut server, target) = thermostat();
+    *target.lock().unwrap() = 18;
+    let signal = server
+        .properties_changed(PATH, INTERFACE, &["Target", "Reading", "Model"])
+        .unwrap();
+    assert_eq!(
+        entries(&signal.message[1]),
+        vec![(string("Target"), variant(int(18)))]
+    );
+    match &signal.message[2] {
+        DbusTypeContainer::Array(invalidated) => {
+            assert_eq!(invalidated.clone().into_inner(), vec![string("Reading")])
+        }
+        other => panic!("unexpected value {:?}", other),
+    }
+
+    // Constant properties never change
+    assert!(server
+        .properties_changed(PATH, INTERFACE, &["Model"])
+        .is_none());
+}
+
+#[test]
+fn introspected_access() {
+    let (server, _) = thermostat();
+    let interface = server.interfaces(PATH).next().unwrap();
+    let access: Vec<_> = interface
+        .properties()
+        .map(|(name, property)| (name, property.access()))
+        .collect();
+    assert_eq!(
+        access,
+        vec![
+            ("Model", PropertyAccess::Read),
+            ("Pin", PropertyAccess::Write),
+            ("Reading", PropertyAccess::Read),
+            ("Target", PropertyAccess::ReadWrite),
+        ]
+    );
+}
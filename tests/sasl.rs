@@ -0,0 +1,288 @@
+use conducto_nom::sasl::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+/// Runs the handshake to completion, passing every line through the codec
+fn handshake(client: &mut SaslClient, server: &mut SaslServer) -> Result<(), SaslError> {
+    let mut line = client.start()?;
+    for _ in 0..16 {
+        let (_, command) = Command::parse(&line.to_bytes()).unwrap();
+        let reply = match server.handle(command)? {
+            Some(reply) => reply,
+            None => return Ok(()),
+        };
+
+        let (_, reply) = Command::parse(&reply.to_bytes()).unwrap();
+        line = client.handle(reply)?;
+    }
+
+    panic!("the handshake did not finish");
+}
+
+/// A temporary directory only its owner can access, as keyrings require
+fn keyring_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700)).unwrap();
+    dir
+}
+
+fn server(mechanisms: Vec<Box<dyn ServerMechanism>>) -> SaslServer {
+    SaslServer::new("0123456789abcdef0123456789abcdef".to_string(), mechanisms)
+}
+
+#[test]
+fn command_round_trip() {
+    let lines: &[&[u8]] = &[
+        b"AUTH\r\n",
+        b"AUTH EXTERNAL 31303030\r\n",
+        b"CANCEL\r\n",
+        b"BEGIN\r\n",
+        b"DATA\r\n",
+        b"DATA 6f6b\r\n",
+        b"ERROR\r\n",
+        b"ERROR Unknown command\r\n",
+        b"NEGOTIATE_UNIX_FD\r\n",
+        b"REJECTED EXTERNAL DBUS_COOKIE_SHA1 ANONYMOUS\r\n",
+        b"OK 0123456789abcdef\r\n",
+        b"AGREE_UNIX_FD\r\n",
+    ];
+    for line in lines {
+        let (rest, command) = Command::parse(line).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(command.to_bytes(), *line);
+    }
+
+    assert!(Command::parse(b"DATA zz\r\n").is_err());
+    assert!(Command::parse(b"FOO\r\n").is_err());
+    assert!(Command::parse(b"BEGIN").unwrap_err().is_incomplete());
+}
+
+#[test]
+fn command_parsing() {
+    // Whatever follows the line is left for the caller
+    let (rest, command) = Command::parse(b"OK 1234\r\nBEGIN\r\n").unwrap();
+    assert_eq!(command, Command::Ok("1234".into()));
+    assert_eq!(rest, b"BEGIN\r\n");
+
+    let (_, command) = Command::parse(b"AUTH  EXTERNAL  \r\n").unwrap();
+    assert_eq!(
+        command,
+        Command::Auth {
+            mechanism: Some("EXTERNAL".into()),
+            initial_response: None,
+        }
+    );
+    assert_eq!(
+        Command::Data(b"ok".to_vec()).to_string(),
+        "DATA 6f6b".to_string()
+    );
+
+    let invalid: &[&[u8]] = &[
+        b"OK\r\n",
+        b"BEGIN now\r\n",
+        b"AUTH EXTERNAL 31 32\r\n",
+        b"ERROR \xc3\xa9\r\n",
+        b"begin\r\n",
+    ];
+    for line in invalid {
+        assert!(Command::parse(line).is_err());
+    }
+}
+
+#[test]
+fn command_line_limit() {
+    let mut line = b"ERROR ".to_vec();
+    line.resize(MAX_LINE_LEN, b'x');
+    line.extend_from_slice(b"\r\n");
+    assert!(Command::parse(&line).is_ok());
+
+    // Until the limit is reached the rest of the line may still come
+    let unterminated = &line[..MAX_LINE_LEN + 1];
+    assert!(Command::parse(unterminated).unwrap_err().is_incomplete());
+
+    let mut too_long = line[..MAX_LINE_LEN].to_vec();
+    too_long.extend_from_slice(b"x\r\n");
+    assert!(matches!(
+        Command::parse(&too_long),
+        Err(nom::Err::Failure(_))
+    ));
+    assert!(matches!(
+        Command::parse(&vec![b'x'; MAX_LINE_LEN + 2]),
+        Err(nom::Err::Failure(_))
+    ));
+}
+
+#[test]
+fn external() {
+    let mut client = SaslClient::new(vec![Box::new(ExternalClient::with_uid(1000))]);
+    let mut server = server(vec![Box::new(ExternalServer::new(Some(1000)))]);
+    handshake(&mut client, &mut server).unwrap();
+
+    assert!(client.is_authenticated());
+    assert!(server.is_authenticated());
+    assert_eq!(client.guid(), Some("0123456789abcdef0123456789abcdef"));
+    assert_eq!(server.mechanism().and_then(|m| m.uid()), Some(1000));
+}
+
+#[test]
+fn external_identity_requested_by_challenge() {
+    let mut client = ExternalClient::with_uid(1000);
+    let mut server = ExternalServer::new(Some(1000));
+    assert_eq!(server.start(None), ServerStep::Challenge(vec![]));
+    let response = client.challenge(&[]).unwrap();
+    assert_eq!(server.response(&response), ServerStep::Accept);
+}
+
+#[test]
+fn external_rejects_other_uid() {
+    let mut client = SaslClient::new(vec![Box::new(ExternalClient::with_uid(0))]);
+    let mut server = server(vec![Box::new(ExternalServer::new(Some(1000)))]);
+    match handshake(&mut client, &mut server) {
+        Err(SaslError::NoMechanismLeft) => {}
+        other => panic!("unexpected outcome {:?}", other),
+    }
+    assert!(!server.is_authenticated());
+}
+
+#[test]
+fn anonymous() {
+    let mut client = SaslClient::new(vec![Box::new(AnonymousClient::with_trace("tests"))]);
+    let mut server = server(vec![Box::new(AnonymousServer::new())]);
+    handshake(&mut client, &mut server).unwrap();
+
+    assert!(client.is_authenticated());
+    assert_eq!(server.mechanism().and_then(|m| m.uid()), None);
+}
+
+#[test]
+fn falls_back_to_next_mechanism() {
+    let mut client = SaslClient::new(vec![
+        Box::new(ExternalClient::with_uid(0)),
+        Box::new(AnonymousClient::new()),
+    ]);
+    let mut server = server(vec![
+        Box::new(ExternalServer::new(Some(1000))),
+        Box::new(AnonymousServer::new()),
+    ]);
+    handshake(&mut client, &mut server).unwrap();
+
+    assert!(client.is_authenticated());
+    assert_eq!(server.mechanism().map(|m| m.name()), Some("ANONYMOUS"));
+}
+
+#[test]
+fn unix_fd_negotiation() {
+    let mut client =
+        SaslClient::new(vec![Box::new(AnonymousClient::new())]).with_unix_fd_negotiation();
+    let mut server = server(vec![Box::new(AnonymousServer::new())]).with_unix_fd_support();
+    handshake(&mut client, &mut server).unwrap();
+    assert!(client.unix_fd_agreed());
+    assert!(server.unix_fd_agreed());
+
+    let mut client =
+        SaslClient::new(vec![Box::new(AnonymousClient::new())]).with_unix_fd_negotiation();
+    let mut server = self::server(vec![Box::new(AnonymousServer::new())]);
+    handshake(&mut client, &mut server).unwrap();
+    assert!(client.is_authenticated());
+    assert!(!client.unix_fd_agreed());
+}
+
+#[test]
+fn cookie_sha1() {
+    let dir = keyring_dir();
+    let keyring = Keyring::new(dir.path().join("keyrings"));
+    let mut client = SaslClient::new(vec![Box::new(CookieSha1Client::new(
+        keyring.clone(),
+        "1000",
+    ))]);
+    let mut server = server(vec![Box::new(CookieSha1Server::new(keyring.clone()))]);
+    handshake(&mut client, &mut server).unwrap();
+
+    assert!(client.is_authenticated());
+    assert!(server.is_authenticated());
+    assert_eq!(keyring.cookies(DEFAULT_CONTEXT).unwrap().len(), 1);
+}
+
+#[test]
+fn cookie_sha1_without_shared_keyring() {
+    let server_dir = keyring_dir();
+    let client_dir = keyring_dir();
+    let mut client = SaslClient::new(vec![Box::new(CookieSha1Client::new(
+        Keyring::new(client_dir.path()),
+        "1000",
+    ))]);
+    let mut server = server(vec![Box::new(CookieSha1Server::new(Keyring::new(
+        server_dir.path(),
+    )))]);
+
+    assert!(handshake(&mut client, &mut server).is_err());
+    assert!(!server.is_authenticated());
+}
+
+#[test]
+fn cookie_sha1_rejects_wrong_digest() {
+    let dir = keyring_dir();
+    let mut server = CookieSha1Server::new(Keyring::new(dir.path()));
+    match server.start(Some(b"1000")) {
+        ServerStep::Challenge(challenge) => assert!(!challenge.is_empty()),
+        step => panic!("unexpected step {:?}", step),
+    }
+    assert_eq!(server.response(b"abcd 0000"), ServerStep::Reject);
+}
+
+#[test]
+fn keyring_reuses_fresh_cookies() {
+    let dir = keyring_dir();
+    let keyring = Keyring::new(dir.path());
+    let cookie = keyring.fresh_cookie(DEFAULT_CONTEXT).unwrap();
+    assert_eq!(keyring.fresh_cookie(DEFAULT_CONTEXT).unwrap(), cookie);
+    assert_eq!(
+        keyring.cookie(DEFAULT_CONTEXT, cookie.id).unwrap(),
+        Some(cookie)
+    );
+
+    assert!(keyring.fresh_cookie("../escape").is_err());
+    assert!(!dir
+        .path()
+        .join(format!("{}.lock", DEFAULT_CONTEXT))
+        .exists());
+}
+
+#[test]
+fn keyring_runs_out_of_cookie_ids() {
+    let dir = keyring_dir();
+    let keyring = Keyring::new(dir.path());
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    // Too old to hand out again, but not yet expired
+    fs::write(
+        dir.path().join(DEFAULT_CONTEXT),
+        format!("{} {} 0123abcd\n", u32::MAX, now - 6 * 60),
+    )
+    .unwrap();
+
+    assert!(keyring.fresh_cookie(DEFAULT_CONTEXT).is_err());
+    assert_eq!(keyring.cookies(DEFAULT_CONTEXT).unwrap().len(), 1);
+}
+
+#[test]
+fn keyring_refuses_shared_directories() {
+    let dir = keyring_dir();
+    let keyring = Keyring::new(dir.path());
+    keyring.fresh_cookie(DEFAULT_CONTEXT).unwrap();
+
+    for mode in [0o750, 0o705, 0o777].iter() {
+        fs::set_permissions(dir.path(), fs::Permissions::from_mode(*mode)).unwrap();
+        let err = keyring.fresh_cookie(DEFAULT_CONTEXT).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(keyring.cookies(DEFAULT_CONTEXT).is_err());
+    }
+
+    // A keyring that was never created has no cookies
+    assert!(Keyring::new(dir.path().join("missing"))
+        .cookies(DEFAULT_CONTEXT)
+        .unwrap()
+        .is_empty());
+}
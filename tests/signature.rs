@@ -0,0 +1,209 @@
+mod common;
+
+use common::{path, signature, string};
+use conducto_nom::types::basic::DbusObjectPath;
+use conducto_nom::types::containers::{
+    DbusArray, DbusDict, DbusDictEntry, DbusStruct, DbusVariant,
+};
+use conducto_nom::*;
+use std::convert::TryFrom;
+
+/// Builds a signature without checking it is well formed
+fn unchecked(s: &str) -> Signature {
+    Signature::new(
+        s.bytes()
+            .map(|b| SignatureType::try_from(b).unwrap())
+            .collect(),
+    )
+}
+
+fn uint32(value: u32) -> DbusTypeContainer {
+    DbusTypeContainer::Uint32(value.into())
+}
+
+#[test]
+fn basic_values() {
+    let cases = [
+        (DbusTypeContainer::Boolean(true.into()), "b"),
+        (DbusTypeContainer::Byte(7.into()), "y"),
+        (DbusTypeContainer::Int16((-7).into()), "n"),
+        (uint32(7), "u"),
+        (DbusTypeContainer::Int64((-7).into()), "x"),
+        (DbusTypeContainer::Double(0.5.into()), "d"),
+        (string("text"), "s"),
+        (DbusTypeContainer::ObjectPath(path("/com/example")), "o"),
+        (
+            DbusTypeContainer::Variant(Box::new(DbusVariant::new(uint32(7)))),
+            "v",
+        ),
+    ];
+
+    for (value, expected) in cases.iter() {
+        assert_eq!(value.signature(), signature(expected));
+    }
+}
+
+#[test]
+fn double_type_code() {
+    assert_eq!(
+        SignatureType::try_from(b'd').unwrap(),
+        SignatureType::Double
+    );
+    assert_eq!(SignatureType::Double as u8, b'd');
+    assert!(SignatureType::try_from(b'f').is_err());
+}
+
+#[test]
+fn container_values() {
+    let array = DbusArray::new(signature("s"), vec![string("a"), string("b")]).unwrap();
+    assert_eq!(DbusTypeContainer::Array(array).signature(), signature("as"));
+
+    // Empty containers keep the signature they were declared with
+    let empty = DbusArray::new(signature("(iu)"), vec![]).unwrap();
+    assert_eq!(
+        DbusTypeContainer::Array(empty).signature(),
+        signature("a(iu)")
+    );
+
+    let structure = DbusStruct::new(vec![
+        uint32(1),
+        DbusTypeContainer::Struct(DbusStruct::new(vec![string("a")]).unwrap()),
+    ])
+    .unwrap();
+    assert_eq!(
+        DbusTypeContainer::Struct(structure).signature(),
+        signature("(u(s))")
+    );
+
+    let dict = DbusDict::new(
+        signature("s"),
+        signature("v"),
+        vec![DbusDictEntry::new(
+            string("key"),
+            DbusTypeContainer::Variant(Box::new(DbusVariant::new(uint32(1)))),
+        )],
+    )
+    .unwrap();
+    assert_eq!(dict.entry_signature(), signature("{sv}"));
+    assert_eq!(
+        DbusTypeContainer::Dict(dict).signature(),
+        signature("a{sv}")
+    );
+}
+
+#[test]
+fn mismatched_elements() {
+    assert!(matches!(
+        DbusArray::new(signature("s"), vec![string("a"), uint32(1)]),
+        Err(DbusParseError::InvalidElementSignature)
+    ));
+    assert!(matches!(
+        DbusDict::new(
+            signature("s"),
+            signature("u"),
+            vec![DbusDictEntry::new(string("key"), string("value"))],
+        ),
+        Err(DbusParseError::InvalidElementSignature)
+    ));
+}
+
+#[test]
+fn invalid_container_signatures() {
+    for element in ["", "ss", "a", "{sv}x"].iter() {
+        assert!(
+            matches!(
+                DbusArray::new(unchecked(element), vec![]),
+                Err(DbusParseError::InvalidSignature)
+            ),
+            "{}",
+            element
+        );
+    }
+
+    for (key, value) in [
+        ("v", "s"),
+        ("as", "s"),
+        ("(s)", "s"),
+        ("", "s"),
+        ("ss", "s"),
+        ("s", "ss"),
+        ("s", ""),
+    ]
+    .iter()
+    {
+        assert!(
+            matches!(
+                DbusDict::new(unchecked(key), unchecked(value), vec![]),
+                Err(DbusParseError::InvalidSignature)
+            ),
+            "{} {}",
+            key,
+            value
+        );
+    }
+
+    assert!(matches!(
+        DbusStruct::new(vec![]),
+        Err(DbusParseError::InvalidSignature)
+    ));
+}
+
+#[test]
+fn message_body_signature() {
+    let message = Message::signal("/com/example", "com.example.Signals", "Changed")
+        .unwrap()
+        .with_body(vec![
+            string("name"),
+            DbusTypeContainer::Array(DbusArray::new(signature("u"), vec![uint32(1)]).unwrap()),
+        ]);
+    assert_eq!(message.body_signature(), signature("sau"));
+    assert_eq!(
+        message.header.fields.signature.as_ref(),
+        Some(&signature("sau"))
+    );
+
+    let empty = Message::signal("/com/example", "com.example.Signals", "Changed").unwrap();
+    assert_eq!(empty.body_signature(), Signature::default());
+}
+
+#[test]
+fn split_and_display() {
+    let types: Vec<_> = signature("ia{sv}(yv)as")
+        .split_complete_types()
+        .unwrap()
+        .iter()
+        .map(Signature::to_string)
+        .collect();
+    assert_eq!(types, vec!["i", "a{sv}", "(yv)", "as"]);
+
+    for invalid in ["a", "()", "a{vs}", "a{sss}", "(i", "ai)"].iter() {
+        assert!(invalid.parse::<Signature>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn object_paths() {
+    for valid in ["/", "/com", "/com/example/Object_1"].iter() {
+        assert!(DbusObjectPath::try_from(*valid).is_ok(), "{}", valid);
+    }
+
+    for invalid in [
+        "",
+        "com",
+        "/com/",
+        "//com",
+        "/com//example",
+        "/com/ex-ample",
+    ]
+    .iter()
+    {
+        assert!(
+            matches!(
+                DbusObjectPath::try_from(*invalid),
+                Err(DbusParseError::MalformedObjectPath)
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use common::{signal, signature, Encoder};
+use conducto_nom::components::MessageEndianness;
+use conducto_nom::*;
+
+fn ctx(buf: &[u8]) -> ParseContext {
+    ParseContext::new(MessageEndianness::LittleEndian, buf)
+}
+
+#[test]
+fn skips_whole_bodies() {
+    let buf = [
+        1, 0, 0, 0, b'a', 0, 0, 0, // "a" and padding
+        7, 0, 0, 0, // 7u32
+        1, b'u', 0, 0, 5, 0, 0, 0, // a variant holding 5u32
+    ];
+    let s = signature("suv");
+    let (rest, _) = s.skip(&buf, &ctx(&buf)).unwrap();
+    assert!(rest.is_empty());
+    let (rest, _) = s.validate(&buf, &ctx(&buf)).unwrap();
+    assert!(rest.is_empty());
+
+    // Stopping short of the end leaves the rest alone
+    let (rest, _) = signature("su").skip(&buf, &ctx(&buf)).unwrap();
+    assert_eq!(rest.len(), 8);
+    assert!(signature("suvu").skip(&buf, &ctx(&buf)).is_err());
+}
+
+#[test]
+fn validate_checks_contents() {
+    let cases: [(&str, &[u8]); 5] = [
+        ("b", &[2, 0, 0, 0]),
+        ("s", &[1, 0, 0, 0, 0xff, 0]),
+        ("s", &[1, 0, 0, 0, b'a', b'b']),
+        ("o", &[3, 0, 0, 0, b'a', b'/', b'b', 0]),
+        ("g", &[1, b'!', 0]),
+    ];
+
+    for (s, buf) in cases.iter() {
+        // Only the framing matters when skipping
+        assert!(signature(s).skip(buf, &ctx(buf)).is_ok(), "{}", s);
+        assert!(signature(s).validate(buf, &ctx(buf)).is_err(), "{}", s);
+    }
+
+    let booleans = [8, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0];
+    assert!(signature("ab").skip(&booleans, &ctx(&booleans)).is_ok());
+    assert!(signature("ab")
+        .validate(&booleans, &ctx(&booleans))
+        .is_err());
+}
+
+#[test]
+fn arg_offsets() {
+    let mut body = Encoder::new(MessageEndianness::LittleEndian);
+    body.u8(1).str("text").u64(0x0102_0304_0506_0708);
+    let buf = signal(MessageEndianness::LittleEndian, "yst", &body.buf);
+    let (_, offset) = Message::arg_offset(&buf, 2).unwrap();
+    assert_eq!(offset % 8, 0);
+    assert_eq!(
+        buf[offset..offset + 8],
+        0x0102_0304_0506_0708u64.to_le_bytes()
+    );
+
+    let (_, offset) = Message::arg_offset(&buf, 0).unwrap();
+    assert_eq!(buf[offset], 1);
+    assert!(Message::arg_offset(&buf, 3).is_err());
+}
+
+#[test]
+fn depth_limit() {
+    let limits = ParseLimits {
+        max_depth: 1,
+        ..ParseLimits::default()
+    };
+    let buf = [4, 0, 0, 0, 0, 0, 0, 0];
+    let nested = signature("aai");
+    assert!(nested.skip(&buf, &ctx(&buf)).is_ok());
+    assert!(nested.parse_buffer(&buf, &ctx(&buf)).is_ok());
+
+    let limited = ctx(&buf).with_limits(limits);
+    assert!(nested.skip(&buf, &limited).is_err());
+    assert!(nested.parse_buffer(&buf, &limited).is_err());
+    assert!(nested.parse_buffer_borrowed(&buf, &limited).is_err());
+    assert!(signature("aay")
+        .parse_buffer_borrowed(&buf, &limited)
+        .is_err());
+    let compiled = CompiledSignature::new(nested).unwrap();
+    assert!(compiled.parse_buffer(&buf, &limited).is_err());
+    assert!(signature("ai").parse_buffer(&buf, &limited).is_ok());
+}
+
+#[test]
+fn array_and_message_length_limits() {
+    let limits = ParseLimits {
+        max_array_len: 4,
+        ..ParseLimits::default()
+    };
+    let buf = [5, 0, 0, 0, 1, 2, 3, 4, 5];
+    let limited = ctx(&buf).with_limits(limits);
+    assert!(signature("ay").parse_buffer(&buf, &ctx(&buf)).is_ok());
+    assert!(signature("ay").parse_buffer(&buf, &limited).is_err());
+    assert!(signature("ay").skip(&buf, &limited).is_err());
+
+    let mut body = Encoder::new(MessageEndianness::LittleEndian);
+    body.signature("s").str("text");
+    let buf = signal(MessageEndianness::LittleEndian, "v", &body.buf);
+    let header =
+        |limits| Header::unmarshal(&buf, &ctx(&buf).with_limits(limits), &Signature::default());
+    assert!(header(ParseLimits::default()).is_ok());
+    let limits = ParseLimits {
+        max_message_len: buf.len() - 1,
+        ..ParseLimits::default()
+    };
+    assert!(header(limits).is_err());
+}
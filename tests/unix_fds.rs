@@ -0,0 +1,158 @@
+mod common;
+
+use common::socketpair;
+use conducto_nom::*;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, OwnedFd};
+use std::os::unix::net::UnixStream;
+
+fn signal(member: &str, fds: usize) -> Message {
+    let body = (0..fds as u32)
+        .map(|i| DbusTypeContainer::UnixFd(i.into()))
+        .collect();
+    let fds = (0..fds)
+        .map(|_| UnixStream::pair().unwrap().0.into())
+        .collect();
+    let mut message = Message::signal("/com/example", "com.example.Fds", member)
+        .unwrap()
+        .with_body(body)
+        .with_fds(fds);
+    message.header.fixed.msg_serial = 1;
+    message
+}
+
+/// Writes `data` in a single call, with `fds` attached as `SCM_RIGHTS`
+fn send_with_fds(socket: &UnixStream, data: &[u8], fds: &[&OwnedFd]) {
+    let payload_len = fds.len() * std::mem::size_of::<libc::c_int>();
+    // SAFETY: `CMSG_SPACE` only computes a size
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(payload_len as u32) } as usize];
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    // SAFETY: an all-zero `msghdr` is a valid empty header
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    // SAFETY: the control buffer has room for one header holding `fds`
+    let sent = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(payload_len as u32) as _;
+        let payload = libc::CMSG_DATA(cmsg) as *mut libc::c_int;
+        for (i, fd) in fds.iter().enumerate() {
+            payload.add(i).write_unaligned(fd.as_raw_fd());
+        }
+
+        libc::sendmsg(socket.as_raw_fd(), &msg, 0)
+    };
+    assert_eq!(sent, data.len() as isize);
+}
+
+/// Checks that `fd` is still the end of a socket pair, by echoing through it
+fn assert_connected(fd: &OwnedFd, other: &mut UnixStream) {
+    let mut stream = UnixStream::from(fd.try_clone().unwrap());
+    stream.write_all(b"ping").unwrap();
+    let mut buf = [0; 4];
+    other.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+}
+
+#[test]
+fn splits_descriptors_between_messages() {
+    let (mut connection, peer) = socketpair(true);
+    let first = signal("First", 1);
+    let second = signal("Second", 2);
+    let (a, mut a_other) = UnixStream::pair().unwrap();
+    let (b, mut b_other) = UnixStream::pair().unwrap();
+    let (c, _c_other) = UnixStream::pair().unwrap();
+    let (a, b, c): (OwnedFd, OwnedFd, OwnedFd) = (a.into(), b.into(), c.into());
+
+    // Both messages and all their descriptors arrive in one go
+    let mut data = first.marshal();
+    data.extend_from_slice(&second.marshal());
+    send_with_fds(&peer.stream, &data, &[&a, &b, &c]);
+
+    let received = connection.receive().unwrap();
+    assert_eq!(received.header.member(), Some("First"));
+    assert_eq!(received.fds.len(), 1);
+    assert_connected(received.fd(0.into()).unwrap(), &mut a_other);
+
+    let received = connection.receive().unwrap();
+    assert_eq!(received.header.member(), Some("Second"));
+    assert_eq!(received.fds.len(), 2);
+    assert_connected(received.fd(0.into()).unwrap(), &mut b_other);
+    assert!(received.fd(2.into()).is_err());
+}
+
+#[test]
+fn descriptors_sent_ahead_of_their_message() {
+    let (mut connection, mut peer) = socketpair(true);
+    let message = signal("Early", 1);
+    let data = message.marshal();
+    let (a, mut a_other) = UnixStream::pair().unwrap();
+    let a: OwnedFd = a.into();
+
+    // The descriptor travels with the first bytes, the rest follows separately
+    send_with_fds(&peer.stream, &data[..1], &[&a]);
+    peer.stream.write_all(&data[1..]).unwrap();
+
+    let received = connection.receive().unwrap();
+    assert_eq!(received.fds.len(), 1);
+    assert_connected(received.fd(0.into()).unwrap(), &mut a_other);
+}
+
+#[test]
+fn missing_descriptors() {
+    let (mut connection, peer) = socketpair(true);
+    let (a, _) = UnixStream::pair().unwrap();
+    let a: OwnedFd = a.into();
+
+    // The header announces two descriptors, only one comes along
+    send_with_fds(&peer.stream, &signal("Short", 2).marshal(), &[&a]);
+    assert!(matches!(
+        connection.receive(),
+        Err(ConnectionError::InvalidMessage)
+    ));
+}
+
+#[test]
+fn send_checks_indices() {
+    let (mut connection, _peer) = socketpair(true);
+    let mut message = signal("Indices", 1);
+    message.message.push(DbusTypeContainer::UnixFd(1.into()));
+    assert!(matches!(
+        connection.send(&mut message),
+        Err(ConnectionError::InvalidMessage)
+    ));
+}
+
+#[test]
+fn passes_descriptors_across_the_bus() {
+    let bus = Bus::new().unwrap();
+    let mut sender = bus.connect().unwrap();
+    let mut receiver = bus.connect().unwrap();
+    assert!(sender.unix_fd_agreed());
+
+    let (a, mut a_other) = UnixStream::pair().unwrap();
+    let mut message = Message::signal("/com/example", "com.example.Fds", "Pass")
+        .unwrap()
+        .with_body(vec![DbusTypeContainer::UnixFd(0.into())])
+        .with_fds(vec![a.into()]);
+    message.header.fields.destination = receiver.unique_name().map(Into::into);
+    sender.send(&mut message).unwrap();
+
+    let received = loop {
+        let received = receiver.receive().unwrap();
+        if received.header.member() == Some("Pass") {
+            break received;
+        }
+    };
+    assert_eq!(received.fds.len(), 1);
+    assert_eq!(received.header.fields.unix_fds.map(u32::from), Some(1));
+    assert_connected(received.fd(0.into()).unwrap(), &mut a_other);
+}